@@ -0,0 +1,239 @@
+use crate::commands::stone::bundle::{BundleParams, bundle_command};
+use crate::commands::stone::create::create_command;
+use crate::commands::stone::provision::provision_manifest;
+use crate::commands::stone::validate::validate_command;
+use crate::error::Error;
+use crate::log::{self, Level, Reporter};
+use crate::manifest::{Manifest, ManifestFormat, ManifestOptions};
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use crate::commands::stone::bundle::{Compression, CompressionArgs, ReleaseArgs};
+
+/// One manifest and the directories its inputs are found in.
+///
+/// Each operation matches the CLI subcommand of the same name. Messages go to
+/// the reporter when one is set, and to stdout otherwise.
+#[derive(Clone)]
+pub struct Stone {
+    manifest_path: PathBuf,
+    manifest_options: ManifestOptions,
+    input_dirs: Vec<PathBuf>,
+    verbose: bool,
+    reporter: Option<Reporter>,
+}
+
+impl Stone {
+    /// Stone for the manifest at `manifest_path`. Inputs are looked up next to
+    /// the manifest unless input directories are given.
+    pub fn new<P: Into<PathBuf>>(manifest_path: P) -> Self {
+        Self {
+            manifest_path: manifest_path.into(),
+            manifest_options: ManifestOptions::default(),
+            input_dirs: Vec::new(),
+            verbose: false,
+            reporter: None,
+        }
+    }
+
+    /// Directories searched for inputs, in priority order
+    pub fn with_input_dirs<I, P>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.input_dirs = dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Read the manifest as `format` instead of choosing by file extension
+    pub fn with_format(mut self, format: ManifestFormat) -> Self {
+        self.manifest_options.format = Some(format);
+        self
+    }
+
+    /// Value for `${name}` references in the manifest, like `--var name=value`
+    pub fn with_var<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.manifest_options.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// Report the details the CLI prints with `--verbose`
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Send progress messages and warnings to `reporter` instead of stdout
+    pub fn with_reporter<F>(mut self, reporter: F) -> Self
+    where
+        F: Fn(Level, &str) + Send + Sync + 'static,
+    {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    pub fn manifest_path(&self) -> &Path {
+        &self.manifest_path
+    }
+
+    /// Input directories; the manifest's directory when none were given
+    pub fn input_dirs(&self) -> Vec<PathBuf> {
+        if !self.input_dirs.is_empty() {
+            return self.input_dirs.clone();
+        }
+        let manifest_dir = self
+            .manifest_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        vec![manifest_dir.to_path_buf()]
+    }
+
+    /// Load the manifest with `extends` and `${NAME}` references resolved
+    pub fn manifest(&self) -> Result<Manifest, Error> {
+        self.run(|| Manifest::load(&self.manifest_path, &self.manifest_options))
+    }
+
+    /// Check that the manifest is valid and all of its inputs exist
+    pub fn validate(&self) -> Result<(), Error> {
+        self.run(|| {
+            validate_command(
+                &self.manifest_path,
+                &self.manifest_options,
+                &self.input_dirs(),
+            )
+        })
+    }
+
+    /// Copy the manifest's artifacts and `os_release` into `output_dir`
+    pub fn create<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &self,
+        os_release: P1,
+        output_dir: P2,
+    ) -> Result<(), Error> {
+        self.run(|| {
+            create_command(
+                &self.manifest_path,
+                &self.manifest_options,
+                os_release.as_ref(),
+                &self.input_dirs(),
+                &output_dir.as_ref().to_path_buf(),
+                self.verbose,
+            )
+        })
+    }
+
+    /// Build an OS bundle (.aos)
+    pub fn bundle(&self, options: &BundleOptions) -> Result<(), Error> {
+        self.run(|| {
+            bundle_command(BundleParams {
+                manifest_path: &self.manifest_path,
+                manifest_options: &self.manifest_options,
+                os_release_path: &options.os_release,
+                os_release_initrd_path: options.os_release_initrd.as_deref(),
+                input_dirs: &self.input_dirs(),
+                output_path: &options.output,
+                build_dir_override: options.build_dir.as_deref(),
+                signing_key_path: options.signing_key.as_deref(),
+                compression: &options.compression,
+                chunk_size: options.chunk_size,
+                release: &options.release,
+                verbose: self.verbose,
+            })
+        })
+    }
+
+    /// Build the manifest's artifacts in `_build` next to the manifest and run
+    /// its provision script
+    pub fn provision(&self) -> Result<(), Error> {
+        self.run(|| {
+            provision_manifest(
+                &self.manifest_path,
+                &self.manifest_options,
+                &self.input_dirs(),
+                self.verbose,
+            )
+        })
+    }
+
+    fn run<T>(&self, operation: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        match &self.reporter {
+            Some(reporter) => log::with_reporter(reporter.clone(), operation),
+            None => operation(),
+        }
+    }
+}
+
+/// Options for [`Stone::bundle`], matching the options of `stone bundle`
+#[derive(Debug, Clone)]
+pub struct BundleOptions {
+    /// OS release file to include
+    pub os_release: PathBuf,
+    /// Initramfs OS release file, for the initramfs build ID
+    pub os_release_initrd: Option<PathBuf>,
+    /// Path of the .aos bundle to write
+    pub output: PathBuf,
+    /// Directory for intermediate build artifacts. When unset, `_build` next to
+    /// the output is used; it is left in place after the build.
+    pub build_dir: Option<PathBuf>,
+    /// Ed25519 private key used to sign bundle.json
+    pub signing_key: Option<PathBuf>,
+    pub compression: CompressionArgs,
+    /// Average chunk size when a chunk index should be written for each artifact
+    pub chunk_size: Option<u32>,
+    /// Release metadata overriding the manifest's release section
+    pub release: ReleaseArgs,
+}
+
+impl BundleOptions {
+    /// Create new BundleOptions with required parameters
+    pub fn new<P1, P2>(os_release: P1, output: P2) -> Self
+    where
+        P1: Into<PathBuf>,
+        P2: Into<PathBuf>,
+    {
+        Self {
+            os_release: os_release.into(),
+            os_release_initrd: None,
+            output: output.into(),
+            build_dir: None,
+            signing_key: None,
+            compression: CompressionArgs::default(),
+            chunk_size: None,
+            release: ReleaseArgs::default(),
+        }
+    }
+
+    pub fn with_os_release_initrd<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.os_release_initrd = Some(path.into());
+        self
+    }
+
+    pub fn with_build_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.build_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_signing_key<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.signing_key = Some(path.into());
+        self
+    }
+
+    pub fn with_compression(mut self, compression: CompressionArgs) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Write a chunk index for each artifact, with chunks of `avg_size` bytes on average
+    pub fn with_chunk_index(mut self, avg_size: u32) -> Self {
+        self.chunk_size = Some(avg_size);
+        self
+    }
+
+    pub fn with_release(mut self, release: ReleaseArgs) -> Self {
+        self.release = release;
+        self
+    }
+}
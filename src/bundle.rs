@@ -0,0 +1,411 @@
+use crate::manifest::{DEFAULT_BLOCK_SIZE, Release, SizeUnit, SlotAction, SlotDetection};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// bundle.json format version written by this version of stone.
+///
+/// History:
+/// - 1: single-device `layout`, slot targets name only a partition
+/// - 2: `layout` keyed by storage device, slot targets name a device and a partition
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Oldest bundle.json format version this version of stone can read. Older
+/// versions are converted to the current model when read.
+pub const MIN_FORMAT_VERSION: u32 = 1;
+
+/// bundle.json: the description of a .aos bundle, stored at the root of the archive
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[schemars(title = "Avocado OS bundle (bundle.json)")]
+pub struct Bundle {
+    /// Version of the bundle.json format
+    pub format_version: u32,
+    /// Target platform (runtime.platform of the manifest)
+    pub platform: String,
+    /// Target architecture (runtime.architecture of the manifest)
+    pub architecture: String,
+    /// AVOCADO_OS_BUILD_ID of the OS in this bundle
+    pub os_build_id: String,
+    /// AVOCADO_OS_BUILD_ID of the initramfs, when built separately
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initramfs_build_id: Option<String>,
+    /// Release version, notes and the installs it is compatible with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<Release>,
+    /// How the device installs and activates the artifacts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<BundleUpdate>,
+    /// Partition layout of each storage device, keyed by device name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub layout: BTreeMap<String, DeviceLayout>,
+    /// Check that the booted OS is the one from this bundle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<OsReleaseCheck>,
+    /// Check that the booted initramfs is the one from this bundle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_initramfs: Option<OsReleaseCheck>,
+    /// Present on delta bundles, which carry patches against an installed base
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<BundleDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct BundleUpdate {
+    /// Update strategy (runtime.update_strategy of the manifest)
+    pub strategy: String,
+    /// How the device finds its active slot
+    pub slot_detection: SlotDetection,
+    /// Artifacts in the archive and where each one is installed
+    pub artifacts: Vec<BundleArtifact>,
+    /// Actions that switch the device to the newly written slot
+    pub activate: Vec<SlotAction>,
+    /// Actions that switch the device back when the new slot fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<Vec<SlotAction>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct BundleArtifact {
+    /// Artifact name (key in update.os_artifacts of the manifest)
+    pub name: String,
+    /// Path of the artifact inside the archive
+    pub file: String,
+    /// SHA256 hash of the archive entry
+    pub sha256: String,
+    /// Size of the archive entry in bytes
+    pub size: u64,
+    /// Partition the artifact is written to, keyed by slot
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub slot_targets: BTreeMap<String, SlotTarget>,
+    /// Content-defined chunk index of the artifact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<ChunkIndexRef>,
+    /// Present when the entry is a patch rather than the full image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<ArtifactDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct SlotTarget {
+    /// Storage device name (key in `layout`)
+    pub device: String,
+    /// Partition name on that device
+    pub partition: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct ChunkIndexRef {
+    /// Chunking algorithm
+    pub algorithm: String,
+    /// Average chunk size in bytes
+    pub avg_size: u32,
+    /// Number of chunks
+    pub count: u64,
+    /// Path of the chunk index inside the archive
+    pub index: String,
+    /// SHA256 hash of the chunk index
+    pub sha256: String,
+    /// Size of the chunk index in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct ArtifactDelta {
+    /// Patch format
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// SHA256 hash of the image the patch applies to
+    pub base_sha256: String,
+    /// Size of the image the patch applies to
+    pub base_size: u64,
+    /// SHA256 hash of the patched image
+    pub target_sha256: String,
+    /// Size of the patched image
+    pub target_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct DeviceLayout {
+    /// Device path on the target (e.g. /dev/mmcblk0)
+    pub devpath: String,
+    /// Block size of the device in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+    /// Partitions in manifest order
+    pub partitions: Vec<LayoutPartition>,
+}
+
+impl DeviceLayout {
+    /// Block size, defaulting to 512 bytes
+    pub fn block_size(&self) -> u64 {
+        self.block_size.map_or(DEFAULT_BLOCK_SIZE, u64::from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct LayoutPartition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Partition start
+    pub offset: u64,
+    /// Unit of `offset` (always "bytes")
+    pub offset_unit: SizeUnit,
+    pub size: u64,
+    pub size_unit: SizeUnit,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expand: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct OsReleaseCheck {
+    /// Check type (always "os-release")
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// os-release field to compare
+    pub field: String,
+    /// os-release file to read, when not /etc/os-release
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Expected field value
+    pub expected: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct BundleDelta {
+    /// OS build the patches apply to
+    pub base_os_build_id: String,
+}
+
+impl Bundle {
+    /// Parse bundle.json, rejecting format versions this stone cannot read
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_slice(bytes)
+            .map_err(|e| format!("Failed to parse bundle.json: {e}"))?;
+        let version = value["format_version"]
+            .as_u64()
+            .ok_or("bundle.json has no format_version")?;
+        if version > u64::from(FORMAT_VERSION) {
+            return Err(format!(
+                "bundle.json format version {version} is newer than this stone supports ({FORMAT_VERSION}). Upgrade stone."
+            ));
+        }
+        if version < u64::from(MIN_FORMAT_VERSION) {
+            return Err(format!(
+                "bundle.json format version {version} is no longer supported (oldest supported: {MIN_FORMAT_VERSION}). Rebuild the bundle."
+            ));
+        }
+        let mut value = value;
+        if version == 1 {
+            upgrade_v1(&mut value);
+        }
+        serde_json::from_value(value).map_err(|e| format!("Invalid bundle.json: {e}"))
+    }
+
+    /// Serialize as pretty-printed JSON. Object keys are always sorted, so the
+    /// output is stable even where the model holds hash maps.
+    pub fn to_json_pretty(&self) -> Result<String, String> {
+        serde_json::to_value(self)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .map_err(|e| format!("Failed to serialize bundle.json: {e}"))
+    }
+
+    /// Artifacts listed in the update section
+    pub fn artifacts(&self) -> &[BundleArtifact] {
+        self.update
+            .as_ref()
+            .map(|u| u.artifacts.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// JSON Schema describing bundle.json
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Bundle)).unwrap_or_default()
+    }
+}
+
+/// Convert a format 1 bundle.json to the format 2 model, keeping its
+/// format_version. Format 1 describes a single unnamed device as
+/// `{"device": devpath, "block_size", "partitions"}` and slot targets name only
+/// a partition; the device is named after the last component of its path.
+fn upgrade_v1(value: &mut serde_json::Value) {
+    let layout = value.get_mut("layout").map(serde_json::Value::take);
+    let devpath = layout
+        .as_ref()
+        .and_then(|layout| layout.get("device"))
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string);
+    let device = devpath
+        .as_deref()
+        .and_then(|devpath| devpath.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("device")
+        .to_string();
+
+    if let Some(mut layout) = layout.filter(serde_json::Value::is_object) {
+        if let Some(layout) = layout.as_object_mut()
+            && let Some(devpath) = layout.remove("device")
+        {
+            layout.insert("devpath".to_string(), devpath);
+        }
+        value["layout"] = serde_json::json!({ device.clone(): layout });
+    } else if let Some(bundle) = value.as_object_mut() {
+        bundle.remove("layout");
+    }
+
+    let artifacts = value
+        .pointer_mut("/update/artifacts")
+        .and_then(serde_json::Value::as_array_mut);
+    for artifact in artifacts.into_iter().flatten() {
+        let targets = artifact
+            .get_mut("slot_targets")
+            .and_then(serde_json::Value::as_object_mut);
+        for target in targets.into_iter().flat_map(|targets| targets.values_mut()) {
+            if let Some(target) = target.as_object_mut() {
+                target
+                    .entry("device")
+                    .or_insert_with(|| serde_json::Value::String(device.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_bundle() -> serde_json::Value {
+        serde_json::json!({
+            "format_version": FORMAT_VERSION,
+            "platform": "test-platform",
+            "architecture": "x86_64",
+            "os_build_id": "build-0001",
+            "release": {
+                "version": "1.1.0",
+                "min_version": "1.0.0",
+                "hardware_revisions": ["rev-a", "rev-b"]
+            },
+            "update": {
+                "strategy": "uboot-ab",
+                "slot_detection": { "type": "uboot-env", "var": "boot_slot" },
+                "artifacts": [{
+                    "name": "rootfs",
+                    "file": "images/rootfs.img",
+                    "sha256": "00",
+                    "size": 4,
+                    "slot_targets": {
+                        "a": { "device": "rootdisk", "partition": "rootfs_a" },
+                        "b": { "device": "rootdisk", "partition": "rootfs_b" }
+                    }
+                }],
+                "activate": [{ "type": "uboot-env", "set": { "boot_slot": "{slot}" } }]
+            },
+            "layout": {
+                "rootdisk": {
+                    "devpath": "/dev/mmcblk0",
+                    "block_size": 512,
+                    "partitions": [{
+                        "name": "rootfs_a",
+                        "offset": 1048576,
+                        "offset_unit": "bytes",
+                        "size": 2,
+                        "size_unit": "mebibytes"
+                    }]
+                }
+            },
+            "verify": {
+                "type": "os-release",
+                "field": "AVOCADO_OS_BUILD_ID",
+                "expected": "build-0001"
+            }
+        })
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = sample_bundle();
+        let bundle = Bundle::from_slice(&serde_json::to_vec(&value).unwrap()).unwrap();
+
+        assert_eq!(
+            bundle.artifacts()[0].slot_targets["b"].partition,
+            "rootfs_b"
+        );
+        assert_eq!(bundle.layout["rootdisk"].block_size(), 512);
+
+        let json = bundle.to_json_pretty().unwrap();
+        let reparsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(reparsed, value);
+    }
+
+    #[test]
+    fn test_format_version_checks() {
+        let mut value = sample_bundle();
+        value["format_version"] = serde_json::json!(FORMAT_VERSION + 1);
+        let err = Bundle::from_slice(&serde_json::to_vec(&value).unwrap()).unwrap_err();
+        assert!(err.contains("newer than this stone supports"));
+
+        value["format_version"] = serde_json::json!(0);
+        let err = Bundle::from_slice(&serde_json::to_vec(&value).unwrap()).unwrap_err();
+        assert!(err.contains("no longer supported"));
+
+        // Format 1, as written by older stone, still loads
+        let v1 = serde_json::json!({
+            "format_version": 1,
+            "platform": "test-platform",
+            "architecture": "x86_64",
+            "os_build_id": "build-0001",
+            "update": {
+                "strategy": "uboot-ab",
+                "slot_detection": { "type": "uboot-env", "var": "boot_slot" },
+                "artifacts": [{
+                    "name": "rootfs",
+                    "file": "images/rootfs.img",
+                    "sha256": "00",
+                    "size": 4,
+                    "slot_targets": {
+                        "a": { "partition": "rootfs_a" },
+                        "b": { "partition": "rootfs_b" }
+                    }
+                }],
+                "activate": [{ "type": "uboot-env", "set": { "boot_slot": "{slot}" } }]
+            },
+            "layout": {
+                "device": "/dev/mmcblk0",
+                "block_size": 4096,
+                "partitions": [{
+                    "name": "rootfs_a",
+                    "offset": 1048576,
+                    "offset_unit": "bytes",
+                    "size": 2,
+                    "size_unit": "mebibytes",
+                    "expand": "false"
+                }]
+            }
+        });
+        let bundle = Bundle::from_slice(&serde_json::to_vec(&v1).unwrap()).unwrap();
+        assert_eq!(bundle.format_version, 1);
+        let layout = &bundle.layout["mmcblk0"];
+        assert_eq!(layout.devpath, "/dev/mmcblk0");
+        assert_eq!(layout.block_size(), 4096);
+        assert_eq!(layout.partitions[0].offset, 1048576);
+        assert_eq!(
+            bundle.artifacts()[0].slot_targets["b"],
+            SlotTarget {
+                device: "mmcblk0".to_string(),
+                partition: "rootfs_b".to_string()
+            }
+        );
+
+        value.as_object_mut().unwrap().remove("format_version");
+        let err = Bundle::from_slice(&serde_json::to_vec(&value).unwrap()).unwrap_err();
+        assert!(err.contains("no format_version"));
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = Bundle::json_schema();
+        assert_eq!(schema["title"], "Avocado OS bundle (bundle.json)");
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&serde_json::json!("format_version")));
+        assert!(schema["properties"]["layout"].is_object());
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Content-defined chunking algorithm recorded in bundle.json
+pub const CHUNK_ALGORITHM: &str = "fastcdc-2020";
+
+/// Default average chunk size in bytes
+pub const DEFAULT_AVG_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Smallest and largest average chunk size accepted; min and max sizes are
+/// derived as a quarter and four times the average, which keeps them inside
+/// the bounds FastCDC accepts.
+pub const MIN_AVG_CHUNK_SIZE: u32 = 4 * 1024;
+pub const MAX_AVG_CHUNK_SIZE: u32 = 4 * 1024 * 1024;
+
+/// One content-defined chunk of an artifact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Byte offset of the chunk in the artifact
+    pub offset: u64,
+    /// Chunk length in bytes
+    pub length: u64,
+    /// SHA256 hash of the chunk contents
+    pub sha256: String,
+}
+
+/// Chunk index of a single artifact, stored as a sidecar file in the bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkIndex {
+    /// Chunking algorithm (`CHUNK_ALGORITHM`)
+    pub algorithm: String,
+    pub min_size: u32,
+    pub avg_size: u32,
+    pub max_size: u32,
+    /// Chunks in artifact order; they are contiguous and cover the whole artifact
+    pub chunks: Vec<Chunk>,
+}
+
+/// Split the file at `path` into content-defined chunks around `avg_size` bytes
+pub fn chunk_file(path: &Path, avg_size: u32) -> Result<ChunkIndex, String> {
+    if !(MIN_AVG_CHUNK_SIZE..=MAX_AVG_CHUNK_SIZE).contains(&avg_size) {
+        return Err(format!(
+            "Average chunk size {avg_size} is out of range ({MIN_AVG_CHUNK_SIZE}-{MAX_AVG_CHUNK_SIZE} bytes)."
+        ));
+    }
+    let (min_size, max_size) = (avg_size / 4, avg_size * 4);
+
+    let file = fs::File::open(path)
+        .map_err(|e| format!("Failed to open '{}' for chunking: {}", path.display(), e))?;
+    let chunker =
+        fastcdc::v2020::StreamCDC::new(BufReader::new(file), min_size, avg_size, max_size);
+
+    let mut chunks = Vec::new();
+    for chunk in chunker {
+        let chunk = chunk.map_err(|e| format!("Failed to chunk '{}': {}", path.display(), e))?;
+        chunks.push(Chunk {
+            offset: chunk.offset,
+            length: chunk.length as u64,
+            sha256: format!("{:x}", Sha256::digest(&chunk.data)),
+        });
+    }
+
+    Ok(ChunkIndex {
+        algorithm: CHUNK_ALGORITHM.to_string(),
+        min_size,
+        avg_size,
+        max_size,
+        chunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks_cover_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.img");
+        let data = pseudo_random(300_000, 1);
+        fs::write(&path, &data).unwrap();
+
+        let index = chunk_file(&path, 8192).unwrap();
+        assert_eq!(index.algorithm, CHUNK_ALGORITHM);
+        assert_eq!((index.min_size, index.max_size), (2048, 32768));
+
+        let mut offset = 0;
+        for chunk in &index.chunks {
+            assert_eq!(chunk.offset, offset);
+            let bytes = &data[offset as usize..(offset + chunk.length) as usize];
+            assert_eq!(chunk.sha256, format!("{:x}", Sha256::digest(bytes)));
+            offset += chunk.length;
+        }
+        assert_eq!(offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_chunks_survive_insertion() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data = pseudo_random(300_000, 2);
+        let mut edited = data.clone();
+        edited.splice(1000..1000, [0xAAu8; 100]);
+
+        let original_path = temp_dir.path().join("original.img");
+        let edited_path = temp_dir.path().join("edited.img");
+        fs::write(&original_path, &data).unwrap();
+        fs::write(&edited_path, &edited).unwrap();
+
+        let original = chunk_file(&original_path, 8192).unwrap();
+        let edited = chunk_file(&edited_path, 8192).unwrap();
+
+        // Only the chunks around the insertion change
+        let changed = edited
+            .chunks
+            .iter()
+            .filter(|c| !original.chunks.iter().any(|o| o.sha256 == c.sha256))
+            .count();
+        assert!(changed <= 2, "{changed} chunks changed");
+    }
+
+    #[test]
+    fn test_chunk_size_out_of_range() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.img");
+        fs::write(&path, b"data").unwrap();
+
+        assert!(chunk_file(&path, 1024).is_err());
+        assert!(chunk_file(&path, 8 * 1024 * 1024).is_err());
+    }
+}
@@ -0,0 +1,691 @@
+use super::inspect_bundle::{open_bundle_archive, read_bundle, verify_bundle_entries};
+use crate::bundle::Bundle;
+use crate::error::Error;
+use crate::log::*;
+use crate::manifest::{Size, SlotAction};
+use crate::mbr;
+use crate::signing;
+use clap::Args;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Placeholder in activate action values that is replaced with the slot being installed
+const SLOT_PLACEHOLDER: &str = "{slot}";
+
+#[derive(Args, Debug)]
+pub struct ApplyBundleArgs {
+    /// Path to the .aos bundle file
+    #[arg(value_name = "BUNDLE")]
+    pub bundle: PathBuf,
+
+    /// Disk image to install into, as PATH or DEVICE=PATH (repeat for each storage device)
+    #[arg(
+        short = 't',
+        long = "target",
+        value_name = "[DEVICE=]PATH",
+        required = true
+    )]
+    pub targets: Vec<String>,
+
+    /// Slot to install into (e.g. "a" or "b")
+    #[arg(short = 's', long = "slot", value_name = "SLOT")]
+    pub slot: String,
+
+    /// Stand-in U-Boot environment (key=value lines) for uboot-env actions [default: <TARGET>.uboot-env]
+    #[arg(long = "uboot-env", value_name = "PATH")]
+    pub uboot_env: Option<PathBuf>,
+
+    /// Stand-in EFI variables (key=value lines) for efibootmgr actions [default: <TARGET>.efivars]
+    #[arg(long = "efi-vars", value_name = "PATH")]
+    pub efi_vars: Option<PathBuf>,
+
+    /// Ed25519 public key; when given, the bundle signature must verify before anything is written
+    #[arg(long = "pubkey", value_name = "PATH")]
+    pub pubkey: Option<PathBuf>,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+impl ApplyBundleArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        apply_bundle_command(ApplyBundleParams {
+            bundle_path: &self.bundle,
+            targets: &self.targets,
+            slot: &self.slot,
+            uboot_env_path: self.uboot_env.as_deref(),
+            efi_vars_path: self.efi_vars.as_deref(),
+            pubkey_path: self.pubkey.as_deref(),
+            verbose: self.verbose,
+        })
+    }
+}
+
+pub struct ApplyBundleParams<'a> {
+    pub bundle_path: &'a Path,
+    /// Target images as `PATH` or `DEVICE=PATH`
+    pub targets: &'a [String],
+    pub slot: &'a str,
+    pub uboot_env_path: Option<&'a Path>,
+    pub efi_vars_path: Option<&'a Path>,
+    pub pubkey_path: Option<&'a Path>,
+    pub verbose: bool,
+}
+
+/// A partition from the bundle layout, in bytes
+#[derive(Debug, Clone, Copy)]
+struct LayoutPartition {
+    offset: u64,
+    size: u64,
+}
+
+/// One storage device from the bundle layout
+#[derive(Debug)]
+struct DeviceLayout {
+    devpath: String,
+    block_size: u64,
+    partitions: BTreeMap<String, LayoutPartition>,
+}
+
+/// Everything an activate action may touch
+struct ActivateContext<'a> {
+    slot: &'a str,
+    targets: &'a BTreeMap<String, PathBuf>,
+    uboot_env_path: &'a Path,
+    efi_vars_path: &'a Path,
+    layout: &'a BTreeMap<String, DeviceLayout>,
+    verbose: bool,
+}
+
+/// An artifact scheduled to be written into a target
+struct PlannedWrite<'a> {
+    name: &'a str,
+    device: &'a str,
+    partition: &'a str,
+    offset: u64,
+}
+
+pub fn apply_bundle_command(params: ApplyBundleParams) -> Result<(), Error> {
+    let ApplyBundleParams {
+        bundle_path,
+        targets,
+        slot,
+        uboot_env_path,
+        efi_vars_path,
+        pubkey_path,
+        verbose,
+    } = params;
+
+    if !bundle_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Bundle file '{}' not found.",
+            bundle_path.display()
+        )));
+    }
+    let (default_target, named_targets) = parse_targets(targets)?;
+    for target_path in default_target.iter().chain(named_targets.values()) {
+        if !target_path.exists() {
+            return Err(Error::MissingInput(format!(
+                "Target image '{}' not found.",
+                target_path.display()
+            )));
+        }
+    }
+
+    let pubkey = pubkey_path.map(signing::load_verifying_key).transpose()?;
+    let contents = read_bundle(bundle_path, verbose)?;
+
+    if let Some(pubkey) = &pubkey {
+        let signature = contents.signature.as_deref().ok_or_else(|| {
+            format!(
+                "Bundle '{}' is not signed (no {} entry).",
+                bundle_path.display(),
+                signing::SIGNATURE_ENTRY
+            )
+        })?;
+        signing::verify(pubkey, &contents.bundle_json, signature)
+            .map_err(|e| format!("Bundle signature verification failed: {e}"))?;
+        log_info("Bundle signature verified.");
+    }
+
+    let bundle = Bundle::from_slice(&contents.bundle_json)?;
+
+    let problems = verify_bundle_entries(&bundle, &contents.entries);
+    if !problems.is_empty() {
+        return Err(Error::Other(format!(
+            "Bundle '{}' failed verification:\n  {}",
+            bundle_path.display(),
+            problems.join("\n  ")
+        )));
+    }
+    if bundle.delta.is_some() {
+        return Err(Error::Other(format!(
+            "Bundle '{}' is a delta bundle; apply-bundle installs full bundles only.",
+            bundle_path.display()
+        )));
+    }
+
+    let layout = device_layouts(&bundle)?;
+    let plan = plan_writes(&bundle, &layout, slot)?;
+    let activate_actions = bundle
+        .update
+        .as_ref()
+        .map(|u| u.activate.as_slice())
+        .unwrap_or(&[]);
+
+    // Every device written to, either by an artifact or by an mbr-switch action
+    let mut used_devices: BTreeSet<&str> = plan.values().map(|w| w.device).collect();
+    for action in activate_actions {
+        if let SlotAction::MbrSwitch { devpath, .. } = action {
+            used_devices.insert(mbr_switch_device(devpath, &layout)?);
+        }
+    }
+    let targets = resolve_targets(&used_devices, default_target, named_targets)?;
+
+    for write in plan.values() {
+        let target_path = &targets[write.device];
+        let partition = &layout[write.device].partitions[write.partition];
+        let target_size = fs::metadata(target_path).map(|m| m.len()).map_err(|e| {
+            Error::Io(format!(
+                "Failed to get size of '{}': {e}",
+                target_path.display()
+            ))
+        })?;
+        if partition.offset + partition.size > target_size {
+            return Err(Error::Other(format!(
+                "Target image '{}' is too small for partition '{}' (needs {} bytes, has {}).",
+                target_path.display(),
+                write.partition,
+                partition.offset + partition.size,
+                target_size
+            )));
+        }
+    }
+
+    let mut summary = format!(
+        "Applying bundle to slot '{slot}'.\n  Bundle:  {}",
+        bundle_path.display()
+    );
+    for (device, target_path) in &targets {
+        summary.push_str(&format!(
+            "\n  Target:  {device} -> {}",
+            target_path.display()
+        ));
+    }
+    log_info(&summary);
+
+    write_artifacts(bundle_path, &targets, &plan)?;
+
+    // Stand-in files live next to the first target unless given explicitly
+    let first_target = targets
+        .values()
+        .next()
+        .ok_or("No target image to apply the bundle to.")?;
+    let uboot_env_path = uboot_env_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| stand_in_path(first_target, "uboot-env"));
+    let efi_vars_path = efi_vars_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| stand_in_path(first_target, "efivars"));
+
+    let context = ActivateContext {
+        slot,
+        targets: &targets,
+        uboot_env_path: &uboot_env_path,
+        efi_vars_path: &efi_vars_path,
+        layout: &layout,
+        verbose,
+    };
+    for action in activate_actions {
+        run_activate_action(action, &context)?;
+    }
+
+    log_success(&format!(
+        "Applied bundle '{}' (slot '{slot}').",
+        bundle_path.display()
+    ));
+    Ok(())
+}
+
+/// Split `--target` values into an optional device-less target and per-device targets
+fn parse_targets(
+    targets: &[String],
+) -> Result<(Option<PathBuf>, BTreeMap<String, PathBuf>), String> {
+    let mut default_target = None;
+    let mut named_targets = BTreeMap::new();
+
+    for target in targets {
+        match target.split_once('=') {
+            Some((device, path)) if !device.is_empty() && !device.contains('/') => {
+                if named_targets
+                    .insert(device.to_string(), PathBuf::from(path))
+                    .is_some()
+                {
+                    return Err(format!(
+                        "Device '{device}' was given more than one --target."
+                    ));
+                }
+            }
+            _ => {
+                if default_target.replace(PathBuf::from(target)).is_some() {
+                    return Err(
+                        "Only one --target may omit the device name; use DEVICE=PATH for the others."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok((default_target, named_targets))
+}
+
+/// Map every device the bundle writes to onto a target image.
+/// A device-less target is only accepted when exactly one device is written.
+fn resolve_targets(
+    used_devices: &BTreeSet<&str>,
+    mut default_target: Option<PathBuf>,
+    mut named_targets: BTreeMap<String, PathBuf>,
+) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut targets = BTreeMap::new();
+    let unnamed_allowed = used_devices.len() == 1;
+
+    for device in used_devices {
+        let target = match named_targets.remove(*device) {
+            Some(path) => path,
+            None => match default_target.take() {
+                Some(path) if unnamed_allowed => path,
+                _ => {
+                    return Err(format!(
+                        "No target image for device '{device}'. The bundle writes to {}; pass --target DEVICE=PATH for each.",
+                        used_devices.iter().copied().collect::<Vec<_>>().join(", ")
+                    ));
+                }
+            },
+        };
+        targets.insert(device.to_string(), target);
+    }
+
+    if let Some(device) = named_targets.keys().next() {
+        log_warning(&format!(
+            "The bundle does not write to device '{device}'; ignoring its --target."
+        ));
+    }
+
+    Ok(targets)
+}
+
+/// Default location of a stand-in file next to a target image
+fn stand_in_path(target_path: &Path, extension: &str) -> PathBuf {
+    let mut path = target_path.as_os_str().to_owned();
+    path.push(format!(".{extension}"));
+    PathBuf::from(path)
+}
+
+/// Read the bundle layout into devices keyed by name
+fn device_layouts(bundle: &Bundle) -> Result<BTreeMap<String, DeviceLayout>, String> {
+    if bundle.layout.is_empty() {
+        return Err("Bundle has no layout section; cannot place artifacts.".to_string());
+    }
+
+    let mut devices = BTreeMap::new();
+    for (device_name, device) in &bundle.layout {
+        let mut partitions = BTreeMap::new();
+        for partition in &device.partitions {
+            let Some(name) = &partition.name else {
+                continue;
+            };
+            let offset = Size::new(partition.offset, partition.offset_unit)
+                .bytes(device.block_size())
+                .map_err(|e| format!("Layout partition '{name}' offset: {e}"))?;
+            let size = Size::new(partition.size, partition.size_unit)
+                .bytes(device.block_size())
+                .map_err(|e| format!("Layout partition '{name}' size: {e}"))?;
+            partitions.insert(name.clone(), LayoutPartition { offset, size });
+        }
+
+        devices.insert(
+            device_name.clone(),
+            DeviceLayout {
+                devpath: device.devpath.clone(),
+                block_size: device.block_size(),
+                partitions,
+            },
+        );
+    }
+
+    Ok(devices)
+}
+
+/// Resolve the device and partition each artifact is written to for `slot`, keyed by archive path
+fn plan_writes<'a>(
+    bundle: &'a Bundle,
+    layout: &BTreeMap<String, DeviceLayout>,
+    slot: &str,
+) -> Result<BTreeMap<&'a str, PlannedWrite<'a>>, String> {
+    let mut plan = BTreeMap::new();
+    let mut known_slots = BTreeSet::new();
+
+    for artifact in bundle.artifacts() {
+        let name = artifact.name.as_str();
+        known_slots.extend(artifact.slot_targets.keys().map(String::as_str));
+
+        let Some(slot_target) = artifact.slot_targets.get(slot) else {
+            log_warning(&format!(
+                "Artifact '{name}' has no target for slot '{slot}'; skipping."
+            ));
+            continue;
+        };
+        let (device, partition) = (slot_target.device.as_str(), slot_target.partition.as_str());
+        let size = artifact.size;
+
+        let target = layout
+            .get(device)
+            .and_then(|d| d.partitions.get(partition))
+            .ok_or_else(|| {
+                format!(
+                    "Artifact '{name}' targets partition '{partition}' on device '{device}', which is not in the bundle layout."
+                )
+            })?;
+        if size > target.size {
+            return Err(format!(
+                "Artifact '{name}' ({size} bytes) does not fit in partition '{partition}' ({} bytes).",
+                target.size
+            ));
+        }
+
+        plan.insert(
+            artifact.file.as_str(),
+            PlannedWrite {
+                name,
+                device,
+                partition,
+                offset: target.offset,
+            },
+        );
+    }
+
+    if plan.is_empty() {
+        let available: Vec<_> = known_slots.into_iter().collect();
+        return Err(format!(
+            "Slot '{slot}' is not targeted by any artifact in the bundle (available: {}).",
+            if available.is_empty() {
+                "none".to_string()
+            } else {
+                available.join(", ")
+            }
+        ));
+    }
+
+    Ok(plan)
+}
+
+/// Stream the archive once, writing each planned artifact at its partition offset
+fn write_artifacts(
+    bundle_path: &Path,
+    targets: &BTreeMap<String, PathBuf>,
+    plan: &BTreeMap<&str, PlannedWrite>,
+) -> Result<(), Error> {
+    let mut files = BTreeMap::new();
+    for (device, target_path) in targets {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(target_path)
+            .map_err(|e| {
+                Error::Io(format!(
+                    "Failed to open target '{}': {}",
+                    target_path.display(),
+                    e
+                ))
+            })?;
+        files.insert(device.as_str(), file);
+    }
+
+    let mut archive = open_bundle_archive(bundle_path)?;
+    let entries = archive
+        .entries()
+        .map_err(|e| Error::Io(format!("Failed to read bundle archive: {e}")))?;
+    for entry in entries {
+        let mut entry =
+            entry.map_err(|e| Error::Io(format!("Failed to read bundle archive entry: {e}")))?;
+        let path = entry
+            .path()
+            .map_err(|e| Error::Other(format!("Invalid path in bundle archive: {e}")))?
+            .to_string_lossy()
+            .to_string();
+        let Some(write) = plan.get(path.as_str()) else {
+            continue;
+        };
+
+        log_info(&format!(
+            "Writing artifact '{}' to partition '{}' on '{}' at offset {}.",
+            write.name, write.partition, write.device, write.offset
+        ));
+        let target = files.get_mut(write.device).ok_or_else(|| {
+            Error::Other(format!("No target image for device '{}'.", write.device))
+        })?;
+        target
+            .seek(SeekFrom::Start(write.offset))
+            .and_then(|_| std::io::copy(&mut entry, target))
+            .map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write artifact '{}' to '{}': {}",
+                    write.name,
+                    targets[write.device].display(),
+                    e
+                ))
+            })?;
+    }
+
+    for (device, file) in files {
+        file.sync_all().map_err(|e| {
+            Error::Io(format!(
+                "Failed to flush '{}': {}",
+                targets[device].display(),
+                e
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Find the layout device an mbr-switch action applies to, by its devpath
+fn mbr_switch_device<'a>(
+    devpath: &str,
+    layout: &'a BTreeMap<String, DeviceLayout>,
+) -> Result<&'a str, String> {
+    layout
+        .iter()
+        .find(|(_, device)| device.devpath == devpath)
+        .map(|(name, _)| name.as_str())
+        .ok_or_else(|| format!("mbr-switch devpath '{devpath}' is not in the bundle layout"))
+}
+
+/// Run one activate action against the targets and their stand-in files
+fn run_activate_action(action: &SlotAction, context: &ActivateContext) -> Result<(), Error> {
+    let ActivateContext {
+        slot,
+        targets,
+        uboot_env_path,
+        efi_vars_path,
+        layout,
+        verbose,
+    } = *context;
+
+    match action {
+        SlotAction::UbootEnv { set } => {
+            let vars: BTreeMap<String, String> = set
+                .iter()
+                .map(|(key, value)| (key.clone(), value.replace(SLOT_PLACEHOLDER, slot)))
+                .collect();
+            if verbose {
+                log_debug(&format!("uboot-env: {vars:?}"));
+            }
+            update_env_file(uboot_env_path, &vars)?;
+            log_info(&format!(
+                "Updated U-Boot environment '{}'.",
+                uboot_env_path.display()
+            ));
+        }
+        SlotAction::Efibootmgr { slot_entries } => {
+            let label = slot_entries
+                .get(slot)
+                .ok_or_else(|| format!("efibootmgr action has no boot entry for slot '{slot}'"))?;
+            let vars = BTreeMap::from([("BootNext".to_string(), label.clone())]);
+            update_env_file(efi_vars_path, &vars)?;
+            log_info(&format!(
+                "Set BootNext to '{label}' in '{}'.",
+                efi_vars_path.display()
+            ));
+        }
+        SlotAction::MbrSwitch {
+            devpath,
+            slot_layouts,
+        } => {
+            let device_name = mbr_switch_device(devpath, layout)?;
+            let device = &layout[device_name];
+            let target_path = &targets[device_name];
+            let names = slot_layouts
+                .get(slot)
+                .ok_or_else(|| format!("mbr-switch action has no layout for slot '{slot}'"))?;
+            let partitions = names
+                .iter()
+                .map(|name| {
+                    device.partitions.get(name).copied().ok_or_else(|| {
+                        format!(
+                            "mbr-switch partition '{name}' is not on device '{device_name}' in the bundle layout"
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            write_mbr_entries(target_path, &partitions, device.block_size)?;
+            log_info(&format!(
+                "Switched MBR of '{}' to [{}].",
+                target_path.display(),
+                names.join(", ")
+            ));
+        }
+        SlotAction::Command { command } => {
+            log_warning(&format!(
+                "Skipping command action {command:?}: commands are not run offline."
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Set `vars` in a key=value stand-in file, keeping unrelated lines in place
+fn update_env_file(path: &Path, vars: &BTreeMap<String, String>) -> Result<(), Error> {
+    let existing = if path.exists() {
+        fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("Failed to read '{}': {}", path.display(), e)))?
+    } else {
+        String::new()
+    };
+
+    let mut remaining = vars.clone();
+    let mut lines: Vec<String> = existing
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((key, _)) => match remaining.remove(key) {
+                Some(value) => format!("{key}={value}"),
+                None => line.to_string(),
+            },
+            None => line.to_string(),
+        })
+        .collect();
+    lines.extend(
+        remaining
+            .iter()
+            .map(|(key, value)| format!("{key}={value}")),
+    );
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(path, content)
+        .map_err(|e| Error::Io(format!("Failed to write '{}': {}", path.display(), e)))
+}
+
+/// Rewrite the primary partition table so its entries are exactly `partitions`, in order.
+/// Existing entries keep their status and type bytes; new entries are Linux partitions.
+fn write_mbr_entries(
+    target_path: &Path,
+    partitions: &[LayoutPartition],
+    block_size: u64,
+) -> Result<(), Error> {
+    if partitions.len() > mbr::PRIMARY_COUNT {
+        return Err(Error::Other(format!(
+            "mbr-switch lists {} partitions; an MBR holds at most {} primary partitions.",
+            partitions.len(),
+            mbr::PRIMARY_COUNT
+        )));
+    }
+
+    let mut target = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(target_path)
+        .map_err(|e| {
+            Error::Io(format!(
+                "Failed to open target '{}': {}",
+                target_path.display(),
+                e
+            ))
+        })?;
+    let mut record = [0u8; 512];
+    target.read_exact(&mut record).map_err(|e| {
+        Error::Io(format!(
+            "Failed to read MBR of '{}': {}",
+            target_path.display(),
+            e
+        ))
+    })?;
+
+    for (idx, entry) in record[446..510].chunks_exact_mut(16).enumerate() {
+        let Some(partition) = partitions.get(idx) else {
+            entry.fill(0);
+            continue;
+        };
+        if partition.offset % block_size != 0 || partition.size % block_size != 0 {
+            return Err(Error::Other(format!(
+                "Partition at offset {} is not aligned to the {block_size}-byte block size.",
+                partition.offset
+            )));
+        }
+        let start = u32::try_from(partition.offset / block_size).map_err(|_| {
+            Error::Other(format!(
+                "Partition offset {} is beyond MBR range.",
+                partition.offset
+            ))
+        })?;
+        let sectors = u32::try_from(partition.size / block_size).map_err(|_| {
+            Error::Other(format!(
+                "Partition size {} is beyond MBR range.",
+                partition.size
+            ))
+        })?;
+
+        let (status, partition_type) = match entry[4] {
+            0 => (0, mbr::LINUX),
+            partition_type => (entry[0], partition_type),
+        };
+        mbr::write_entry(entry, status, partition_type, start, sectors);
+    }
+    record[510] = 0x55;
+    record[511] = 0xAA;
+
+    target
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| target.write_all(&record))
+        .map_err(|e| {
+            Error::Io(format!(
+                "Failed to write MBR of '{}': {}",
+                target_path.display(),
+                e
+            ))
+        })
+}
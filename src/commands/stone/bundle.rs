@@ -1,14 +1,159 @@
+use crate::bundle;
+use crate::chunk_index::{self, DEFAULT_AVG_CHUNK_SIZE, MAX_AVG_CHUNK_SIZE, MIN_AVG_CHUNK_SIZE};
+use crate::error::Error;
 use crate::fat;
 use crate::log::*;
-use crate::manifest::{BuildArgs, FatVariant, FileEntry, Image, Manifest};
-use clap::Args;
+use crate::manifest::{
+    BuildArgs, DEFAULT_BLOCK_SIZE, FatVariant, FileEntry, Image, Manifest, ManifestFormat,
+    ManifestOptions, Release, Size, SizeUnit, parse_var,
+};
+use crate::signing;
+use clap::{Args, ValueEnum};
 use sha2::{Digest, Sha256};
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::Read;
+use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
+/// Window log used by `--long` (128 MiB, the default of `zstd --long`)
+const LONG_WINDOW_LOG: u32 = 27;
+
+/// Compression applied to the tar stream of a .aos archive
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// zstd-compressed tar
+    #[default]
+    Zstd,
+    /// Plain tar, for local testing
+    None,
+}
+
+/// Options controlling how a .aos archive is compressed
+#[derive(Args, Debug, Clone)]
+pub struct CompressionArgs {
+    /// Compression applied to the .aos archive
+    #[arg(long = "compression", value_enum, default_value_t = Compression::Zstd)]
+    pub compression: Compression,
+
+    /// zstd compression level (1-22)
+    #[arg(
+        long = "compression-level",
+        value_name = "LEVEL",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(i32).range(1..=22)
+    )]
+    pub level: i32,
+
+    /// Number of zstd worker threads (0 compresses on the calling thread)
+    #[arg(long = "threads", value_name = "N", default_value_t = 0)]
+    pub threads: u32,
+
+    /// Enable zstd long-distance matching with a 128 MiB window
+    #[arg(long = "long")]
+    pub long: bool,
+}
+
+impl Default for CompressionArgs {
+    fn default() -> Self {
+        Self {
+            compression: Compression::Zstd,
+            level: 3,
+            threads: 0,
+            long: false,
+        }
+    }
+}
+
+/// Release metadata written to bundle.json; each option overrides the manifest's release section
+#[derive(Args, Debug, Clone, Default)]
+pub struct ReleaseArgs {
+    /// Release version of the bundle
+    #[arg(long = "release-version", value_name = "VERSION")]
+    pub version: Option<String>,
+
+    /// Release notes of the bundle
+    #[arg(long = "release-notes", value_name = "TEXT")]
+    pub notes: Option<String>,
+
+    /// Read the release notes from a file
+    #[arg(
+        long = "release-notes-file",
+        value_name = "PATH",
+        conflicts_with = "notes"
+    )]
+    pub notes_file: Option<PathBuf>,
+
+    /// Oldest installed AVOCADO_OS_BUILD_ID the bundle may be installed over
+    #[arg(long = "min-os-build-id", value_name = "ID")]
+    pub min_os_build_id: Option<String>,
+
+    /// Oldest installed release version the bundle may be installed over
+    #[arg(long = "min-version", value_name = "VERSION")]
+    pub min_version: Option<String>,
+
+    /// Hardware revision the bundle is compatible with (repeat for each; replaces the manifest's list)
+    #[arg(long = "hardware-revision", value_name = "REV")]
+    pub hardware_revisions: Vec<String>,
+}
+
+impl ReleaseArgs {
+    /// Apply the options over the manifest's release section.
+    /// Returns None when neither gives any release metadata.
+    pub fn resolve(&self, manifest_release: Option<&Release>) -> Result<Option<Release>, Error> {
+        let mut release = manifest_release.cloned().unwrap_or_default();
+
+        if let Some(version) = &self.version {
+            release.version = Some(version.clone());
+        }
+        if let Some(notes) = &self.notes {
+            release.notes = Some(notes.clone());
+        }
+        if let Some(notes_file) = &self.notes_file {
+            let notes = fs::read_to_string(notes_file).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to read release notes '{}': {}",
+                    notes_file.display(),
+                    e
+                ))
+            })?;
+            release.notes = Some(notes.trim_end().to_string());
+        }
+        if let Some(min_os_build_id) = &self.min_os_build_id {
+            release.min_os_build_id = Some(min_os_build_id.clone());
+        }
+        if let Some(min_version) = &self.min_version {
+            release.min_version = Some(min_version.clone());
+        }
+        if !self.hardware_revisions.is_empty() {
+            release.hardware_revisions = self.hardware_revisions.clone();
+        }
+
+        for (field, value) in [
+            ("version", &release.version),
+            ("min_os_build_id", &release.min_os_build_id),
+            ("min_version", &release.min_version),
+        ] {
+            if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+                return Err(format!("Release {field} must not be empty.").into());
+            }
+        }
+        let mut seen = std::collections::BTreeSet::new();
+        for revision in &release.hardware_revisions {
+            if revision.trim().is_empty() {
+                return Err("Hardware revisions must not be empty.".into());
+            }
+            if !seen.insert(revision.as_str()) {
+                return Err(
+                    format!("Hardware revision '{revision}' is listed more than once.").into(),
+                );
+            }
+        }
+
+        Ok((!release.is_empty()).then_some(release))
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct BundleArgs {
     /// Path to the stone manifest JSON file
@@ -20,6 +165,14 @@ pub struct BundleArgs {
     )]
     pub manifest: PathBuf,
 
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Value for a ${NAME} reference in the manifest (can be specified multiple times)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
     /// Path to the OS release file to include
     #[arg(long = "os-release", value_name = "PATH")]
     pub os_release: PathBuf,
@@ -50,25 +203,70 @@ pub struct BundleArgs {
     #[arg(long = "build-dir", value_name = "DIR")]
     pub build_dir: Option<PathBuf>,
 
+    /// Ed25519 private key used to sign bundle.json (PKCS#8 PEM, hex or raw seed)
+    #[arg(long = "signing-key", value_name = "PATH")]
+    pub signing_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub compression: CompressionArgs,
+
+    /// Split each artifact into content-defined chunks and add a chunk index per artifact
+    #[arg(long = "chunk-index")]
+    pub chunk_index: bool,
+
+    /// Average chunk size in bytes for --chunk-index
+    #[arg(
+        long = "chunk-size",
+        value_name = "BYTES",
+        default_value_t = DEFAULT_AVG_CHUNK_SIZE,
+        value_parser = clap::value_parser!(u32).range(MIN_AVG_CHUNK_SIZE as i64..=MAX_AVG_CHUNK_SIZE as i64),
+        requires = "chunk_index"
+    )]
+    pub chunk_size: u32,
+
+    #[command(flatten)]
+    pub release: ReleaseArgs,
+
     /// Enable verbose output
     #[arg(short = 'v', long = "verbose")]
     pub verbose: bool,
 }
 
 impl BundleArgs {
-    pub fn execute(&self) -> Result<(), String> {
-        bundle_command(
-            &self.manifest,
-            &self.os_release,
-            self.os_release_initrd.as_deref(),
-            &self.input_dirs,
-            &self.output,
-            self.build_dir.as_deref(),
-            self.verbose,
-        )
+    pub fn execute(&self) -> Result<(), Error> {
+        bundle_command(BundleParams {
+            manifest_path: &self.manifest,
+            manifest_options: &ManifestOptions::new(self.format, &self.vars),
+            os_release_path: &self.os_release,
+            os_release_initrd_path: self.os_release_initrd.as_deref(),
+            input_dirs: &self.input_dirs,
+            output_path: &self.output,
+            build_dir_override: self.build_dir.as_deref(),
+            signing_key_path: self.signing_key.as_deref(),
+            compression: &self.compression,
+            chunk_size: self.chunk_index.then_some(self.chunk_size),
+            release: &self.release,
+            verbose: self.verbose,
+        })
     }
 }
 
+pub struct BundleParams<'a> {
+    pub manifest_path: &'a Path,
+    pub manifest_options: &'a ManifestOptions,
+    pub os_release_path: &'a Path,
+    pub os_release_initrd_path: Option<&'a Path>,
+    pub input_dirs: &'a [PathBuf],
+    pub output_path: &'a Path,
+    pub build_dir_override: Option<&'a Path>,
+    pub signing_key_path: Option<&'a Path>,
+    pub compression: &'a CompressionArgs,
+    /// Average chunk size when a chunk index should be written for each artifact
+    pub chunk_size: Option<u32>,
+    pub release: &'a ReleaseArgs,
+    pub verbose: bool,
+}
+
 /// Find a file in multiple input directories, searching in order
 fn find_file_in_dirs(filename: &str, input_dirs: &[PathBuf]) -> Option<PathBuf> {
     for dir in input_dirs {
@@ -81,15 +279,20 @@ fn find_file_in_dirs(filename: &str, input_dirs: &[PathBuf]) -> Option<PathBuf>
 }
 
 /// Compute SHA256 hash of a file, returning the hex string
-fn sha256_file(path: &Path) -> Result<String, String> {
-    let mut file = fs::File::open(path)
-        .map_err(|e| format!("Failed to open '{}' for hashing: {}", path.display(), e))?;
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to open '{}' for hashing: {}",
+            path.display(),
+            e
+        ))
+    })?;
     let mut hasher = Sha256::new();
     let mut buf = [0u8; 8192];
     loop {
         let n = file
             .read(&mut buf)
-            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+            .map_err(|e| Error::Io(format!("Failed to read '{}': {}", path.display(), e)))?;
         if n == 0 {
             break;
         }
@@ -98,30 +301,46 @@ fn sha256_file(path: &Path) -> Result<String, String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-pub fn bundle_command(
-    manifest_path: &Path,
-    os_release_path: &Path,
-    os_release_initrd_path: Option<&Path>,
-    input_dirs: &[PathBuf],
-    output_path: &Path,
-    build_dir_override: Option<&Path>,
-    verbose: bool,
-) -> Result<(), String> {
+pub fn bundle_command(params: BundleParams) -> Result<(), Error> {
+    let BundleParams {
+        manifest_path,
+        manifest_options,
+        os_release_path,
+        os_release_initrd_path,
+        input_dirs,
+        output_path,
+        build_dir_override,
+        signing_key_path,
+        compression,
+        chunk_size,
+        release,
+        verbose,
+    } = params;
+
     // Validate inputs exist
     if !manifest_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "Manifest file '{}' not found.",
             manifest_path.display()
-        ));
+        )));
     }
     if !os_release_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "OS release file '{}' not found.",
             os_release_path.display()
-        ));
+        )));
     }
 
-    let manifest = Manifest::from_file(manifest_path)?;
+    let manifest = Manifest::load(manifest_path, manifest_options)?;
+    let release = release.resolve(manifest.release.as_ref())?;
+
+    // Every timestamp written into the bundle comes from SOURCE_DATE_EPOCH (or 0)
+    let timestamp = source_date_epoch()?;
+
+    // Load the signing key up front so a bad key fails before any images are built
+    let signing_key = signing_key_path
+        .map(signing::load_signing_key)
+        .transpose()?;
 
     // Determine build directory
     let default_build_dir = output_path
@@ -131,20 +350,20 @@ pub fn bundle_command(
     let build_dir = build_dir_override.unwrap_or(&default_build_dir);
 
     fs::create_dir_all(build_dir).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to create build directory '{}': {}",
             build_dir.display(),
             e
-        )
+        ))
     })?;
 
     let images_dir = build_dir.join("images");
     fs::create_dir_all(&images_dir).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to create images directory '{}': {}",
             images_dir.display(),
             e
-        )
+        ))
     })?;
 
     log_info(&format!(
@@ -154,10 +373,15 @@ pub fn bundle_command(
         output_path.display()
     ));
 
-    // Step 1: Copy all manifest inputs to build dir (like stone create)
+    // Step 1: Copy all manifest inputs to build dir (like stone create).
+    // The manifest itself is always stored as JSON, whatever format it was written in.
+    Manifest::copy_as_json(
+        manifest_path,
+        manifest_options,
+        &build_dir.join("manifest.json"),
+    )?;
     copy_manifest_inputs(
         &manifest,
-        manifest_path,
         os_release_path,
         os_release_initrd_path,
         input_dirs,
@@ -166,10 +390,33 @@ pub fn bundle_command(
     )?;
 
     // Step 2: Build FAT images and collect built image artifacts
-    let built_images = build_all_images(&manifest, input_dirs, build_dir, &images_dir, verbose)?;
+    let built_images = build_all_images(
+        &manifest,
+        input_dirs,
+        build_dir,
+        &images_dir,
+        timestamp,
+        verbose,
+    )?;
 
     // Step 3: Collect all artifacts (built images + pre-existing images)
-    let artifacts = collect_artifacts(&manifest, &built_images, input_dirs, &images_dir, verbose)?;
+    let mut artifacts =
+        collect_artifacts(&manifest, &built_images, input_dirs, &images_dir, verbose)?;
+
+    // Step 3b: Write a chunk index for each artifact (if requested)
+    if let Some(avg_size) = chunk_size {
+        let chunks_dir = build_dir.join("chunks");
+        fs::create_dir_all(&chunks_dir).map_err(|e| {
+            Error::Io(format!(
+                "Failed to create chunks directory '{}': {}",
+                chunks_dir.display(),
+                e
+            ))
+        })?;
+        for artifact in &mut artifacts {
+            artifact.chunk_index = Some(write_chunk_index(artifact, &chunks_dir, avg_size)?);
+        }
+    }
 
     // Step 4: Parse os-release for OS build ID
     let os_build_id = parse_os_release_field(os_release_path, "AVOCADO_OS_BUILD_ID")?;
@@ -188,52 +435,123 @@ pub fn bundle_command(
         &artifacts,
         &os_build_id,
         initramfs_build_id.as_deref(),
+        release,
     )?;
     let bundle_json_path = build_dir.join("bundle.json");
-    let bundle_json_str = serde_json::to_string_pretty(&bundle_json)
-        .map_err(|e| format!("Failed to serialize bundle.json: {e}"))?;
+    let bundle_json_str = bundle_json.to_json_pretty()?;
     fs::write(&bundle_json_path, &bundle_json_str)
-        .map_err(|e| format!("Failed to write bundle.json: {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to write bundle.json: {e}")))?;
 
     if verbose {
         log_debug(&format!("Generated bundle.json:\n{bundle_json_str}"));
     }
 
-    // Step 6: Package into .aos (tar.zst)
-    package_aos(output_path, &bundle_json_path, &artifacts, verbose)?;
+    // Step 6: Sign bundle.json (if a signing key was provided)
+    let signature_path = if let Some(key) = &signing_key {
+        let signature_path = build_dir.join(signing::SIGNATURE_ENTRY);
+        fs::write(
+            &signature_path,
+            signing::sign(key, bundle_json_str.as_bytes()),
+        )
+        .map_err(|e| Error::Io(format!("Failed to write bundle.json signature: {e}")))?;
+        log_info("Signed bundle.json.");
+        Some(signature_path)
+    } else {
+        None
+    };
+
+    // Step 7: Package into .aos (tar.zst)
+    package_aos(
+        output_path,
+        &bundle_json_path,
+        signature_path.as_deref(),
+        &artifacts,
+        timestamp,
+        compression,
+        verbose,
+    )?;
 
     log_success(&format!("OS bundle created: {}", output_path.display()));
     Ok(())
 }
 
 /// Represents a built/collected artifact ready for packaging
-struct BundleArtifact {
+pub struct BundleArtifact {
     /// Name of the artifact (e.g., "boot", "rootfs")
-    name: String,
+    pub name: String,
     /// Path to the artifact file on disk
-    path: PathBuf,
+    pub path: PathBuf,
     /// Relative path inside the .aos archive (e.g., "images/boot.img")
-    archive_path: String,
+    pub archive_path: String,
     /// SHA256 hash
-    sha256: String,
+    pub sha256: String,
     /// File size in bytes
-    size: u64,
+    pub size: u64,
+    /// Chunk index sidecar for this artifact, if one was written
+    pub chunk_index: Option<ChunkIndexFile>,
+}
+
+/// A chunk index sidecar file ready for packaging
+pub struct ChunkIndexFile {
+    /// Path to the index file on disk
+    pub path: PathBuf,
+    /// Relative path inside the .aos archive (e.g., "chunks/rootfs.json")
+    pub archive_path: String,
+    /// SHA256 hash of the index file
+    pub sha256: String,
+    /// Index file size in bytes
+    pub size: u64,
+    /// Average chunk size the index was built with
+    pub avg_size: u32,
+    /// Number of chunks in the index
+    pub count: usize,
+}
+
+/// Chunk an artifact and write its index as `chunks/<name>.json`
+fn write_chunk_index(
+    artifact: &BundleArtifact,
+    chunks_dir: &Path,
+    avg_size: u32,
+) -> Result<ChunkIndexFile, Error> {
+    let index = chunk_index::chunk_file(&artifact.path, avg_size)?;
+    let archive_path = format!("chunks/{}.json", artifact.name);
+    let path = chunks_dir.join(format!("{}.json", artifact.name));
+
+    let index_json = serde_json::to_string(&index)
+        .map_err(|e| format!("Failed to serialize chunk index: {e}"))?;
+    fs::write(&path, &index_json).map_err(|e| {
+        Error::Io(format!(
+            "Failed to write chunk index '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    log_info(&format!(
+        "Chunked artifact '{}' into {} chunks.",
+        artifact.name,
+        index.chunks.len()
+    ));
+
+    Ok(ChunkIndexFile {
+        sha256: sha256_file(&path)?,
+        size: index_json.len() as u64,
+        path,
+        archive_path,
+        avg_size,
+        count: index.chunks.len(),
+    })
 }
 
 /// Copy manifest inputs to the build directory (mirrors stone create behavior)
 fn copy_manifest_inputs(
     manifest: &Manifest,
-    manifest_path: &Path,
     os_release_path: &Path,
     os_release_initrd_path: Option<&Path>,
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
-    // Copy the manifest itself
-    let manifest_dest = build_dir.join("manifest.json");
-    copy_file(manifest_path, &manifest_dest, verbose)?;
-
+) -> Result<(), Error> {
     // Copy os-release
     let os_release_dest = build_dir.join("os-release");
     copy_file(os_release_path, &os_release_dest, verbose)?;
@@ -304,8 +622,9 @@ fn build_all_images(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     images_dir: &Path,
+    timestamp: u64,
     verbose: bool,
-) -> Result<HashMap<String, PathBuf>, String> {
+) -> Result<HashMap<String, PathBuf>, Error> {
     let mut built = HashMap::new();
 
     for device in manifest.storage_devices.values() {
@@ -316,11 +635,13 @@ fn build_all_images(
                     build_args: Some(BuildArgs::Fat { variant, files }),
                     size,
                     size_unit,
+                    block_size,
                     ..
                 } => {
                     log_info(&format!("Building FAT image '{image_name}' -> '{out}'."));
 
-                    let size_mb = convert_size_to_mb(*size, size_unit)?;
+                    let size = Size::new(*size, *size_unit)
+                        .bytes(block_size.map_or(DEFAULT_BLOCK_SIZE, u64::from))?;
                     let fat_type = match variant {
                         FatVariant::Fat12 => fat::FatType::Fat12,
                         FatVariant::Fat16 => fat::FatType::Fat16,
@@ -332,8 +653,9 @@ fn build_all_images(
                         build_dir.join(format!("temp_manifest_{image_name}.json"));
                     let manifest_json = serde_json::to_string_pretty(&fat_manifest)
                         .map_err(|e| format!("Failed to serialize FAT manifest: {e}"))?;
-                    fs::write(&temp_manifest_path, manifest_json)
-                        .map_err(|e| format!("Failed to write temporary manifest: {e}"))?;
+                    fs::write(&temp_manifest_path, manifest_json).map_err(|e| {
+                        Error::Io(format!("Failed to write temporary manifest: {e}"))
+                    })?;
 
                     // Build into images/ dir for the bundle, and also into build_dir for provision
                     let output_in_images = images_dir.join(out);
@@ -344,16 +666,18 @@ fn build_all_images(
                         .with_manifest_path(&temp_manifest_path)
                         .with_base_path(&base_path)
                         .with_output_path(&output_in_images)
-                        .with_size_mebibytes(size_mb)
+                        .with_size_bytes(size)
                         .with_fat_type(fat_type)
+                        .with_timestamp(timestamp)
                         .with_verbose(verbose);
 
                     fat::create_fat_image(&options)?;
                     let _ = fs::remove_file(&temp_manifest_path);
 
                     // Also copy to build_dir so provision can find it at the same path as before
-                    fs::copy(&output_in_images, &output_in_build)
-                        .map_err(|e| format!("Failed to copy built image to build dir: {e}"))?;
+                    fs::copy(&output_in_images, &output_in_build).map_err(|e| {
+                        Error::Io(format!("Failed to copy built image to build dir: {e}"))
+                    })?;
 
                     log_success(&format!("Built FAT image '{out}'."));
                     built.insert(image_name.clone(), output_in_images);
@@ -376,7 +700,7 @@ fn collect_artifacts(
     input_dirs: &[PathBuf],
     images_dir: &Path,
     verbose: bool,
-) -> Result<Vec<BundleArtifact>, String> {
+) -> Result<Vec<BundleArtifact>, Error> {
     let mut artifacts = Vec::new();
 
     let update = match &manifest.update {
@@ -394,8 +718,11 @@ fn collect_artifacts(
         }
     };
 
-    // Collect only the images referenced in os_artifacts
-    for (artifact_name, artifact_ref) in &update.os_artifacts {
+    // Collect only the images referenced in os_artifacts, sorted by name for a stable archive
+    let mut os_artifacts: Vec<_> = update.os_artifacts.iter().collect();
+    os_artifacts.sort_by_key(|(name, _)| *name);
+
+    for (artifact_name, artifact_ref) in os_artifacts {
         let image_key = &artifact_ref.image_key;
 
         // Find this image in the manifest's storage_devices
@@ -411,10 +738,10 @@ fn collect_artifacts(
             // stale cached artifacts from a previous build
             let dest = images_dir.join(filename);
             let src = find_file_in_dirs(filename, input_dirs).ok_or_else(|| {
-                format!(
+                Error::MissingInput(format!(
                     "Image file '{}' for artifact '{}' not found in any input directory",
                     filename, artifact_name
-                )
+                ))
             })?;
             copy_file(&src, &dest, verbose)?;
             dest
@@ -429,7 +756,12 @@ fn collect_artifacts(
         let sha256 = sha256_file(&image_path)?;
         let size = std::fs::metadata(&image_path)
             .map(|m| m.len())
-            .map_err(|e| format!("Failed to get size of '{}': {e}", image_path.display()))?;
+            .map_err(|e| {
+                Error::Io(format!(
+                    "Failed to get size of '{}': {e}",
+                    image_path.display()
+                ))
+            })?;
 
         if verbose {
             log_debug(&format!(
@@ -443,6 +775,7 @@ fn collect_artifacts(
             archive_path,
             sha256,
             size,
+            chunk_index: None,
         });
     }
 
@@ -456,11 +789,18 @@ fn collect_all_images_as_artifacts(
     input_dirs: &[PathBuf],
     images_dir: &Path,
     verbose: bool,
-) -> Result<Vec<BundleArtifact>, String> {
+) -> Result<Vec<BundleArtifact>, Error> {
     let mut artifacts = Vec::new();
 
-    for device in manifest.storage_devices.values() {
-        for (image_name, image) in &device.images {
+    // Walk devices and images in name order for a stable archive
+    let mut devices: Vec<_> = manifest.storage_devices.iter().collect();
+    devices.sort_by_key(|(name, _)| *name);
+
+    for (_, device) in devices {
+        let mut images: Vec<_> = device.images.iter().collect();
+        images.sort_by_key(|(name, _)| *name);
+
+        for (image_name, image) in images {
             let image_path = if let Some(path) = built_images.get(image_name) {
                 path.clone()
             } else {
@@ -492,7 +832,12 @@ fn collect_all_images_as_artifacts(
             let sha256 = sha256_file(&image_path)?;
             let size = std::fs::metadata(&image_path)
                 .map(|m| m.len())
-                .map_err(|e| format!("Failed to get size of '{}': {e}", image_path.display()))?;
+                .map_err(|e| {
+                    Error::Io(format!(
+                        "Failed to get size of '{}': {e}",
+                        image_path.display()
+                    ))
+                })?;
 
             artifacts.push(BundleArtifact {
                 name: image_name.clone(),
@@ -500,6 +845,7 @@ fn collect_all_images_as_artifacts(
                 archive_path,
                 sha256,
                 size,
+                chunk_index: None,
             });
         }
     }
@@ -523,9 +869,14 @@ fn find_image_in_manifest<'a>(
 }
 
 /// Parse a field from an os-release file (KEY=VALUE format)
-fn parse_os_release_field(path: &Path, field: &str) -> Result<String, String> {
-    let content = fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read os-release '{}': {}", path.display(), e))?;
+fn parse_os_release_field(path: &Path, field: &str) -> Result<String, Error> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to read os-release '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
 
     for line in content.lines() {
         let line = line.trim();
@@ -546,272 +897,371 @@ fn generate_bundle_json(
     artifacts: &[BundleArtifact],
     os_build_id: &str,
     initramfs_build_id: Option<&str>,
-) -> Result<serde_json::Value, String> {
+    release: Option<Release>,
+) -> Result<bundle::Bundle, String> {
     let update = manifest.update.as_ref();
 
+    // Determine slot identifiers based on update strategy
+    let strategy = manifest
+        .runtime
+        .update_strategy
+        .as_deref()
+        .unwrap_or("uboot-ab");
+    let slot_ids: Vec<&str> = match strategy {
+        "tegra-ab" => vec!["0", "1"],
+        _ => vec!["a", "b"],
+    };
+
     // Build the update.artifacts array for bundle.json
     let mut bundle_artifacts = Vec::new();
     for artifact in artifacts {
-        let mut artifact_entry = serde_json::json!({
-            "name": artifact.name,
-            "file": artifact.archive_path,
-            "sha256": artifact.sha256,
-            "size": artifact.size,
-        });
+        let chunks = artifact
+            .chunk_index
+            .as_ref()
+            .map(|chunk_index| bundle::ChunkIndexRef {
+                algorithm: chunk_index::CHUNK_ALGORITHM.to_string(),
+                avg_size: chunk_index.avg_size,
+                count: chunk_index.count as u64,
+                index: chunk_index.archive_path.clone(),
+                sha256: chunk_index.sha256.clone(),
+                size: chunk_index.size,
+            });
 
         // Add slot_targets from the manifest's os_artifacts
+        let mut slot_targets = std::collections::BTreeMap::new();
         if let Some(update) = update
             && let Some(os_artifact) = update.os_artifacts.get(&artifact.name)
         {
-            let slot_partitions = &os_artifact.slot_partitions;
-            let mut slot_targets = serde_json::Map::new();
-
-            // Determine slot identifiers based on update strategy
-            let strategy = manifest
-                .runtime
-                .update_strategy
-                .as_deref()
-                .unwrap_or("uboot-ab");
-            let slot_ids: Vec<&str> = match strategy {
-                "tegra-ab" => vec!["0", "1"],
-                _ => vec!["a", "b"],
-            };
-
-            for (idx, slot_id) in slot_ids.iter().enumerate() {
-                if let Some(partition) = slot_partitions.get(idx) {
-                    slot_targets.insert(
-                        slot_id.to_string(),
-                        serde_json::json!({ "partition": partition }),
-                    );
-                }
+            for (slot_id, partition) in slot_ids.iter().zip(&os_artifact.slot_partitions) {
+                let device = find_partition_device(manifest, &os_artifact.image_key, partition)
+                    .ok_or_else(|| {
+                        format!(
+                            "Slot partition '{partition}' of artifact '{}' is not defined on any storage device",
+                            artifact.name
+                        )
+                    })?;
+                slot_targets.insert(
+                    slot_id.to_string(),
+                    bundle::SlotTarget {
+                        device: device.to_string(),
+                        partition: partition.clone(),
+                    },
+                );
             }
-
-            artifact_entry["slot_targets"] = serde_json::Value::Object(slot_targets);
         }
 
-        bundle_artifacts.push(artifact_entry);
-    }
-
-    // Build the top-level bundle.json
-    let mut bundle = serde_json::json!({
-        "format_version": 1,
-        "platform": manifest.runtime.platform,
-        "architecture": manifest.runtime.architecture,
-        "os_build_id": os_build_id,
-    });
-
-    if let Some(initramfs_id) = initramfs_build_id {
-        bundle["initramfs_build_id"] = serde_json::json!(initramfs_id);
+        bundle_artifacts.push(bundle::BundleArtifact {
+            name: artifact.name.clone(),
+            file: artifact.archive_path.clone(),
+            sha256: artifact.sha256.clone(),
+            size: artifact.size,
+            slot_targets,
+            chunks,
+            delta: None,
+        });
     }
 
     // Add update section if manifest has one
-    if let Some(update) = update {
-        let strategy = manifest
-            .runtime
-            .update_strategy
-            .as_deref()
-            .unwrap_or("uboot-ab");
-
-        let mut update_section = serde_json::json!({
-            "strategy": strategy,
-            "slot_detection": serde_json::to_value(&update.slot_detection)
-                .map_err(|e| format!("Failed to serialize slot_detection: {e}"))?,
-            "artifacts": bundle_artifacts,
-            "activate": serde_json::to_value(update.activate.as_vec())
-                .map_err(|e| format!("Failed to serialize activate: {e}"))?,
-        });
+    let update_section = update.map(|update| bundle::BundleUpdate {
+        strategy: strategy.to_string(),
+        slot_detection: update.slot_detection.clone(),
+        artifacts: bundle_artifacts,
+        activate: update.activate.as_vec().into_iter().cloned().collect(),
+        rollback: update
+            .rollback
+            .as_ref()
+            .map(|rollback| rollback.as_vec().into_iter().cloned().collect()),
+    });
 
-        if let Some(rollback) = &update.rollback {
-            update_section["rollback"] = serde_json::to_value(rollback.as_vec())
-                .map_err(|e| format!("Failed to serialize rollback: {e}"))?;
+    // Add layout section: one entry per storage device with partitions, keyed by device name
+    // Offsets and sizes are in bytes, placed exactly as provision places them
+    let mut layout = std::collections::BTreeMap::new();
+    for (device_name, device) in &manifest.storage_devices {
+        if device.partitions.is_empty() {
+            continue;
         }
-
-        bundle["update"] = update_section;
+        let partitions = device
+            .partition_layout()
+            .map_err(|e| format!("Storage device '{device_name}': {e}"))?
+            .into_iter()
+            .map(|placed| bundle::LayoutPartition {
+                name: placed.partition.name.clone(),
+                offset: placed.offset,
+                offset_unit: SizeUnit::Bytes,
+                size: placed.size,
+                size_unit: SizeUnit::Bytes,
+                expand: placed.partition.expand.clone(),
+            })
+            .collect();
+
+        layout.insert(
+            device_name.clone(),
+            bundle::DeviceLayout {
+                devpath: device.devpath.clone(),
+                block_size: device.block_size,
+                partitions,
+            },
+        );
     }
 
-    // Add layout section from storage_devices partitions
-    // Compute sequential offsets for partitions that don't have explicit ones
-    for device in manifest.storage_devices.values() {
-        if !device.partitions.is_empty() {
-            let mut cursor_bytes: u64 = 0;
-            let partitions: Vec<serde_json::Value> = device
+    Ok(bundle::Bundle {
+        format_version: bundle::FORMAT_VERSION,
+        platform: manifest.runtime.platform.clone(),
+        architecture: manifest.runtime.architecture.clone(),
+        os_build_id: os_build_id.to_string(),
+        initramfs_build_id: initramfs_build_id.map(str::to_string),
+        release,
+        update: update_section,
+        layout,
+        // Add verify section
+        verify: (!os_build_id.is_empty()).then(|| bundle::OsReleaseCheck {
+            kind: "os-release".to_string(),
+            field: "AVOCADO_OS_BUILD_ID".to_string(),
+            path: None,
+            expected: os_build_id.to_string(),
+        }),
+        // Add initramfs verify section
+        verify_initramfs: initramfs_build_id.map(|initramfs_id| bundle::OsReleaseCheck {
+            kind: "os-release".to_string(),
+            field: "AVOCADO_OS_BUILD_ID".to_string(),
+            path: Some("/etc/os-release-initrd".to_string()),
+            expected: initramfs_id.to_string(),
+        }),
+        delta: None,
+    })
+}
+
+/// Find the storage device that defines `partition`.
+/// When several devices share the partition name, the one holding `image_key` wins,
+/// then the first by device name.
+fn find_partition_device<'a>(
+    manifest: &'a Manifest,
+    image_key: &str,
+    partition: &str,
+) -> Option<&'a str> {
+    let mut candidates: Vec<_> = manifest
+        .storage_devices
+        .iter()
+        .filter(|(_, device)| {
+            device
                 .partitions
                 .iter()
-                .map(|p| {
-                    let mut part = serde_json::json!({});
-                    if let Some(name) = &p.name {
-                        part["name"] = serde_json::json!(name);
-                    }
-                    part["size"] = serde_json::json!(p.size);
-                    part["size_unit"] = serde_json::json!(p.size_unit);
-
-                    // Use explicit offset if provided, otherwise use sequential cursor
-                    let offset_bytes = if let Some(offset) = p.offset {
-                        let unit = p.offset_unit.as_deref();
-                        to_bytes(offset as u64, unit)
-                    } else {
-                        cursor_bytes
-                    };
-                    part["offset"] = serde_json::json!(offset_bytes);
-                    part["offset_unit"] = serde_json::json!("bytes");
-
-                    // Advance cursor past this partition
-                    let size_bytes = to_bytes(p.size as u64, Some(&p.size_unit));
-                    cursor_bytes = offset_bytes + size_bytes;
-
-                    if let Some(expand) = &p.expand {
-                        part["expand"] = serde_json::json!(expand);
-                    }
-                    part
-                })
-                .collect();
-
-            bundle["layout"] = serde_json::json!({
-                "device": device.devpath,
-                "partitions": partitions,
-            });
-
-            if let Some(block_size) = device.block_size {
-                bundle["layout"]["block_size"] = serde_json::json!(block_size);
-            }
-
-            // Only include the first device's layout
-            break;
-        }
-    }
-
-    // Add verify section
-    if !os_build_id.is_empty() {
-        bundle["verify"] = serde_json::json!({
-            "type": "os-release",
-            "field": "AVOCADO_OS_BUILD_ID",
-            "expected": os_build_id,
-        });
-    }
-
-    // Add initramfs verify section
-    if let Some(initramfs_id) = initramfs_build_id {
-        bundle["verify_initramfs"] = serde_json::json!({
-            "type": "os-release",
-            "field": "AVOCADO_OS_BUILD_ID",
-            "path": "/etc/os-release-initrd",
-            "expected": initramfs_id,
-        });
-    }
-
-    Ok(bundle)
+                .any(|p| p.name.as_deref() == Some(partition))
+        })
+        .collect();
+    candidates.sort_by_key(|(name, device)| (!device.images.contains_key(image_key), *name));
+    candidates.first().map(|(name, _)| name.as_str())
 }
 
-/// Convert a size value to bytes based on its unit.
-fn to_bytes(value: u64, unit: Option<&str>) -> u64 {
-    match unit {
-        Some("tebibytes") => value * 1024 * 1024 * 1024 * 1024,
-        Some("gibibytes") => value * 1024 * 1024 * 1024,
-        Some("mebibytes") => value * 1024 * 1024,
-        Some("kibibytes") => value * 1024,
-        Some("terabytes") => value * 1_000_000_000_000,
-        Some("gigabytes") => value * 1_000_000_000,
-        Some("megabytes") => value * 1_000_000,
-        Some("kilobytes") => value * 1_000,
-        Some("bytes") | None => value,
-        _ => value,
+/// Read SOURCE_DATE_EPOCH, the timestamp used for reproducible builds.
+/// Defaults to 0 (the Unix epoch) when unset.
+pub fn source_date_epoch() -> Result<u64, String> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse().map_err(|_| {
+            format!("Invalid SOURCE_DATE_EPOCH '{value}': expected seconds since the Unix epoch.")
+        }),
+        _ => Ok(0),
     }
 }
 
-/// Package everything into a .aos tar.zst archive
-fn package_aos(
+/// Package everything into a .aos archive (tar.zst, or plain tar with `Compression::None`).
+///
+/// Entries are written in the given order with normalized headers (mode 0644,
+/// uid/gid 0, no owner names, mtime `timestamp`), so identical inputs produce
+/// identical archives. Multithreaded zstd output is reproducible for a fixed
+/// thread count, but differs from single-threaded output.
+pub fn package_aos(
     output_path: &Path,
     bundle_json_path: &Path,
+    signature_path: Option<&Path>,
     artifacts: &[BundleArtifact],
+    timestamp: u64,
+    compression: &CompressionArgs,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     // Create output directory if needed
     if let Some(parent) = output_path.parent() {
         fs::create_dir_all(parent).map_err(|e| {
-            format!(
+            Error::Io(format!(
                 "Failed to create output directory '{}': {}",
                 parent.display(),
                 e
-            )
+            ))
         })?;
     }
 
     let output_file = fs::File::create(output_path).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to create output file '{}': {}",
             output_path.display(),
             e
-        )
+        ))
     })?;
+    let entries = ArchiveEntries {
+        bundle_json_path,
+        signature_path,
+        artifacts,
+        timestamp,
+        verbose,
+    };
 
-    let zst_encoder = zstd::Encoder::new(output_file, 3)
-        .map_err(|e| format!("Failed to create zstd encoder: {e}"))?;
+    match compression.compression {
+        Compression::None => {
+            if compression.long || compression.threads > 0 {
+                log_warning("--threads and --long have no effect with --compression none.");
+            }
+            let mut tar_builder = tar::Builder::new(BufWriter::new(output_file));
+            entries.append_to(&mut tar_builder)?;
+            tar_builder
+                .into_inner()
+                .and_then(|mut writer| writer.flush())
+                .map_err(|e| Error::Io(format!("Failed to finalize tar archive: {e}")))?;
+        }
+        Compression::Zstd => {
+            let mut zst_encoder = zstd::Encoder::new(output_file, compression.level)
+                .map_err(|e| format!("Failed to create zstd encoder: {e}"))?;
+            if compression.threads > 0 {
+                zst_encoder
+                    .multithread(compression.threads)
+                    .map_err(|e| format!("Failed to enable zstd multithreading: {e}"))?;
+            }
+            if compression.long {
+                zst_encoder
+                    .long_distance_matching(true)
+                    .and_then(|_| zst_encoder.window_log(LONG_WINDOW_LOG))
+                    .map_err(|e| format!("Failed to enable zstd long-distance matching: {e}"))?;
+            }
 
-    let mut tar_builder = tar::Builder::new(zst_encoder);
+            let mut tar_builder = tar::Builder::new(zst_encoder);
+            entries.append_to(&mut tar_builder)?;
 
-    // Add bundle.json at the root
-    if verbose {
-        log_debug("Adding bundle.json to archive.");
+            // Finish the tar, then finish zstd
+            let zst_encoder = tar_builder
+                .into_inner()
+                .map_err(|e| Error::Io(format!("Failed to finalize tar archive: {e}")))?;
+            zst_encoder
+                .finish()
+                .map_err(|e| Error::Io(format!("Failed to finalize zstd compression: {e}")))?;
+        }
     }
-    tar_builder
-        .append_path_with_name(bundle_json_path, "bundle.json")
-        .map_err(|e| format!("Failed to add bundle.json to archive: {e}"))?;
 
-    // Add each artifact
-    for artifact in artifacts {
-        if verbose {
-            log_debug(&format!(
-                "Adding {} -> {}",
-                artifact.path.display(),
-                artifact.archive_path
-            ));
+    Ok(())
+}
+
+/// The files making up a .aos archive, in archive order
+struct ArchiveEntries<'a> {
+    bundle_json_path: &'a Path,
+    signature_path: Option<&'a Path>,
+    artifacts: &'a [BundleArtifact],
+    timestamp: u64,
+    verbose: bool,
+}
+
+impl ArchiveEntries<'_> {
+    fn append_to<W: Write>(&self, tar_builder: &mut tar::Builder<W>) -> Result<(), Error> {
+        // Add bundle.json at the root
+        if self.verbose {
+            log_debug("Adding bundle.json to archive.");
         }
-        tar_builder
-            .append_path_with_name(&artifact.path, &artifact.archive_path)
+        append_normalized(
+            tar_builder,
+            self.bundle_json_path,
+            "bundle.json",
+            self.timestamp,
+        )
+        .map_err(|e| Error::Io(format!("Failed to add bundle.json to archive: {e}")))?;
+
+        // Add the detached signature right after bundle.json
+        if let Some(signature_path) = self.signature_path {
+            if self.verbose {
+                log_debug(&format!("Adding {} to archive.", signing::SIGNATURE_ENTRY));
+            }
+            append_normalized(
+                tar_builder,
+                signature_path,
+                signing::SIGNATURE_ENTRY,
+                self.timestamp,
+            )
             .map_err(|e| {
-                format!(
-                    "Failed to add '{}' to archive: {}",
-                    artifact.archive_path, e
-                )
+                Error::Io(format!(
+                    "Failed to add bundle.json signature to archive: {e}"
+                ))
             })?;
-    }
-
-    // Finish the tar, then finish zstd
-    let zst_encoder = tar_builder
-        .into_inner()
-        .map_err(|e| format!("Failed to finalize tar archive: {e}"))?;
-    zst_encoder
-        .finish()
-        .map_err(|e| format!("Failed to finalize zstd compression: {e}"))?;
+        }
 
-    Ok(())
-}
+        // Chunk indexes go before the artifacts so a streaming reader sees them first
+        for chunk_index in self.artifacts.iter().filter_map(|a| a.chunk_index.as_ref()) {
+            if self.verbose {
+                log_debug(&format!(
+                    "Adding {} -> {}",
+                    chunk_index.path.display(),
+                    chunk_index.archive_path
+                ));
+            }
+            append_normalized(
+                tar_builder,
+                &chunk_index.path,
+                &chunk_index.archive_path,
+                self.timestamp,
+            )
+            .map_err(|e| {
+                Error::Io(format!(
+                    "Failed to add '{}' to archive: {}",
+                    chunk_index.archive_path, e
+                ))
+            })?;
+        }
 
-/// Convert size value to mebibytes based on unit string
-fn convert_size_to_mb(size: i64, size_unit: &str) -> Result<u64, String> {
-    let size_mb = match size_unit.to_lowercase().as_str() {
-        "bytes" | "byte" | "b" => size as f64 / (1024.0 * 1024.0),
-        "kilobytes" | "kilobyte" | "kb" => size as f64 / 1024.0,
-        "kibibytes" | "kibibyte" | "kib" => size as f64 / 1024.0,
-        "megabytes" | "megabyte" | "mb" => size as f64,
-        "mebibytes" | "mebibyte" | "mib" => size as f64,
-        "gigabytes" | "gigabyte" | "gb" => size as f64 * 1024.0,
-        "gibibytes" | "gibibyte" | "gib" => size as f64 * 1024.0,
-        _ => return Err(format!("Unsupported size unit: {size_unit}")),
-    };
+        // Add each artifact
+        for artifact in self.artifacts {
+            if self.verbose {
+                log_debug(&format!(
+                    "Adding {} -> {}",
+                    artifact.path.display(),
+                    artifact.archive_path
+                ));
+            }
+            append_normalized(
+                tar_builder,
+                &artifact.path,
+                &artifact.archive_path,
+                self.timestamp,
+            )
+            .map_err(|e| {
+                Error::Io(format!(
+                    "Failed to add '{}' to archive: {}",
+                    artifact.archive_path, e
+                ))
+            })?;
+        }
 
-    if size_mb <= 0.0 {
-        return Err("Image size must be positive".to_string());
+        Ok(())
     }
+}
 
-    Ok(size_mb.ceil() as u64)
+/// Append a regular file with a header that carries nothing from the host
+fn append_normalized<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    archive_path: &str,
+    timestamp: u64,
+) -> std::io::Result<()> {
+    let file = fs::File::open(path)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(file.metadata()?.len());
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(timestamp);
+    builder.append_data(&mut header, archive_path, file)
 }
 
 /// Resolve file paths for FAT manifest entries
 fn create_fat_manifest_with_resolved_paths(
     files: &[FileEntry],
     input_dirs: &[PathBuf],
-) -> Result<fat::Manifest, String> {
+) -> Result<fat::Manifest, Error> {
     let mut fat_files = Vec::new();
 
     for entry in files {
@@ -821,7 +1271,9 @@ fn create_fat_manifest_with_resolved_paths(
         };
 
         let resolved_path = find_file_in_dirs(input_filename, input_dirs).ok_or_else(|| {
-            format!("File '{input_filename}' not found in any input directory for FAT image")
+            Error::MissingInput(format!(
+                "File '{input_filename}' not found in any input directory for FAT image"
+            ))
         })?;
 
         fat_files.push(fat::FileEntry {
@@ -836,9 +1288,12 @@ fn create_fat_manifest_with_resolved_paths(
     })
 }
 
-fn copy_path(input_path: &Path, output_path: &Path, verbose: bool) -> Result<(), String> {
+fn copy_path(input_path: &Path, output_path: &Path, verbose: bool) -> Result<(), Error> {
     if !input_path.exists() {
-        return Err(format!("Input path '{}' not found.", input_path.display()));
+        return Err(Error::MissingInput(format!(
+            "Input path '{}' not found.",
+            input_path.display()
+        )));
     }
 
     if input_path.is_dir() {
@@ -848,20 +1303,25 @@ fn copy_path(input_path: &Path, output_path: &Path, verbose: bool) -> Result<(),
     }
 }
 
-fn copy_directory(input_dir: &Path, output_dir: &Path, verbose: bool) -> Result<(), String> {
+fn copy_directory(input_dir: &Path, output_dir: &Path, verbose: bool) -> Result<(), Error> {
     fs::create_dir_all(output_dir).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to create directory '{}': {}",
             output_dir.display(),
             e
-        )
+        ))
     })?;
 
-    let entries = fs::read_dir(input_dir)
-        .map_err(|e| format!("Failed to read directory '{}': {}", input_dir.display(), e))?;
+    let entries = fs::read_dir(input_dir).map_err(|e| {
+        Error::Io(format!(
+            "Failed to read directory '{}': {}",
+            input_dir.display(),
+            e
+        ))
+    })?;
 
     for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {e}"))?;
+        let entry = entry.map_err(|e| Error::Io(format!("Failed to read directory entry: {e}")))?;
         let input_child = entry.path();
         let output_child = output_dir.join(entry.file_name());
 
@@ -875,23 +1335,31 @@ fn copy_directory(input_dir: &Path, output_dir: &Path, verbose: bool) -> Result<
     Ok(())
 }
 
-fn copy_file(input_path: &Path, output_path: &Path, verbose: bool) -> Result<(), String> {
+fn copy_file(input_path: &Path, output_path: &Path, verbose: bool) -> Result<(), Error> {
     if !input_path.exists() {
-        return Err(format!("Input file '{}' not found.", input_path.display()));
+        return Err(Error::MissingInput(format!(
+            "Input file '{}' not found.",
+            input_path.display()
+        )));
     }
 
     if let Some(parent) = output_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory '{}': {}", parent.display(), e))?;
+        fs::create_dir_all(parent).map_err(|e| {
+            Error::Io(format!(
+                "Failed to create directory '{}': {}",
+                parent.display(),
+                e
+            ))
+        })?;
     }
 
     fs::copy(input_path, output_path).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to copy '{}' to '{}': {}",
             input_path.display(),
             output_path.display(),
             e
-        )
+        ))
     })?;
 
     if verbose {
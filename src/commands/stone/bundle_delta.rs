@@ -0,0 +1,459 @@
+use super::bundle::{
+    BundleArtifact, ChunkIndexFile, CompressionArgs, package_aos, sha256_file, source_date_epoch,
+};
+use super::inspect_bundle::{
+    MAX_WINDOW_LOG, open_bundle_archive, read_bundle, verify_bundle_entries,
+};
+use crate::bundle::{self, Bundle};
+use crate::error::Error;
+use crate::log::*;
+use crate::signing;
+use clap::Args;
+
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// zstd compression level used for binary patches
+const PATCH_COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Args, Debug)]
+pub struct BundleDeltaArgs {
+    /// Path to the base .aos bundle already installed on the device
+    #[arg(long = "from", value_name = "PATH")]
+    pub from: PathBuf,
+
+    /// Path to the new .aos bundle to produce patches for
+    #[arg(long = "to", value_name = "PATH")]
+    pub to: PathBuf,
+
+    /// Path to the output delta .aos bundle file
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "PATH",
+        default_value = "os-bundle-delta.aos"
+    )]
+    pub output: PathBuf,
+
+    /// Directory for intermediate build artifacts
+    #[arg(long = "build-dir", value_name = "DIR")]
+    pub build_dir: Option<PathBuf>,
+
+    /// Ed25519 private key used to sign bundle.json (PKCS#8 PEM, hex or raw seed)
+    #[arg(long = "signing-key", value_name = "PATH")]
+    pub signing_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub compression: CompressionArgs,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+impl BundleDeltaArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        bundle_delta_command(
+            &self.from,
+            &self.to,
+            &self.output,
+            self.build_dir.as_deref(),
+            self.signing_key.as_deref(),
+            &self.compression,
+            self.verbose,
+        )
+    }
+}
+
+pub fn bundle_delta_command(
+    from_path: &Path,
+    to_path: &Path,
+    output_path: &Path,
+    build_dir_override: Option<&Path>,
+    signing_key_path: Option<&Path>,
+    compression: &CompressionArgs,
+    verbose: bool,
+) -> Result<(), Error> {
+    let timestamp = source_date_epoch()?;
+    let signing_key = signing_key_path
+        .map(signing::load_signing_key)
+        .transpose()?;
+
+    // Determine build directory
+    let default_build_dir = output_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("_build_delta");
+    let build_dir = build_dir_override.unwrap_or(&default_build_dir);
+
+    log_info(&format!(
+        "Building delta OS bundle.\n  From:       {}\n  To:         {}\n  Build dir:  {}\n  Output:     {}",
+        from_path.display(),
+        to_path.display(),
+        build_dir.display(),
+        output_path.display()
+    ));
+
+    let from_dir = build_dir.join("from");
+    let to_dir = build_dir.join("to");
+    let patches_dir = build_dir.join("images");
+
+    // Clear anything left over from a previous run so stale images are never packaged
+    for dir in [&from_dir, &to_dir, &patches_dir] {
+        if dir.exists() {
+            fs::remove_dir_all(dir).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to clean directory '{}': {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+        }
+    }
+
+    let from_bundle = unpack_verified_bundle(from_path, &from_dir, verbose)?;
+    let mut to_bundle = unpack_verified_bundle(to_path, &to_dir, verbose)?;
+    fs::create_dir_all(&patches_dir).map_err(|e| {
+        Error::Io(format!(
+            "Failed to create images directory '{}': {}",
+            patches_dir.display(),
+            e
+        ))
+    })?;
+
+    if from_bundle.platform != to_bundle.platform {
+        return Err(Error::Other(format!(
+            "Cannot build a delta between platforms \"{}\" and \"{}\".",
+            from_bundle.platform, to_bundle.platform
+        )));
+    }
+
+    let from_artifacts = from_bundle.artifacts();
+
+    let mut artifacts = Vec::new();
+    let to_artifacts = to_bundle
+        .update
+        .as_mut()
+        .map(|u| &mut u.artifacts)
+        .ok_or_else(|| format!("Bundle '{}' has no update artifacts.", to_path.display()))?;
+
+    for artifact in to_artifacts.iter_mut() {
+        let name = artifact.name.clone();
+        let file = artifact.file.clone();
+        let target_path = to_dir.join(&file);
+
+        let base = from_artifacts.iter().find(|a| a.name == name);
+
+        let Some(base) = base else {
+            log_info(&format!(
+                "Artifact '{name}' has no base in '{}'; including the full image.",
+                from_path.display()
+            ));
+            artifacts.push(BundleArtifact {
+                name,
+                path: target_path,
+                archive_path: file,
+                sha256: artifact.sha256.clone(),
+                size: artifact.size,
+                chunk_index: carried_chunk_index(artifact, &to_dir),
+            });
+            continue;
+        };
+
+        let base_path = from_dir.join(&base.file);
+        let patch_archive_path = format!("{file}.zstpatch");
+        let patch_path = build_dir.join(&patch_archive_path);
+
+        log_info(&format!("Creating patch for artifact '{name}'."));
+        let base_image = map_base_image(&base_path)?;
+        create_patch(&base_image, &base_path, &target_path, &patch_path)?;
+
+        let target_sha256 = artifact.sha256.clone();
+        let reconstructed_sha256 = sha256_patched(&base_image, &patch_path)?;
+        if reconstructed_sha256 != target_sha256 {
+            return Err(Error::Other(format!(
+                "Patch for artifact '{name}' does not reconstruct the target image (expected sha256 {target_sha256}, got {reconstructed_sha256})"
+            )));
+        }
+
+        let patch_sha256 = sha256_file(&patch_path)?;
+        let patch_size = fs::metadata(&patch_path).map(|m| m.len()).map_err(|e| {
+            Error::Io(format!(
+                "Failed to get size of '{}': {e}",
+                patch_path.display()
+            ))
+        })?;
+
+        if verbose {
+            log_debug(&format!(
+                "Artifact '{name}': {patch_archive_path} ({patch_size} bytes, target {} bytes)",
+                artifact.size
+            ));
+        }
+
+        artifact.delta = Some(bundle::ArtifactDelta {
+            kind: "zstd-patch".to_string(),
+            base_sha256: base.sha256.clone(),
+            base_size: base.size,
+            target_sha256,
+            target_size: artifact.size,
+        });
+        // The chunk index describes the full target image, not the patch
+        artifact.chunks = None;
+        artifact.file = patch_archive_path.clone();
+        artifact.sha256 = patch_sha256.clone();
+        artifact.size = patch_size;
+
+        artifacts.push(BundleArtifact {
+            name,
+            path: patch_path,
+            archive_path: patch_archive_path,
+            sha256: patch_sha256,
+            size: patch_size,
+            chunk_index: None,
+        });
+    }
+
+    to_bundle.delta = Some(bundle::BundleDelta {
+        base_os_build_id: from_bundle.os_build_id.clone(),
+    });
+    // Older bundles are read into the current model, which is what gets written
+    to_bundle.format_version = bundle::FORMAT_VERSION;
+
+    let bundle_json_path = build_dir.join("bundle.json");
+    let bundle_json_str = to_bundle.to_json_pretty()?;
+    fs::write(&bundle_json_path, &bundle_json_str)
+        .map_err(|e| Error::Io(format!("Failed to write bundle.json: {e}")))?;
+
+    if verbose {
+        log_debug(&format!("Generated bundle.json:\n{bundle_json_str}"));
+    }
+
+    let signature_path = if let Some(key) = &signing_key {
+        let signature_path = build_dir.join(signing::SIGNATURE_ENTRY);
+        fs::write(
+            &signature_path,
+            signing::sign(key, bundle_json_str.as_bytes()),
+        )
+        .map_err(|e| Error::Io(format!("Failed to write bundle.json signature: {e}")))?;
+        log_info("Signed bundle.json.");
+        Some(signature_path)
+    } else {
+        None
+    };
+
+    package_aos(
+        output_path,
+        &bundle_json_path,
+        signature_path.as_deref(),
+        &artifacts,
+        timestamp,
+        compression,
+        verbose,
+    )?;
+
+    log_success(&format!(
+        "Delta OS bundle created: {}",
+        output_path.display()
+    ));
+    Ok(())
+}
+
+/// Verify a bundle's artifact hashes, then unpack it into `dest`.
+/// Returns the parsed bundle.json.
+fn unpack_verified_bundle(bundle_path: &Path, dest: &Path, verbose: bool) -> Result<Bundle, Error> {
+    if !bundle_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Bundle file '{}' not found.",
+            bundle_path.display()
+        )));
+    }
+
+    let contents = read_bundle(bundle_path, verbose)?;
+    let bundle = Bundle::from_slice(&contents.bundle_json)?;
+    let problems = verify_bundle_entries(&bundle, &contents.entries);
+    if !problems.is_empty() {
+        return Err(Error::Other(format!(
+            "Bundle '{}' failed verification:\n  {}",
+            bundle_path.display(),
+            problems.join("\n  ")
+        )));
+    }
+    if bundle.delta.is_some() {
+        return Err(Error::Other(format!(
+            "Bundle '{}' is itself a delta bundle; deltas must be built from full bundles.",
+            bundle_path.display()
+        )));
+    }
+
+    open_bundle_archive(bundle_path)?
+        .unpack(dest)
+        .map_err(|e| {
+            Error::Io(format!(
+                "Failed to unpack bundle '{}' to '{}': {}",
+                bundle_path.display(),
+                dest.display(),
+                e
+            ))
+        })?;
+
+    Ok(bundle)
+}
+
+/// Keep the chunk index of an artifact that is included in full
+fn carried_chunk_index(
+    artifact: &bundle::BundleArtifact,
+    unpacked_dir: &Path,
+) -> Option<ChunkIndexFile> {
+    let chunks = artifact.chunks.as_ref()?;
+    Some(ChunkIndexFile {
+        path: unpacked_dir.join(&chunks.index),
+        archive_path: chunks.index.clone(),
+        sha256: chunks.sha256.clone(),
+        size: chunks.size,
+        avg_size: chunks.avg_size,
+        count: chunks.count as usize,
+    })
+}
+
+/// Window log large enough for zstd to reference anywhere in the base image,
+/// or None when that takes a larger window than zstd supports
+fn patch_window_log(base_size: u64, target_size: u64) -> Option<u32> {
+    let largest = base_size.max(target_size).max(1);
+    let window_log = (u64::BITS - largest.leading_zeros() + 1).max(10);
+    (window_log <= MAX_WINDOW_LOG).then_some(window_log)
+}
+
+/// Map the base image into memory, so creating and checking its patch share
+/// one view of it without reading it in full
+fn map_base_image(base_path: &Path) -> Result<memmap2::Mmap, Error> {
+    let io_error = |e: std::io::Error| {
+        Error::Io(format!(
+            "Failed to read base image '{}': {}",
+            base_path.display(),
+            e
+        ))
+    };
+    let file = fs::File::open(base_path).map_err(io_error)?;
+    // SAFETY: the base image was unpacked into the build directory by this
+    // command, and nothing modifies it while the patch is made
+    unsafe { memmap2::Mmap::map(&file) }.map_err(io_error)
+}
+
+/// Compress `target` using `base` as a reference prefix (zstd `--patch-from`)
+fn create_patch(
+    base: &[u8],
+    base_path: &Path,
+    target_path: &Path,
+    patch_path: &Path,
+) -> Result<(), Error> {
+    let mut target = fs::File::open(target_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to open target image '{}': {}",
+            target_path.display(),
+            e
+        ))
+    })?;
+    let target_size = target.metadata().map(|m| m.len()).map_err(|e| {
+        Error::Io(format!(
+            "Failed to get size of '{}': {e}",
+            target_path.display()
+        ))
+    })?;
+
+    if let Some(parent) = patch_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            Error::Io(format!(
+                "Failed to create directory '{}': {}",
+                parent.display(),
+                e
+            ))
+        })?;
+    }
+    let output = fs::File::create(patch_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to create patch '{}': {}",
+            patch_path.display(),
+            e
+        ))
+    })?;
+
+    let window_log = patch_window_log(base.len() as u64, target_size).unwrap_or_else(|| {
+        log_warning(&format!(
+            "Base image '{}' is larger than the {} GiB zstd window; the patch cannot reference all of it and may be much larger.",
+            base_path.display(),
+            1u64 << (MAX_WINDOW_LOG - 30)
+        ));
+        MAX_WINDOW_LOG
+    });
+
+    let mut encoder =
+        zstd::Encoder::with_ref_prefix(BufWriter::new(output), PATCH_COMPRESSION_LEVEL, base)
+            .map_err(|e| format!("Failed to create zstd patch encoder: {e}"))?;
+    encoder
+        .window_log(window_log)
+        .and_then(|_| encoder.long_distance_matching(true))
+        .and_then(|_| encoder.set_pledged_src_size(Some(target_size)))
+        .map_err(|e| format!("Failed to configure zstd patch encoder: {e}"))?;
+
+    std::io::copy(&mut target, &mut encoder).map_err(|e| {
+        Error::Io(format!(
+            "Failed to create patch for '{}': {}",
+            target_path.display(),
+            e
+        ))
+    })?;
+    encoder.finish().map_err(|e| {
+        Error::Io(format!(
+            "Failed to finalize patch '{}': {}",
+            patch_path.display(),
+            e
+        ))
+    })?;
+
+    Ok(())
+}
+
+/// Apply `patch` to `base` and hash the reconstructed image without writing it out
+fn sha256_patched(base: &[u8], patch_path: &Path) -> Result<String, Error> {
+    use sha2::{Digest, Sha256};
+
+    let patch = fs::File::open(patch_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to open patch '{}': {}",
+            patch_path.display(),
+            e
+        ))
+    })?;
+
+    let mut decoder = zstd::Decoder::with_ref_prefix(BufReader::new(patch), base)
+        .map_err(|e| format!("Failed to create zstd patch decoder: {e}"))?;
+    decoder
+        .window_log_max(MAX_WINDOW_LOG)
+        .map_err(|e| format!("Failed to configure zstd patch decoder: {e}"))?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut decoder, &mut hasher).map_err(|e| {
+        Error::Io(format!(
+            "Failed to apply patch '{}': {}",
+            patch_path.display(),
+            e
+        ))
+    })?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_window_log() {
+        assert_eq!(patch_window_log(0, 0), Some(10));
+        assert_eq!(patch_window_log(200_000, 100), Some(19));
+        assert_eq!(patch_window_log(1 << 29, 1 << 29), Some(31));
+        assert_eq!(patch_window_log(1 << 30, 1 << 30), None);
+        assert_eq!(patch_window_log(u64::MAX, 0), None);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::bundle::Bundle;
+use crate::error::Error;
+use crate::log::*;
+use clap::Args;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct BundleSchemaArgs {
+    /// Write the schema to this file instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+impl BundleSchemaArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        bundle_schema_command(self.output.as_deref())
+    }
+}
+
+pub fn bundle_schema_command(output_path: Option<&Path>) -> Result<(), Error> {
+    let schema = serde_json::to_string_pretty(&Bundle::json_schema())
+        .map_err(|e| format!("Failed to serialize bundle.json schema: {e}"))?;
+
+    match output_path {
+        Some(path) => {
+            fs::write(path, format!("{schema}\n")).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write schema to '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            log_success(&format!(
+                "Wrote bundle.json schema to '{}'.",
+                path.display()
+            ));
+        }
+        None => println!("{schema}"),
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
+use crate::error::Error;
 use crate::log::*;
-use crate::manifest::Manifest;
+use crate::manifest::{Manifest, ManifestFormat, ManifestOptions, parse_var};
 use clap::Args;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -15,6 +16,14 @@ pub struct CreateArgs {
     )]
     pub manifest: PathBuf,
 
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Value for a ${NAME} reference in the manifest (can be specified multiple times)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
     /// Path to the OS release file to include
     #[arg(long = "os-release", value_name = "PATH")]
     pub os_release: PathBuf,
@@ -43,9 +52,10 @@ pub struct CreateArgs {
 }
 
 impl CreateArgs {
-    pub fn execute(&self) -> Result<(), String> {
+    pub fn execute(&self) -> Result<(), Error> {
         create_command(
             &self.manifest,
+            &ManifestOptions::new(self.format, &self.vars),
             &self.os_release,
             &self.input_dirs,
             &self.output_dir,
@@ -67,36 +77,37 @@ fn find_file_in_dirs(filename: &str, input_dirs: &[PathBuf]) -> Option<PathBuf>
 
 pub fn create_command(
     manifest_path: &Path,
+    manifest_options: &ManifestOptions,
     os_release_path: &Path,
     input_dirs: &[PathBuf],
     output_dir: &PathBuf,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     // Check if manifest file exists
     if !manifest_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "Manifest file '{}' not found.",
             manifest_path.display()
-        ));
+        )));
     }
 
     // Check if OS release file exists
     if !os_release_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "OS release file '{}' not found.",
             os_release_path.display()
-        ));
+        )));
     }
 
-    let manifest = Manifest::from_file(manifest_path)?;
+    let manifest = Manifest::load(manifest_path, manifest_options)?;
 
     // Ensure output directory exists
     if let Err(e) = fs::create_dir_all(output_dir) {
-        return Err(format!(
+        return Err(Error::Io(format!(
             "Failed to create output directory '{}': {}",
             output_dir.display(),
             e
-        ));
+        )));
     }
 
     // Create all files referenced in the manifest
@@ -114,15 +125,15 @@ pub fn create_command(
                 Some(input_path) => {
                     let output_path = output_dir.join(template);
                     if let Err(e) = copy_file(&input_path, &output_path, verbose) {
-                        errors.push(format!(
+                        errors.push(Error::Io(format!(
                             "Failed to copy fwup template '{template}' for device '{device_name}': {e}"
-                        ));
+                        )));
                     }
                 }
                 None => {
-                    errors.push(format!(
+                    errors.push(Error::MissingInput(format!(
                         "fwup template '{template}' for device '{device_name}' not found in any input directory"
-                    ));
+                    )));
                 }
             }
         }
@@ -137,9 +148,9 @@ pub fn create_command(
                 output_dir,
                 verbose,
             ) {
-                errors.push(format!(
-                    "Failed to process image '{image_name}' in device '{device_name}': {e}"
-                ));
+                errors.push(e.context(&format!(
+                    "Failed to process image '{image_name}' in device '{device_name}'"
+                )));
             }
         }
     }
@@ -150,15 +161,15 @@ pub fn create_command(
             Some(provision_input_path) => {
                 let provision_output_path = output_dir.join(provision_file);
                 if let Err(e) = copy_file(&provision_input_path, &provision_output_path, verbose) {
-                    errors.push(format!(
+                    errors.push(Error::Io(format!(
                         "Failed to copy provision file '{provision_file}': {e}"
-                    ));
+                    )));
                 }
             }
             None => {
-                errors.push(format!(
+                errors.push(Error::MissingInput(format!(
                     "Provision file '{provision_file}' not found in any input directory"
-                ));
+                )));
             }
         }
     }
@@ -170,47 +181,50 @@ pub fn create_command(
                 Some(script_input_path) => {
                     let script_output_path = output_dir.join(&profile.script);
                     if let Err(e) = copy_file(&script_input_path, &script_output_path, verbose) {
-                        errors.push(format!(
+                        errors.push(Error::Io(format!(
                             "Failed to copy provision profile script '{}' for profile '{profile_name}': {e}",
                             profile.script
-                        ));
+                        )));
                     }
                 }
                 None => {
-                    errors.push(format!(
+                    errors.push(Error::MissingInput(format!(
                         "Failed to copy provision profile script '{}' for profile '{profile_name}': not found in any input directory",
                         profile.script
-                    ));
+                    )));
                 }
             }
         }
     }
 
-    // Copy the manifest file to the output directory as manifest.json
+    // Copy the manifest file to the output directory as manifest.json, converting it to JSON
     let manifest_output_path = output_dir.join("manifest.json");
-    if let Err(e) = copy_file(manifest_path, &manifest_output_path, verbose) {
-        errors.push(format!(
+    if let Err(e) = Manifest::copy_as_json(manifest_path, manifest_options, &manifest_output_path) {
+        errors.push(Error::Io(format!(
             "Failed to copy manifest file '{}': {e}",
             manifest_path.display()
-        ));
+        )));
     }
 
     // Copy the OS release file to the output directory as os-release
     let os_release_output_path = output_dir.join("os-release");
     if let Err(e) = copy_file(os_release_path, &os_release_output_path, verbose) {
-        errors.push(format!(
+        errors.push(Error::Io(format!(
             "Failed to copy OS release file '{}': {e}",
             os_release_path.display()
-        ));
+        )));
     }
 
-    // Report errors
+    // Report errors, as missing inputs when that is all they are
     if !errors.is_empty() {
         let mut error_msg = String::from("Create failed with the following errors:");
-        for error in errors {
+        for error in &errors {
             error_msg.push_str(&format!("\n  - {error}"));
         }
-        return Err(error_msg);
+        if errors.iter().all(|e| matches!(e, Error::MissingInput(_))) {
+            return Err(Error::MissingInput(error_msg));
+        }
+        return Err(Error::Other(error_msg));
     }
 
     log_success("Created.");
@@ -224,7 +238,7 @@ fn process_image(
     input_dirs: &[PathBuf],
     output_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     log_info(&format!("Processing image '{image_name}'."));
 
     // Copy fwup template file if image has fwup build args
@@ -235,15 +249,15 @@ fn process_image(
             Some(input_path) => {
                 let output_path = output_dir.join(template);
                 if let Err(e) = copy_file(&input_path, &output_path, verbose) {
-                    return Err(format!(
+                    return Err(Error::Io(format!(
                         "Failed to copy fwup template '{template}' for image '{image_name}': {e}"
-                    ));
+                    )));
                 }
             }
             None => {
-                return Err(format!(
+                return Err(Error::MissingInput(format!(
                     "fwup template '{template}' for image '{image_name}' not found in any input directory"
-                ));
+                )));
             }
         }
     }
@@ -253,9 +267,7 @@ fn process_image(
     if !files.is_empty() {
         for file_entry in files {
             if let Err(e) = process_file_entry(file_entry, input_dirs, output_dir, verbose) {
-                return Err(format!(
-                    "Failed to process file in image '{image_name}': {e}"
-                ));
+                return Err(e.context(&format!("Failed to process file in image '{image_name}'")));
             }
         }
     }
@@ -269,11 +281,11 @@ fn process_image(
                 Some(input_path) => {
                     let output_path = output_dir.join(filename);
                     // Use copy_path to handle both files and directories
-                    copy_path(&input_path, &output_path, verbose)
+                    copy_path(&input_path, &output_path, verbose).map_err(Error::Io)
                 }
-                None => Err(format!(
+                None => Err(Error::MissingInput(format!(
                     "Image file/directory '{filename}' for image '{image_name}' not found in any input directory"
-                )),
+                ))),
             }
         }
         crate::manifest::Image::Object { out, .. } => {
@@ -294,18 +306,18 @@ fn process_file_entry(
     input_dirs: &[PathBuf],
     output_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let input_filename = file_entry.input_filename();
 
     match find_file_in_dirs(input_filename, input_dirs) {
         Some(input_path) => {
             let output_path = output_dir.join(input_filename);
             // Use copy_path to handle both files and directories
-            copy_path(&input_path, &output_path, verbose)
+            copy_path(&input_path, &output_path, verbose).map_err(Error::Io)
         }
-        None => Err(format!(
+        None => Err(Error::MissingInput(format!(
             "File/directory '{input_filename}' not found in any input directory"
-        )),
+        ))),
     }
 }
 
@@ -1,5 +1,6 @@
+use crate::error::Error;
 use crate::log::*;
-use crate::manifest::Manifest;
+use crate::manifest::{Manifest, ManifestFormat, ManifestOptions, Size, SizeUnit, parse_var};
 use clap::Args;
 use std::path::{Path, PathBuf};
 
@@ -13,64 +14,75 @@ pub struct DescribeManifestArgs {
         default_value = "manifest.json"
     )]
     pub manifest: PathBuf,
+
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Value for a ${NAME} reference in the manifest (can be specified multiple times)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// Print the manifest merged over the manifests it extends, instead of describing it
+    #[arg(long = "resolved")]
+    pub resolved: bool,
 }
 
 impl DescribeManifestArgs {
-    pub fn execute(&self) -> Result<(), String> {
-        describe_manifest_command(&self.manifest)
+    pub fn execute(&self) -> Result<(), Error> {
+        let options = ManifestOptions::new(self.format, &self.vars);
+        if self.resolved {
+            print_resolved_manifest(&self.manifest, &options)
+        } else {
+            describe_manifest_command(&self.manifest, &options)
+        }
     }
 }
 
-pub fn describe_manifest_command(manifest_path: &Path) -> Result<(), String> {
+pub fn describe_manifest_command(
+    manifest_path: &Path,
+    manifest_options: &ManifestOptions,
+) -> Result<(), Error> {
     // Check if manifest file exists
     if !manifest_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "Manifest file '{}' not found.",
             manifest_path.display()
-        ));
+        )));
     }
 
-    let manifest = Manifest::from_file(manifest_path)?;
+    let manifest = Manifest::load(manifest_path, manifest_options)?;
     describe_manifest(&manifest)?;
     log_success("Described manifest.");
     Ok(())
 }
 
-// Helper function to format size when both value and unit are known
-fn format_size(size: i64, size_unit: &str) -> Result<String, String> {
-    match size_unit {
-        "bytes" => Ok(format!("{size} bytes")),
-        "kibibytes" => Ok(format!("{size} KiB")),
-        "mebibytes" => Ok(format!("{size} MiB")),
-        "gibibytes" => Ok(format!("{size} GiB")),
-        "tebibytes" => Ok(format!("{size} TiB")),
-        "kilobytes" => Ok(format!("{size} KB")),
-        "megabytes" => Ok(format!("{size} MB")),
-        "gigabytes" => Ok(format!("{size} GB")),
-        "terabytes" => Ok(format!("{size} TB")),
-        "blocks" => Ok(format!("{size} blocks")),
-        _ => Err(format!(
-            "Unknown size unit '{size_unit}'. Supported units: bytes, kibibytes, mebibytes, gibibytes, tebibytes, kilobytes, megabytes, gigabytes, terabytes, blocks."
-        )),
+/// Print the fully resolved manifest in the manifest's own format
+pub fn print_resolved_manifest(
+    manifest_path: &Path,
+    manifest_options: &ManifestOptions,
+) -> Result<(), Error> {
+    if !manifest_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Manifest file '{}' not found.",
+            manifest_path.display()
+        )));
     }
-}
 
-fn format_offset_display(
-    offset: Option<i64>,
-    offset_unit: Option<&String>,
-) -> Result<String, String> {
-    match (offset, offset_unit) {
-        (Some(offset_val), Some(unit)) => match unit.as_str() {
-            "bytes" => Ok(format!("{offset_val} bytes")),
-            "blocks" => Ok(format!("{offset_val} blocks")),
-            _ => Err(format!(
-                "Unknown offset unit '{unit}'. Supported units: bytes, blocks."
-            )),
-        },
-        (Some(offset_val), None) => Ok(format!("{offset_val}")),
-        _ => Ok("-".to_string()),
-    }
+    let resolved = Manifest::resolve_file(manifest_path, manifest_options)?;
+    // Only print manifests stone accepts
+    serde_json::from_value::<Manifest>(resolved.clone()).map_err(|e| {
+        Error::ManifestParse(format!(
+            "Invalid manifest '{}': {}",
+            manifest_path.display(),
+            e
+        ))
+    })?;
+    let format = ManifestFormat::resolve(manifest_options.format, manifest_path);
+    print!("{}", format.to_string(&resolved)?);
+    Ok(())
 }
+
 fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
     let mut output = String::new();
 
@@ -82,6 +94,24 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
         manifest.runtime.platform, manifest.runtime.architecture
     ));
 
+    if let Some(release) = &manifest.release {
+        if let Some(version) = &release.version {
+            output.push_str(&format!("Release: {version}\n"));
+        }
+        if let Some(min_version) = &release.min_version {
+            output.push_str(&format!("Min Version: {min_version}\n"));
+        }
+        if let Some(min_os_build_id) = &release.min_os_build_id {
+            output.push_str(&format!("Min OS Build ID: {min_os_build_id}\n"));
+        }
+        if !release.hardware_revisions.is_empty() {
+            output.push_str(&format!(
+                "Hardware Revisions: {}\n",
+                release.hardware_revisions.join(", ")
+            ));
+        }
+    }
+
     // Storage devices
     for (device_name, device) in &manifest.storage_devices {
         let build_type = device
@@ -114,6 +144,19 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
             output.push_str(&format!("Block Size     : {block_size}\n"));
         }
 
+        if let Some(alignment) = device.alignment {
+            let unit = device.alignment_unit.unwrap_or(SizeUnit::Blocks);
+            output.push_str(&format!(
+                "Alignment      : {}\n",
+                Size::new(alignment, unit)
+            ));
+        }
+
+        if let Some(capacity) = device.capacity {
+            let unit = device.capacity_unit.unwrap_or(SizeUnit::Blocks);
+            output.push_str(&format!("Capacity       : {}\n", Size::new(capacity, unit)));
+        }
+
         // Images section
         output.push_str(&format!("\nImages ({} total):\n", device.images.len()));
 
@@ -126,9 +169,7 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
 
             // Show size if present
             if let Some(size) = image.size() {
-                let size_unit = image.size_unit().unwrap(); // Always present when size is present
-                let size_display = format_size(size, size_unit)?;
-                output.push_str(&format!("    Size: {size_display}\n"));
+                output.push_str(&format!("    Size: {size}\n"));
             }
 
             if let Some(_build) = image.build() {
@@ -146,6 +187,8 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
                         crate::manifest::BuildArgs::Fwup { template } => {
                             output.push_str(&format!("      template: \"{template}\"\n"));
                         }
+                        crate::manifest::BuildArgs::Gpt {} | crate::manifest::BuildArgs::Mbr {} => {
+                        }
                     }
                 }
             }
@@ -186,10 +229,21 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
         output.push_str("  #  Image        Offset       Size           Special\n");
         output.push_str("  ─  ───────────  ───────────  ─────────────  ────────────\n");
 
+        // With a capacity, show where partitions end up and how far they expand
+        let layout = device.capacity.and_then(|_| device.partition_layout().ok());
         for (idx, partition) in device.partitions.iter().enumerate() {
-            let offset = format_offset_display(partition.offset, partition.offset_unit.as_ref())?;
-            let size = format_size(partition.size, &partition.size_unit)?;
-            let special = if partition.expand == Some("true".to_string()) {
+            let placed = layout.as_ref().map(|layout| &layout[idx]);
+            let offset = match placed {
+                Some(placed) => Size::from_bytes(placed.offset).to_string(),
+                None => partition
+                    .offset()
+                    .map_or_else(|| "-".to_string(), |offset| offset.to_string()),
+            };
+            let size = match placed {
+                Some(placed) => Size::from_bytes(placed.size).to_string(),
+                None => partition.size().to_string(),
+            };
+            let special = if partition.expands() {
                 "expandable"
             } else {
                 ""
@@ -220,6 +274,7 @@ fn describe_manifest(manifest: &Manifest) -> Result<(), String> {
                 crate::manifest::BuildArgs::Fwup { template } => {
                     output.push_str(&format!("  template: \"{template}\"\n"));
                 }
+                crate::manifest::BuildArgs::Gpt {} | crate::manifest::BuildArgs::Mbr {} => {}
             }
         }
     }
@@ -0,0 +1,401 @@
+use crate::error::Error;
+use crate::gpt::GptDisk;
+use crate::log::*;
+use crate::manifest::{
+    BuildArgs, Image, MANIFEST_VERSION, Manifest, ManifestFormat, Partition, Runtime, Size,
+    StorageDevice, canonical_manifest,
+};
+use crate::mbr::MbrDisk;
+use clap::Args;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Block sizes tried, in order, when looking for a GPT
+const GPT_BLOCK_SIZES: [u64; 2] = [512, 4096];
+/// Largest --block-size accepted; keeps byte offsets of 32-bit MBR LBAs in range
+const MAX_BLOCK_SIZE: u64 = 64 * 1024;
+
+#[derive(Args, Debug)]
+pub struct ImportImageArgs {
+    /// Disk image with a GPT or MBR partition table
+    #[arg(value_name = "IMAGE")]
+    pub image: PathBuf,
+
+    /// Write the manifest to this file instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Manifest file format (default: from the --output file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Extract each partition to an image file in this directory
+    #[arg(long = "extract", value_name = "DIR")]
+    pub extract_dir: Option<PathBuf>,
+
+    /// Block size of the disk (default: where the GPT is found, else 512)
+    #[arg(long = "block-size", value_name = "BYTES")]
+    pub block_size: Option<u64>,
+
+    /// Name of the storage device in the manifest
+    #[arg(long = "device-name", value_name = "NAME", default_value = "rootdisk")]
+    pub device_name: String,
+
+    /// Device path of the storage device on the target
+    #[arg(long = "devpath", value_name = "PATH", default_value = "/dev/mmcblk0")]
+    pub devpath: String,
+
+    /// Platform of the runtime section
+    #[arg(long = "platform", value_name = "PLATFORM", default_value = "generic")]
+    pub platform: String,
+
+    /// Architecture of the runtime section
+    #[arg(long = "architecture", value_name = "ARCH", default_value = "noarch")]
+    pub architecture: String,
+}
+
+impl ImportImageArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        import_image_command(self)
+    }
+}
+
+/// A partition read from the disk, in blocks
+struct ImportedPartition {
+    name: String,
+    first_lba: u64,
+    blocks: u64,
+    partition_type: String,
+    partition_uuid: Option<String>,
+    bootable: bool,
+    logical: bool,
+}
+
+/// The partition table read from the disk
+struct ImportedDisk {
+    build_args: BuildArgs,
+    block_size: u64,
+    blocks: u64,
+    uuid: String,
+    partitions: Vec<ImportedPartition>,
+}
+
+impl From<GptDisk> for ImportedDisk {
+    fn from(disk: GptDisk) -> Self {
+        let partitions = disk
+            .partitions
+            .into_iter()
+            .map(|partition| ImportedPartition {
+                name: partition.name,
+                first_lba: partition.first_lba,
+                blocks: partition.last_lba + 1 - partition.first_lba,
+                partition_type: partition.type_guid.to_string(),
+                partition_uuid: Some(partition.unique_guid.to_string()),
+                bootable: false,
+                logical: false,
+            })
+            .collect();
+        ImportedDisk {
+            build_args: BuildArgs::Gpt {},
+            block_size: disk.block_size,
+            blocks: disk.blocks,
+            uuid: disk.disk_guid.to_string(),
+            partitions,
+        }
+    }
+}
+
+impl From<MbrDisk> for ImportedDisk {
+    fn from(disk: MbrDisk) -> Self {
+        let primary = disk.primary.into_iter().map(|partition| (partition, false));
+        let logical = disk.logical.into_iter().map(|partition| (partition, true));
+        let partitions = primary
+            .chain(logical)
+            .map(|(partition, logical)| ImportedPartition {
+                name: partition.name,
+                first_lba: partition.first_lba,
+                blocks: partition.blocks,
+                partition_type: format!("0x{:02x}", partition.partition_type),
+                partition_uuid: None,
+                bootable: partition.bootable,
+                logical,
+            })
+            .collect();
+        // stone derives the disk signature from the first four bytes of the
+        // device uuid, so this gives the imported disk its signature back
+        ImportedDisk {
+            build_args: BuildArgs::Mbr {},
+            block_size: disk.block_size,
+            blocks: disk.blocks,
+            uuid: format!("{:08x}-0000-0000-0000-000000000000", disk.disk_signature),
+            partitions,
+        }
+    }
+}
+
+/// Read the partition table of a disk image and write a skeleton manifest for
+/// it, with one image per partition. GPT partition attributes and the exact
+/// placement of MBR extended boot records are not carried over.
+pub fn import_image_command(args: &ImportImageArgs) -> Result<(), Error> {
+    let image_path = &args.image;
+    if !image_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Disk image '{}' not found.",
+            image_path.display()
+        )));
+    }
+    let mut file = File::open(image_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to open disk image '{}': {}",
+            image_path.display(),
+            e
+        ))
+    })?;
+
+    let disk = read_partition_table(&mut file, args.block_size)
+        .map_err(|e| Error::Other(format!("'{}': {e}", image_path.display())))?;
+    let names = partition_names(&disk.partitions);
+
+    if let Some(extract_dir) = &args.extract_dir {
+        fs::create_dir_all(extract_dir).map_err(|e| {
+            Error::Io(format!(
+                "Failed to create directory '{}': {}",
+                extract_dir.display(),
+                e
+            ))
+        })?;
+        for (partition, (_, image_file)) in disk.partitions.iter().zip(&names) {
+            extract_partition(
+                &mut file,
+                partition.first_lba,
+                partition.blocks,
+                disk.block_size,
+                &extract_dir.join(image_file),
+            )?;
+        }
+    }
+
+    let out = image_path.file_name().map_or_else(
+        || "disk.img".to_string(),
+        |name| name.to_string_lossy().to_string(),
+    );
+    let partition_count = disk.partitions.len();
+    let manifest = skeleton_manifest(args, disk, &names, out);
+    let problems = manifest.check();
+
+    let format = args
+        .format
+        .or_else(|| {
+            args.output
+                .as_deref()
+                .and_then(ManifestFormat::from_extension)
+        })
+        .unwrap_or(ManifestFormat::Json);
+    let value = serde_json::to_value(&manifest)
+        .map_err(|e| e.to_string())
+        .and_then(|value| canonical_manifest(&value))
+        .map_err(|e| format!("Failed to write manifest: {e}"))?;
+    let content = format
+        .to_string(&value)
+        .map_err(|e| format!("Failed to write manifest as {format}: {e}"))?;
+
+    match &args.output {
+        Some(path) => {
+            fs::write(path, content).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write manifest to '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            for problem in &problems {
+                log_warning(&format!("Imported manifest: {problem}"));
+            }
+            log_success(&format!(
+                "Imported {} partition(s) from '{}' into '{}'.",
+                partition_count,
+                image_path.display(),
+                path.display()
+            ));
+        }
+        None => print!("{content}"),
+    }
+    Ok(())
+}
+
+/// A GPT if the disk has one at any of the block sizes tried, else an MBR
+fn read_partition_table(
+    reader: &mut (impl Read + Seek),
+    block_size: Option<u64>,
+) -> Result<ImportedDisk, String> {
+    let block_sizes = match block_size {
+        Some(block_size)
+            if !block_size.is_power_of_two() || !(512..=MAX_BLOCK_SIZE).contains(&block_size) =>
+        {
+            return Err(format!(
+                "block size must be a power of two from 512 to {MAX_BLOCK_SIZE} bytes, not {block_size}"
+            ));
+        }
+        Some(block_size) => vec![block_size],
+        None => GPT_BLOCK_SIZES.to_vec(),
+    };
+    for block_size in &block_sizes {
+        if let Some(disk) = GptDisk::read_from(reader, *block_size)? {
+            return Ok(disk.into());
+        }
+    }
+    match MbrDisk::read_from(reader, block_sizes[0])? {
+        Some(disk) => Ok(disk.into()),
+        None => Err("no GPT or MBR partition table found".to_string()),
+    }
+}
+
+/// Unique partition and image names, and the image file of each partition.
+/// Partitions without a name, or whose name or image file is already taken,
+/// are named after their position, with a suffix if that is taken too.
+fn partition_names(partitions: &[ImportedPartition]) -> Vec<(String, String)> {
+    let mut names = HashSet::new();
+    let mut image_files = HashSet::new();
+    partitions
+        .iter()
+        .enumerate()
+        .map(|(index, partition)| {
+            let mut name = partition.name.clone();
+            let mut attempt = 0;
+            loop {
+                if !name.is_empty() && !names.contains(&name) {
+                    let image_file = format!("{}.img", file_stem(&name));
+                    if image_files.insert(image_file.clone()) {
+                        names.insert(name.clone());
+                        return (name, image_file);
+                    }
+                }
+                name = match attempt {
+                    0 => format!("part{}", index + 1),
+                    _ => format!("part{}_{attempt}", index + 1),
+                };
+                attempt += 1;
+            }
+        })
+        .collect()
+}
+
+/// `name` with every character that is unsafe in a file name replaced by '_'
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn skeleton_manifest(
+    args: &ImportImageArgs,
+    disk: ImportedDisk,
+    names: &[(String, String)],
+    out: String,
+) -> Manifest {
+    let block_size = disk.block_size;
+    let partitions = disk
+        .partitions
+        .iter()
+        .zip(names)
+        .map(|(partition, (name, _))| {
+            let offset = Size::from_bytes(partition.first_lba * block_size);
+            let size = Size::from_bytes(partition.blocks * block_size);
+            Partition {
+                name: Some(name.clone()),
+                image: Some(name.clone()),
+                partition_type: Some(partition.partition_type.clone()),
+                partition_uuid: partition.partition_uuid.clone(),
+                offset: Some(offset.value),
+                offset_unit: Some(offset.unit),
+                offset_redundant: None,
+                offset_redundant_unit: None,
+                size: size.value,
+                size_unit: size.unit,
+                expand: None,
+                bootable: partition.bootable.then_some(true),
+                logical: partition.logical.then_some(true),
+            }
+        })
+        .collect();
+    let images: HashMap<String, Image> = names
+        .iter()
+        .map(|(name, image_file)| (name.clone(), Image::String(image_file.clone())))
+        .collect();
+
+    let capacity = Size::from_bytes(disk.blocks * block_size);
+    let device = StorageDevice {
+        out,
+        build_args: Some(disk.build_args),
+        devpath: args.devpath.clone(),
+        block_size: (block_size != 512).then_some(block_size as u32),
+        uuid: Some(disk.uuid),
+        capacity: Some(capacity.value),
+        capacity_unit: Some(capacity.unit),
+        alignment: None,
+        alignment_unit: None,
+        images,
+        partitions,
+    };
+
+    Manifest {
+        manifest_version: Some(MANIFEST_VERSION),
+        runtime: Runtime {
+            platform: args.platform.clone(),
+            architecture: args.architecture.clone(),
+            provision: None,
+            provision_default: None,
+            update_strategy: None,
+        },
+        storage_devices: HashMap::from([(args.device_name.clone(), device)]),
+        provision: None,
+        update: None,
+        release: None,
+        variables: Default::default(),
+    }
+}
+
+/// Copy `blocks` blocks from `first_lba` of the disk to `output`
+fn extract_partition(
+    reader: &mut (impl Read + Seek),
+    first_lba: u64,
+    blocks: u64,
+    block_size: u64,
+    output: &Path,
+) -> Result<(), Error> {
+    let io_error = |e: io::Error| {
+        Error::Io(format!(
+            "Failed to extract partition to '{}': {}",
+            output.display(),
+            e
+        ))
+    };
+    let (Some(offset), Some(size)) = (
+        first_lba.checked_mul(block_size),
+        blocks.checked_mul(block_size),
+    ) else {
+        return Err(Error::Other(format!(
+            "Partition extracted to '{}' is beyond 2^64 bytes",
+            output.display()
+        )));
+    };
+    reader.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+    let mut file = File::create(output).map_err(io_error)?;
+    let copied = io::copy(&mut reader.take(size), &mut file).map_err(io_error)?;
+    if copied < size {
+        return Err(Error::Io(format!(
+            "Partition extracted to '{}' ends past the end of the disk image",
+            output.display()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use crate::bundle::Bundle;
+use crate::error::Error;
+use crate::log::*;
+use crate::signing;
+use clap::Args;
+use sha2::{Digest, Sha256};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Largest window log zstd accepts on 64-bit hosts
+pub const MAX_WINDOW_LOG: u32 = 31;
+
+#[derive(Args, Debug)]
+pub struct InspectBundleArgs {
+    /// Path to the .aos bundle file
+    #[arg(value_name = "BUNDLE")]
+    pub bundle: PathBuf,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+impl InspectBundleArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        inspect_bundle_command(&self.bundle, self.verbose)
+    }
+}
+
+/// Digest of a single entry read from a .aos archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDigest {
+    /// SHA256 hash of the entry contents
+    pub sha256: String,
+    /// Entry size in bytes
+    pub size: u64,
+}
+
+/// Contents of a .aos archive after a single streaming pass
+pub struct BundleContents {
+    /// Raw bytes of the bundle.json entry
+    pub bundle_json: Vec<u8>,
+    /// Hex-encoded detached signature over bundle.json, if the bundle is signed
+    pub signature: Option<String>,
+    /// Digest of every other regular file entry, keyed by archive path
+    pub entries: BTreeMap<String, EntryDigest>,
+}
+
+pub fn inspect_bundle_command(bundle_path: &Path, verbose: bool) -> Result<(), Error> {
+    if !bundle_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Bundle file '{}' not found.",
+            bundle_path.display()
+        )));
+    }
+
+    let contents = read_bundle(bundle_path, verbose)?;
+    let bundle = Bundle::from_slice(&contents.bundle_json)?;
+
+    describe_bundle(&bundle, contents.signature.is_some());
+
+    let problems = verify_bundle_entries(&bundle, &contents.entries);
+    if !problems.is_empty() {
+        let mut error_msg = format!(
+            "Bundle verification failed. {} problem(s) found:",
+            problems.len()
+        );
+        for problem in problems {
+            error_msg.push_str(&format!("\n  {problem}"));
+        }
+        return Err(Error::Other(error_msg));
+    }
+
+    log_success(&format!("Verified bundle '{}'.", bundle_path.display()));
+    Ok(())
+}
+
+/// Open a .aos archive for streaming its tar entries.
+/// zstd-compressed archives are detected by their magic number; anything else is read as plain tar.
+pub fn open_bundle_archive(bundle_path: &Path) -> Result<tar::Archive<Box<dyn Read>>, String> {
+    let file = fs::File::open(bundle_path)
+        .map_err(|e| format!("Failed to open bundle '{}': {}", bundle_path.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let header = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read bundle '{}': {}", bundle_path.display(), e))?;
+
+    let reader: Box<dyn Read> = if header.starts_with(&ZSTD_MAGIC) {
+        let mut decoder = zstd::Decoder::with_buffer(reader)
+            .map_err(|e| format!("Failed to create zstd decoder: {e}"))?;
+        // Accept any window size so bundles built with --long can be read
+        decoder
+            .window_log_max(MAX_WINDOW_LOG)
+            .map_err(|e| format!("Failed to configure zstd decoder: {e}"))?;
+        Box::new(decoder)
+    } else {
+        Box::new(reader)
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Stream a .aos archive, capturing bundle.json and hashing every other entry
+pub fn read_bundle(bundle_path: &Path, verbose: bool) -> Result<BundleContents, String> {
+    let mut archive = open_bundle_archive(bundle_path)?;
+
+    let mut bundle_json = None;
+    let mut signature = None;
+    let mut entries = BTreeMap::new();
+
+    let archive_entries = archive
+        .entries()
+        .map_err(|e| format!("Failed to read bundle archive: {e}"))?;
+    for entry in archive_entries {
+        let mut entry = entry.map_err(|e| format!("Failed to read bundle archive entry: {e}"))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .map_err(|e| format!("Invalid path in bundle archive: {e}"))?
+            .to_string_lossy()
+            .to_string();
+
+        if path == "bundle.json" {
+            let mut buf = Vec::new();
+            entry
+                .read_to_end(&mut buf)
+                .map_err(|e| format!("Failed to read bundle.json from archive: {e}"))?;
+            bundle_json = Some(buf);
+            continue;
+        }
+
+        if path == signing::SIGNATURE_ENTRY {
+            let mut buf = String::new();
+            entry
+                .read_to_string(&mut buf)
+                .map_err(|e| format!("Failed to read bundle.json signature from archive: {e}"))?;
+            signature = Some(buf);
+            continue;
+        }
+
+        let digest = sha256_reader(&mut entry)
+            .map_err(|e| format!("Failed to read '{path}' from archive: {e}"))?;
+        if verbose {
+            log_debug(&format!(
+                "Entry '{path}': sha256 {}, size {}",
+                digest.sha256, digest.size
+            ));
+        }
+        entries.insert(path, digest);
+    }
+
+    let bundle_json = bundle_json.ok_or_else(|| {
+        format!(
+            "Bundle '{}' does not contain a bundle.json entry.",
+            bundle_path.display()
+        )
+    })?;
+
+    Ok(BundleContents {
+        bundle_json,
+        signature,
+        entries,
+    })
+}
+
+/// Compute the SHA256 hash and length of everything readable from `reader`
+fn sha256_reader(reader: &mut impl Read) -> std::io::Result<EntryDigest> {
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    let mut size = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok(EntryDigest {
+        sha256: format!("{:x}", hasher.finalize()),
+        size,
+    })
+}
+
+/// Check every archive entry against the artifacts recorded in bundle.json.
+/// Returns one message per mismatch, missing entry or unexpected entry.
+pub fn verify_bundle_entries(
+    bundle: &Bundle,
+    entries: &BTreeMap<String, EntryDigest>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut expected = BTreeMap::new();
+
+    for artifact in bundle.artifacts() {
+        let name = artifact.name.as_str();
+        expected.insert(
+            artifact.file.as_str(),
+            (name, artifact.sha256.as_str(), artifact.size),
+        );
+
+        // The chunk index sidecar is covered by bundle.json just like the artifact
+        if let Some(chunks) = &artifact.chunks {
+            expected.insert(
+                chunks.index.as_str(),
+                (name, chunks.sha256.as_str(), chunks.size),
+            );
+        }
+    }
+
+    for (file, (name, sha256, size)) in &expected {
+        match entries.get(*file) {
+            None => problems.push(format!("missing: '{file}' (artifact '{name}')")),
+            Some(digest) => {
+                if digest.size != *size {
+                    problems.push(format!(
+                        "size mismatch: '{file}' (expected {size}, found {})",
+                        digest.size
+                    ));
+                }
+                if digest.sha256 != *sha256 {
+                    problems.push(format!(
+                        "sha256 mismatch: '{file}' (expected {sha256}, found {})",
+                        digest.sha256
+                    ));
+                }
+            }
+        }
+    }
+
+    for file in entries.keys() {
+        if !expected.contains_key(file.as_str()) {
+            problems.push(format!("unexpected entry: '{file}'"));
+        }
+    }
+
+    problems
+}
+
+fn describe_bundle(bundle: &Bundle, signed: bool) {
+    let mut output = String::new();
+
+    output.push_str(&format!(
+        "Bundle Description\n\
+        ═══════════════════════════════════════════════════════════════════════════════\n\
+        Format Version : {}\n\
+        Platform       : {} ({})\n\
+        OS Build ID    : {}\n",
+        bundle.format_version, bundle.platform, bundle.architecture, bundle.os_build_id,
+    ));
+
+    if let Some(initramfs_id) = &bundle.initramfs_build_id {
+        output.push_str(&format!("Initramfs ID   : {initramfs_id}\n"));
+    }
+
+    output.push_str(&format!(
+        "Signature      : {}\n",
+        if signed { "present" } else { "none" }
+    ));
+
+    if let Some(update) = &bundle.update {
+        output.push_str(&format!("Strategy       : {}\n", update.strategy));
+    }
+
+    if let Some(release) = &bundle.release {
+        if let Some(version) = &release.version {
+            output.push_str(&format!("Release        : {version}\n"));
+        }
+        if let Some(min_version) = &release.min_version {
+            output.push_str(&format!("Min Version    : {min_version}\n"));
+        }
+        if let Some(min_os_build_id) = &release.min_os_build_id {
+            output.push_str(&format!("Min OS Build ID: {min_os_build_id}\n"));
+        }
+        if !release.hardware_revisions.is_empty() {
+            output.push_str(&format!(
+                "Hardware       : {}\n",
+                release.hardware_revisions.join(", ")
+            ));
+        }
+        if let Some(notes) = &release.notes {
+            output.push_str("\nRelease Notes:\n");
+            for line in notes.lines() {
+                output.push_str(&format!("  {line}\n"));
+            }
+        }
+    }
+
+    let artifacts = bundle.artifacts();
+    output.push_str(&format!("\nArtifacts ({} total):\n", artifacts.len()));
+    for artifact in artifacts {
+        output.push_str(&format!(
+            "\n  • {} → {}\n    Size: {} bytes\n    SHA256: {}\n",
+            artifact.name, artifact.file, artifact.size, artifact.sha256,
+        ));
+        if let Some(chunks) = &artifact.chunks {
+            output.push_str(&format!(
+                "    Chunks: {} (avg {} bytes) → {}\n",
+                chunks.count, chunks.avg_size, chunks.index,
+            ));
+        }
+        if !artifact.slot_targets.is_empty() {
+            output.push_str("    Slot Targets:\n");
+            for (slot, target) in &artifact.slot_targets {
+                output.push_str(&format!(
+                    "      {slot}: {} / {}\n",
+                    target.device, target.partition
+                ));
+            }
+        }
+    }
+
+    for (device_name, device) in &bundle.layout {
+        output.push_str(&format!("\nLayout: {device_name} ({})\n", device.devpath));
+        if let Some(block_size) = device.block_size {
+            output.push_str(&format!("  Block Size: {block_size}\n"));
+        }
+        output.push_str("  Name           Offset (bytes)   Size\n");
+        output.push_str("  ─────────────  ───────────────  ─────────────\n");
+        for partition in &device.partitions {
+            output.push_str(&format!(
+                "  {:<13}  {:<15}  {} {}\n",
+                partition.name.as_deref().unwrap_or("-"),
+                partition.offset,
+                partition.size,
+                partition.size_unit,
+            ));
+        }
+    }
+
+    output.push_str(
+        "═══════════════════════════════════════════════════════════════════════════════",
+    );
+
+    println!("{output}");
+}
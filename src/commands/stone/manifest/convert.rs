@@ -0,0 +1,111 @@
+use crate::error::Error;
+use crate::log::*;
+use crate::manifest::{Manifest, ManifestFormat, ManifestOptions};
+use clap::Args;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// Path to the manifest to convert
+    #[arg(value_name = "INPUT")]
+    pub input: PathBuf,
+
+    /// Format of the input manifest (default: from the file extension, else JSON)
+    #[arg(long = "from", value_name = "FORMAT")]
+    pub from: Option<ManifestFormat>,
+
+    /// Format to convert to (default: from the --output file extension)
+    #[arg(long = "to", value_name = "FORMAT")]
+    pub to: Option<ManifestFormat>,
+
+    /// Write the converted manifest to this file instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+impl ConvertArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        convert_command(&self.input, self.from, self.to, self.output.as_deref())
+    }
+}
+
+/// Convert a manifest to another format. The manifest must be valid; comments are not carried over.
+pub fn convert_command(
+    input_path: &Path,
+    from: Option<ManifestFormat>,
+    to: Option<ManifestFormat>,
+    output_path: Option<&Path>,
+) -> Result<(), Error> {
+    if !input_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Manifest file '{}' not found.",
+            input_path.display()
+        )));
+    }
+
+    let from = ManifestFormat::resolve(from, input_path);
+    let to = to
+        .or_else(|| output_path.and_then(ManifestFormat::from_extension))
+        .ok_or_else(|| {
+            "Cannot tell which format to convert to. Pass --to, or an --output path ending in .json, .json5, .jsonc, .yaml, .yml or .toml.".to_string()
+        })?;
+
+    let content = fs::read_to_string(input_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to read manifest file '{}': {}",
+            input_path.display(),
+            e
+        ))
+    })?;
+    let value: serde_json::Value = from.parse(&content).map_err(|e| {
+        Error::ManifestParse(format!(
+            "Failed to parse manifest {} '{}': {}",
+            from,
+            input_path.display(),
+            e
+        ))
+    })?;
+
+    // Only convert manifests stone can load, so the output is usable as-is.
+    // `extends` is kept; the base manifests are checked but not converted.
+    if value.get("extends").is_some() {
+        let options = ManifestOptions {
+            format: Some(from),
+            ..Default::default()
+        };
+        Manifest::load(input_path, &options)?;
+    } else {
+        serde_json::from_value::<Manifest>(value.clone()).map_err(|e| {
+            Error::ManifestParse(format!(
+                "Invalid manifest '{}': {}",
+                input_path.display(),
+                e
+            ))
+        })?;
+    }
+
+    let converted = to
+        .to_string(&value)
+        .map_err(|e| format!("Failed to write manifest as {to}: {e}"))?;
+
+    match output_path {
+        Some(path) => {
+            fs::write(path, converted).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write manifest to '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            log_success(&format!(
+                "Converted '{}' ({from}) to '{}' ({to}).",
+                input_path.display(),
+                path.display()
+            ));
+        }
+        None => print!("{converted}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::log::*;
+use crate::manifest::{ManifestFormat, canonical_manifest};
+use clap::Args;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// Manifests to format
+    #[arg(value_name = "PATH", default_value = "manifest.json")]
+    pub manifests: Vec<PathBuf>,
+
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Do not rewrite anything; fail if a manifest is not formatted
+    #[arg(long = "check")]
+    pub check: bool,
+
+    /// Rewrite JSON5, YAML and TOML manifests too, dropping their comments
+    #[arg(long = "force")]
+    pub force: bool,
+}
+
+impl FmtArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        fmt_command(&self.manifests, self.format, self.check, self.force)
+    }
+}
+
+/// Rewrite manifests in canonical form. Comments would not be carried over,
+/// so formats that allow them are only compared by value, and rewritten only
+/// with `force`.
+pub fn fmt_command(
+    manifest_paths: &[PathBuf],
+    format: Option<ManifestFormat>,
+    check: bool,
+    force: bool,
+) -> Result<(), Error> {
+    let mut unformatted = Vec::new();
+    let mut skipped = Vec::new();
+
+    for path in manifest_paths {
+        let formatted = format_manifest(path, format)?;
+        let keeps_comments = formatted.format.allows_comments() && !force;
+        if formatted.content == formatted.canonical_content
+            || (keeps_comments && formatted.value == formatted.canonical)
+        {
+            continue;
+        }
+
+        if check {
+            log_warning(&format!("'{}' is not formatted.", path.display()));
+        } else if keeps_comments {
+            log_warning(&format!(
+                "'{}' is not formatted. Not rewriting it, as its {} comments would be lost.",
+                path.display(),
+                formatted.format
+            ));
+            skipped.push(path);
+            continue;
+        } else {
+            let formatted = formatted.canonical_content;
+            fs::write(path, formatted).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write manifest to '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            log_info(&format!("Formatted '{}'.", path.display()));
+        }
+        unformatted.push(path);
+    }
+
+    if check && !unformatted.is_empty() {
+        return Err(Error::Other(format!(
+            "{} manifest(s) not formatted. Run 'stone manifest fmt' to format them.",
+            unformatted.len()
+        )));
+    }
+    if !skipped.is_empty() {
+        return Err(Error::Other(format!(
+            "{} manifest(s) not rewritten. Format them by hand, or pass --force to rewrite them without comments.",
+            skipped.len()
+        )));
+    }
+    log_success(&format!(
+        "{} manifest(s) checked, {} {}.",
+        manifest_paths.len(),
+        unformatted.len(),
+        if check { "unformatted" } else { "reformatted" }
+    ));
+    Ok(())
+}
+
+/// A manifest file as read, and the same manifest in canonical form
+struct FormattedManifest {
+    format: ManifestFormat,
+    content: String,
+    value: serde_json::Value,
+    canonical: serde_json::Value,
+    canonical_content: String,
+}
+
+fn format_manifest(
+    path: &Path,
+    format: Option<ManifestFormat>,
+) -> Result<FormattedManifest, Error> {
+    let format = ManifestFormat::resolve(format, path);
+    let content = fs::read_to_string(path).map_err(|e| {
+        let message = format!("Failed to read manifest file '{}': {}", path.display(), e);
+        if e.kind() == std::io::ErrorKind::NotFound {
+            Error::MissingInput(message)
+        } else {
+            Error::Io(message)
+        }
+    })?;
+    let value: serde_json::Value = format.parse(&content).map_err(|e| {
+        Error::ManifestParse(format!(
+            "Failed to parse manifest {} '{}': {}",
+            format,
+            path.display(),
+            e
+        ))
+    })?;
+
+    let canonical = canonical_manifest(&value).map_err(|e| {
+        Error::ManifestParse(format!("Invalid manifest '{}': {}", path.display(), e))
+    })?;
+    let canonical_content = format
+        .to_string(&canonical)
+        .map_err(|e| format!("Failed to write manifest as {format}: {e}"))?;
+    Ok(FormattedManifest {
+        format,
+        content,
+        value,
+        canonical,
+        canonical_content,
+    })
+}
@@ -0,0 +1,36 @@
+use crate::error::Error;
+use clap::{Args, Subcommand};
+
+pub mod convert;
+pub mod fmt;
+pub mod upgrade;
+
+use convert::ConvertArgs;
+use fmt::FmtArgs;
+use upgrade::UpgradeArgs;
+
+#[derive(Args, Debug)]
+pub struct ManifestArgs {
+    #[command(subcommand)]
+    pub command: ManifestCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ManifestCommands {
+    /// Convert a manifest between JSON, JSON5, YAML and TOML.
+    Convert(ConvertArgs),
+    /// Rewrite manifests in canonical form: sorted keys and canonical unit names.
+    Fmt(FmtArgs),
+    /// Rewrite a manifest to the current manifest version.
+    Upgrade(UpgradeArgs),
+}
+
+impl ManifestArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        match &self.command {
+            ManifestCommands::Convert(args) => args.execute(),
+            ManifestCommands::Fmt(args) => args.execute(),
+            ManifestCommands::Upgrade(args) => args.execute(),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::error::Error;
+use crate::log::*;
+use crate::manifest::{
+    MANIFEST_VERSION, Manifest, ManifestFormat, has_references, upgrade_manifest,
+};
+use clap::Args;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct UpgradeArgs {
+    /// Path to the manifest to upgrade
+    #[arg(value_name = "PATH")]
+    pub input: PathBuf,
+
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Write the upgraded manifest to this file instead of rewriting PATH
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Rewrite JSON5, YAML and TOML manifests in place too, dropping their comments
+    #[arg(long = "force")]
+    pub force: bool,
+}
+
+impl UpgradeArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        upgrade_command(&self.input, self.format, self.output.as_deref(), self.force)
+    }
+}
+
+/// Rewrite a manifest to the current schema version. Comments are not carried
+/// over, so formats that allow them are only rewritten in place with `force`.
+pub fn upgrade_command(
+    input_path: &Path,
+    format: Option<ManifestFormat>,
+    output_path: Option<&Path>,
+    force: bool,
+) -> Result<(), Error> {
+    if !input_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Manifest file '{}' not found.",
+            input_path.display()
+        )));
+    }
+
+    let format = ManifestFormat::resolve(format, input_path);
+    let content = fs::read_to_string(input_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to read manifest file '{}': {}",
+            input_path.display(),
+            e
+        ))
+    })?;
+    let mut value: serde_json::Value = format.parse(&content).map_err(|e| {
+        Error::ManifestParse(format!(
+            "Failed to parse manifest {} '{}': {}",
+            format,
+            input_path.display(),
+            e
+        ))
+    })?;
+
+    if let Some(version) = value.get("manifest_version").and_then(|v| v.as_u64())
+        && version > u64::from(MANIFEST_VERSION)
+    {
+        return Err(Error::ManifestParse(format!(
+            "Manifest '{}' has version {version}, newer than this stone supports ({MANIFEST_VERSION}). Upgrade stone.",
+            input_path.display()
+        )));
+    }
+
+    let changes = upgrade_manifest(&mut value).map_err(|e| {
+        Error::InvalidManifest(format!(
+            "Failed to upgrade '{}': {}",
+            input_path.display(),
+            e
+        ))
+    })?;
+    if changes.is_empty() && output_path.is_none() {
+        log_success(&format!(
+            "'{}' is already at manifest version {MANIFEST_VERSION}.",
+            input_path.display()
+        ));
+        return Ok(());
+    }
+    if output_path.is_none() && format.allows_comments() && !force {
+        return Err(Error::Other(format!(
+            "Not upgrading {} manifest '{}' in place: comments would be lost. Pass --output to write the upgrade elsewhere, or --force to rewrite it without comments.",
+            format,
+            input_path.display()
+        )));
+    }
+
+    // A manifest that extends another may be incomplete on its own, and a
+    // ${NAME} reference in a field with fixed values, such as a unit, only
+    // parses once expanded, so only check the rest.
+    if value.get("extends").is_none() && !has_references(&value) {
+        serde_json::from_value::<Manifest>(value.clone()).map_err(|e| {
+            Error::InvalidManifest(format!(
+                "Upgraded manifest '{}' is invalid: {}",
+                input_path.display(),
+                e
+            ))
+        })?;
+    }
+
+    let output_path = output_path.unwrap_or(input_path);
+    let output_format = ManifestFormat::from_extension(output_path).unwrap_or(format);
+    let upgraded = output_format
+        .to_string(&value)
+        .map_err(|e| format!("Failed to write manifest as {output_format}: {e}"))?;
+    fs::write(output_path, upgraded).map_err(|e| {
+        Error::Io(format!(
+            "Failed to write manifest to '{}': {}",
+            output_path.display(),
+            e
+        ))
+    })?;
+
+    for change in &changes {
+        log_info(change);
+    }
+    log_success(&format!(
+        "Upgraded '{}' to manifest version {MANIFEST_VERSION}.",
+        output_path.display()
+    ));
+    Ok(())
+}
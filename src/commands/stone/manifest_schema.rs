@@ -0,0 +1,40 @@
+use crate::error::Error;
+use crate::log::*;
+use crate::manifest::Manifest;
+use clap::Args;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct ManifestSchemaArgs {
+    /// Write the schema to this file instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+impl ManifestSchemaArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        manifest_schema_command(self.output.as_deref())
+    }
+}
+
+pub fn manifest_schema_command(output_path: Option<&Path>) -> Result<(), Error> {
+    let schema = serde_json::to_string_pretty(&Manifest::json_schema())
+        .map_err(|e| format!("Failed to serialize manifest schema: {e}"))?;
+
+    match output_path {
+        Some(path) => {
+            fs::write(path, format!("{schema}\n")).map_err(|e| {
+                Error::Io(format!(
+                    "Failed to write schema to '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            log_success(&format!("Wrote manifest schema to '{}'.", path.display()));
+        }
+        None => println!("{schema}"),
+    }
+    Ok(())
+}
@@ -1,16 +1,32 @@
 use clap::Subcommand;
 
+pub mod apply_bundle;
 pub mod bundle;
+pub mod bundle_delta;
+pub mod bundle_schema;
 pub mod create;
 pub mod describe_manifest;
+pub mod import_image;
+pub mod inspect_bundle;
+pub mod manifest;
+pub mod manifest_schema;
 pub mod provision;
 pub mod validate;
+pub mod verify_bundle;
 
+use apply_bundle::ApplyBundleArgs;
 use bundle::BundleArgs;
+use bundle_delta::BundleDeltaArgs;
+use bundle_schema::BundleSchemaArgs;
 use create::CreateArgs;
 use describe_manifest::DescribeManifestArgs;
+use import_image::ImportImageArgs;
+use inspect_bundle::InspectBundleArgs;
+use manifest::ManifestArgs;
+use manifest_schema::ManifestSchemaArgs;
 use provision::ProvisionArgs;
 use validate::ValidateArgs;
+use verify_bundle::VerifyBundleArgs;
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
@@ -18,6 +34,17 @@ pub enum Commands {
     #[command(name = "describe-manifest")]
     DescribeManifest(DescribeManifestArgs),
 
+    /// Print the JSON Schema of the manifest file, for editors and pre-commit checks.
+    #[command(name = "manifest-schema")]
+    ManifestSchema(ManifestSchemaArgs),
+
+    /// Work with manifest files: convert, format and upgrade them.
+    Manifest(ManifestArgs),
+
+    /// Write a skeleton manifest for the partition table of an existing disk image.
+    #[command(name = "import-image")]
+    ImportImage(ImportImageArgs),
+
     /// Check if the manifest's inputs are satisfied.
     Validate(ValidateArgs),
 
@@ -25,7 +52,27 @@ pub enum Commands {
     Create(CreateArgs),
 
     /// Build an OS bundle (.aos) containing all boot/OS artifacts for OTA and provisioning.
-    Bundle(BundleArgs),
+    Bundle(Box<BundleArgs>),
+
+    /// Build a delta OS bundle (.aos) of binary patches between two full bundles.
+    #[command(name = "bundle-delta")]
+    BundleDelta(BundleDeltaArgs),
+
+    /// Print the JSON Schema of bundle.json, the description stored in every OS bundle (.aos).
+    #[command(name = "bundle-schema")]
+    BundleSchema(BundleSchemaArgs),
+
+    /// List the contents of an OS bundle (.aos) and verify its artifacts.
+    #[command(name = "inspect-bundle")]
+    InspectBundle(InspectBundleArgs),
+
+    /// Verify an OS bundle's (.aos) signature and then its artifacts.
+    #[command(name = "verify-bundle")]
+    VerifyBundle(VerifyBundleArgs),
+
+    /// Install an OS bundle (.aos) into a disk image file and run its activate actions.
+    #[command(name = "apply-bundle")]
+    ApplyBundle(ApplyBundleArgs),
 
     /// Provision by actually building the artifacts specified in the manifest.
     Provision(ProvisionArgs),
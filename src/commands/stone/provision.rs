@@ -1,10 +1,18 @@
+use crate::error::Error;
 use crate::fat;
+use crate::fwup;
+use crate::gpt::GptDisk;
 use crate::log::*;
-use crate::manifest::{BuildArgs, FatVariant, FileEntry, Image, Manifest};
+use crate::manifest::{
+    BuildArgs, DEFAULT_BLOCK_SIZE, FatVariant, FileEntry, Image, Manifest, ManifestFormat,
+    ManifestOptions, Size, StorageDevice,
+};
+use crate::mbr::MbrDisk;
 use clap::Args;
 
 use std::collections::HashMap;
 use std::fs;
+use std::io::{self, Seek, SeekFrom};
 
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -26,7 +34,7 @@ pub struct ProvisionArgs {
 }
 
 impl ProvisionArgs {
-    pub fn execute(&self) -> Result<(), String> {
+    pub fn execute(&self) -> Result<(), Error> {
         provision_command(&self.input_dirs, self.verbose)
     }
 }
@@ -42,13 +50,34 @@ fn find_file_in_dirs(filename: &str, input_dirs: &[PathBuf]) -> Option<PathBuf>
     None
 }
 
-pub fn provision_command(input_dirs: &[PathBuf], verbose: bool) -> Result<(), String> {
-    // Find manifest.json in the input directories
-    let manifest_path = find_file_in_dirs("manifest.json", input_dirs).ok_or_else(|| {
-        "Manifest file 'manifest.json' not found in any input directory.".to_string()
-    })?;
+pub fn provision_command(input_dirs: &[PathBuf], verbose: bool) -> Result<(), Error> {
+    // Find the manifest in the input directories; manifest.json wins over other formats
+    let manifest_path = ManifestFormat::FILE_NAMES
+        .iter()
+        .find_map(|name| find_file_in_dirs(name, input_dirs))
+        .ok_or_else(|| {
+            Error::MissingInput(
+                "Manifest file 'manifest.json' not found in any input directory.".to_string(),
+            )
+        })?;
+
+    provision_manifest(
+        &manifest_path,
+        &ManifestOptions::default(),
+        input_dirs,
+        verbose,
+    )
+}
 
-    let manifest = Manifest::from_file(&manifest_path)?;
+/// Build the manifest's artifacts and run its provision script, in a fresh
+/// `_build` directory next to the manifest
+pub fn provision_manifest(
+    manifest_path: &Path,
+    manifest_options: &ManifestOptions,
+    input_dirs: &[PathBuf],
+    verbose: bool,
+) -> Result<(), Error> {
+    let manifest = Manifest::load(manifest_path, manifest_options)?;
 
     // Determine the directory containing the manifest
     let input_dir = manifest_path
@@ -64,18 +93,18 @@ pub fn provision_command(input_dirs: &[PathBuf], verbose: bool) -> Result<(), St
     if build_dir.exists()
         && let Err(e) = fs::remove_dir_all(&build_dir)
     {
-        return Err(format!(
+        return Err(Error::Io(format!(
             "Failed to clean build directory '{}': {}",
             build_dir.display(),
             e
-        ));
+        )));
     }
     if let Err(e) = fs::create_dir_all(&build_dir) {
-        return Err(format!(
+        return Err(Error::Io(format!(
             "Failed to create build directory '{}': {}",
             build_dir.display(),
             e
-        ));
+        )));
     }
 
     if verbose {
@@ -113,7 +142,7 @@ pub fn provision_command(input_dirs: &[PathBuf], verbose: bool) -> Result<(), St
     }
 
     // Execute provision script using profile-based approach
-    execute_provision_with_profile(&manifest, &manifest_path, input_dirs, &build_dir, verbose)?;
+    execute_provision_with_profile(&manifest, manifest_path, input_dirs, &build_dir, verbose)?;
 
     log_success("Provision completed.");
     Ok(())
@@ -127,7 +156,7 @@ fn build_storage_device(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     match build_args {
         BuildArgs::Fwup { template } => {
             log_info(&format!(
@@ -144,8 +173,23 @@ fn build_storage_device(
                 verbose,
             )?;
         }
+        BuildArgs::Gpt {} | BuildArgs::Mbr {} => {
+            let table = match build_args {
+                BuildArgs::Gpt {} => device.gpt_disk().map(PartitionTable::Gpt),
+                _ => device.mbr_disk().map(PartitionTable::Mbr),
+            }
+            .map_err(|e| Error::InvalidManifest(format!("Storage device '{device_name}': {e}")))?;
+            log_info(&format!(
+                "Building storage device '{device_name}' with a {} partition table.",
+                table.name()
+            ));
+
+            build_partitioned_device(device_name, device, table, input_dirs, build_dir, verbose)?;
+        }
         BuildArgs::Fat { .. } => {
-            return Err("FAT build args not supported for storage devices".to_string());
+            return Err(Error::InvalidManifest(
+                "FAT build args not supported for storage devices".to_string(),
+            ));
         }
     }
 
@@ -159,7 +203,7 @@ fn build_image(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     log_info(&format!(
         "Building image '{image_name}' in device '{device_name}'."
     ));
@@ -179,6 +223,7 @@ fn build_image(
             build_args: Some(build_args),
             size,
             size_unit,
+            block_size,
             ..
         } => match build_args {
             BuildArgs::Fat { variant, files } => build_fat_image(FatImageParams {
@@ -186,8 +231,8 @@ fn build_image(
                 out,
                 variant,
                 files,
-                size,
-                size_unit,
+                size: Size::new(*size, *size_unit),
+                block_size: *block_size,
                 input_dirs,
                 build_dir,
                 verbose,
@@ -195,6 +240,10 @@ fn build_image(
             BuildArgs::Fwup { template } => {
                 build_fwup_image(image_name, image, template, input_dirs, build_dir, verbose)
             }
+            BuildArgs::Gpt {} | BuildArgs::Mbr {} => Err(Error::InvalidManifest(format!(
+                "Image '{image_name}': {} build args are only supported for storage devices",
+                build_args.build_type()
+            ))),
         },
         Image::Object {
             build_args: None, ..
@@ -215,21 +264,22 @@ struct FatImageParams<'a> {
     out: &'a str,
     variant: &'a FatVariant,
     files: &'a [FileEntry],
-    size: &'a i64,
-    size_unit: &'a str,
+    size: Size,
+    block_size: Option<u32>,
     input_dirs: &'a [PathBuf],
     build_dir: &'a Path,
     verbose: bool,
 }
 
-fn build_fat_image(params: FatImageParams) -> Result<(), String> {
+fn build_fat_image(params: FatImageParams) -> Result<(), Error> {
     log_info(&format!(
         "Building FAT image '{}' -> '{}'.",
         params.image_name, params.out
     ));
 
-    // Convert size to MB
-    let size_mb = convert_size_to_mb(*params.size, params.size_unit)?;
+    let size = params
+        .size
+        .bytes(params.block_size.map_or(DEFAULT_BLOCK_SIZE, u64::from))?;
 
     // Convert FatVariant to fat::FatType
     let fat_type = match params.variant {
@@ -248,7 +298,7 @@ fn build_fat_image(params: FatImageParams) -> Result<(), String> {
     let manifest_json = serde_json::to_string_pretty(&fat_manifest)
         .map_err(|e| format!("Failed to serialize FAT manifest: {e}"))?;
     fs::write(&temp_manifest_path, manifest_json)
-        .map_err(|e| format!("Failed to write temporary manifest: {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to write temporary manifest: {e}")))?;
 
     let output_path = params.build_dir.join(params.out);
 
@@ -260,7 +310,7 @@ fn build_fat_image(params: FatImageParams) -> Result<(), String> {
         .with_manifest_path(&temp_manifest_path)
         .with_base_path(&base_path)
         .with_output_path(&output_path)
-        .with_size_mebibytes(size_mb)
+        .with_size_bytes(size)
         .with_fat_type(fat_type)
         .with_verbose(params.verbose);
 
@@ -283,14 +333,17 @@ fn build_fwup_image(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let out = image.out();
     log_info(&format!(
         "Building fwup image '{image_name}' -> '{out}' using template '{template}'."
     ));
 
-    let template_path = find_file_in_dirs(template, input_dirs)
-        .ok_or_else(|| format!("fwup template '{template}' not found in any input directory"))?;
+    let template_path = find_file_in_dirs(template, input_dirs).ok_or_else(|| {
+        Error::MissingInput(format!(
+            "fwup template '{template}' not found in any input directory"
+        ))
+    })?;
     let output_path = build_dir.join(out);
 
     let mut cmd = Command::new("fwup");
@@ -326,50 +379,20 @@ fn build_fwup_image(
         }
     }
 
-    let status = cmd.status().map_err(|e| {
-        if e.kind() == std::io::ErrorKind::NotFound {
-            "fwup command not found. Please install fwup to build firmware packages.".to_string()
-        } else {
-            format!("Failed to execute fwup command: {e}")
-        }
-    })?;
+    let status = cmd.status().map_err(fwup::spawn_error)?;
 
     if !status.success() {
-        return Err(format!(
-            "fwup command failed with exit code: {}",
-            status.code().unwrap_or(-1)
-        ));
+        return Err(fwup::exit_error(status));
     }
 
     log_success(&format!("Built fwup image '{out}'."));
     Ok(())
 }
 
-fn convert_size_to_mb(size: i64, size_unit: &str) -> Result<u64, String> {
-    let size_mb = match size_unit.to_lowercase().as_str() {
-        "bytes" | "byte" | "b" => size as f64 / (1024.0 * 1024.0),
-        "kilobytes" | "kilobyte" | "kb" => size as f64 / 1024.0,
-        "kibibytes" | "kibibyte" | "kib" => size as f64 / 1024.0,
-        "megabytes" | "megabyte" | "mb" => size as f64,
-        "mebibytes" | "mebibyte" | "mib" => size as f64,
-        "gigabytes" | "gigabyte" | "gb" => size as f64 * 1024.0,
-        "gibibytes" | "gibibyte" | "gib" => size as f64 * 1024.0,
-        _ => {
-            return Err(format!("Unsupported size unit: {size_unit}"));
-        }
-    };
-
-    if size_mb <= 0.0 {
-        return Err("Image size must be positive".to_string());
-    }
-
-    Ok(size_mb.ceil() as u64)
-}
-
 fn create_fat_manifest_with_resolved_paths(
     files: &[FileEntry],
     input_dirs: &[PathBuf],
-) -> Result<fat::Manifest, String> {
+) -> Result<fat::Manifest, Error> {
     let mut fat_files = Vec::new();
 
     for entry in files {
@@ -382,7 +405,9 @@ fn create_fat_manifest_with_resolved_paths(
 
         // Resolve the input file across all input directories
         let resolved_path = find_file_in_dirs(input_filename, input_dirs).ok_or_else(|| {
-            format!("File '{input_filename}' not found in any input directory for FAT image")
+            Error::MissingInput(format!(
+                "File '{input_filename}' not found in any input directory for FAT image"
+            ))
         })?;
 
         fat_files.push(fat::FileEntry {
@@ -407,9 +432,12 @@ fn build_fwup_with_env_vars(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
-    let template_path = find_file_in_dirs(template, input_dirs)
-        .ok_or_else(|| format!("fwup template '{template}' not found in any input directory"))?;
+) -> Result<(), Error> {
+    let template_path = find_file_in_dirs(template, input_dirs).ok_or_else(|| {
+        Error::MissingInput(format!(
+            "fwup template '{template}' not found in any input directory"
+        ))
+    })?;
     let output_path = build_dir.join(&device.out);
 
     // Calculate environment variables from manifest
@@ -442,13 +470,7 @@ fn build_fwup_with_env_vars(
         }
     }
 
-    let status = cmd.status().map_err(|e| {
-        if e.kind() == std::io::ErrorKind::NotFound {
-            "fwup command not found. Please install fwup to build firmware packages.".to_string()
-        } else {
-            format!("Failed to execute fwup command: {e}")
-        }
-    })?;
+    let status = cmd.status().map_err(fwup::spawn_error)?;
 
     if !status.success() {
         // Show environment variables when fwup fails to help with debugging
@@ -458,10 +480,7 @@ fn build_fwup_with_env_vars(
                 log_debug(&format!("  {key}={value}"));
             }
         }
-        return Err(format!(
-            "fwup command failed with exit code: {}",
-            status.code().unwrap_or(-1)
-        ));
+        return Err(fwup::exit_error(status));
     }
 
     log_success(&format!(
@@ -475,6 +494,162 @@ fn build_fwup_with_env_vars(
     Ok(())
 }
 
+/// Full path of an image: built images are in the build directory, the others
+/// are input files
+fn image_path(image: &Image, input_dirs: &[PathBuf], build_dir: &Path) -> Result<PathBuf, Error> {
+    match image {
+        Image::Object {
+            out,
+            build_args: Some(_),
+            ..
+        } => Ok(build_dir.join(out)),
+        Image::String(filename)
+        | Image::Object {
+            out: filename,
+            build_args: None,
+            ..
+        } => find_file_in_dirs(filename, input_dirs).ok_or_else(|| {
+            Error::MissingInput(format!(
+                "Image file '{filename}' not found in any input directory"
+            ))
+        }),
+    }
+}
+
+/// Partition table stone writes itself, for the gpt and mbr build types
+enum PartitionTable {
+    Gpt(GptDisk),
+    Mbr(MbrDisk),
+}
+
+impl PartitionTable {
+    fn name(&self) -> &'static str {
+        match self {
+            PartitionTable::Gpt(_) => "GPT",
+            PartitionTable::Mbr(_) => "MBR",
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            PartitionTable::Gpt(disk) => disk.blocks * disk.block_size,
+            PartitionTable::Mbr(disk) => disk.blocks * disk.block_size,
+        }
+    }
+
+    fn write_to(&self, file: &mut fs::File) -> Result<(), String> {
+        match self {
+            PartitionTable::Gpt(disk) => disk.write_to(file),
+            PartitionTable::Mbr(disk) => disk.write_to(file),
+        }
+    }
+}
+
+/// Write the device's `out` file: the partition table, with each partition's
+/// image at its offset, and at its redundant offset when it has one
+fn build_partitioned_device(
+    device_name: &str,
+    device: &StorageDevice,
+    table: PartitionTable,
+    input_dirs: &[PathBuf],
+    build_dir: &Path,
+    verbose: bool,
+) -> Result<(), Error> {
+    let output_path = build_dir.join(&device.out);
+
+    let mut file = fs::File::create(&output_path).map_err(|e| {
+        Error::Io(format!(
+            "Failed to create disk image '{}': {}",
+            output_path.display(),
+            e
+        ))
+    })?;
+    file.set_len(table.size()).map_err(|e| {
+        Error::Io(format!(
+            "Failed to size disk image '{}': {}",
+            output_path.display(),
+            e
+        ))
+    })?;
+
+    let layout = device.partition_layout()?;
+    for placed in &layout {
+        let partition = placed.partition;
+        let Some(image_name) = &partition.image else {
+            continue;
+        };
+        let label = partition.name.as_deref().unwrap_or(image_name);
+        let image = device.images.get(image_name).ok_or_else(|| {
+            Error::InvalidManifest(format!(
+                "Storage device '{device_name}': partition '{label}' uses image '{image_name}', which is not defined in images"
+            ))
+        })?;
+        let source = image_path(image, input_dirs, build_dir)?;
+
+        for offset in [Some(placed.offset), placed.redundant_offset]
+            .into_iter()
+            .flatten()
+        {
+            if verbose {
+                log_debug(&format!(
+                    "Writing '{}' to partition '{label}' at byte {offset}.",
+                    source.display()
+                ));
+            }
+            write_image_at(&source, &mut file, offset, placed.size)
+                .map_err(|e| e.context(&format!("Partition '{label}'")))?;
+        }
+    }
+
+    table.write_to(&mut file).map_err(Error::Io)?;
+
+    log_success(&format!(
+        "Created {} disk image '{}' with {} partition(s).",
+        table.name(),
+        output_path.display(),
+        layout.len()
+    ));
+    Ok(())
+}
+
+/// Copy the file at `source` into `disk` at `offset`; it must fit in `size` bytes
+fn write_image_at(source: &Path, disk: &mut fs::File, offset: u64, size: u64) -> Result<(), Error> {
+    let mut image = fs::File::open(source).map_err(|e| {
+        Error::Io(format!(
+            "Failed to open image '{}': {}",
+            source.display(),
+            e
+        ))
+    })?;
+    let image_size = image
+        .metadata()
+        .map_err(|e| {
+            Error::Io(format!(
+                "Failed to read image '{}': {}",
+                source.display(),
+                e
+            ))
+        })?
+        .len();
+    if image_size > size {
+        return Err(Error::InvalidManifest(format!(
+            "image '{}' is {image_size} bytes, larger than the partition ({size} bytes)",
+            source.display()
+        )));
+    }
+
+    disk.seek(SeekFrom::Start(offset))
+        .and_then(|_| io::copy(&mut image, disk))
+        .map_err(|e| {
+            Error::Io(format!(
+                "Failed to write image '{}' to the disk image: {}",
+                source.display(),
+                e
+            ))
+        })?;
+    Ok(())
+}
+
 fn calculate_avocado_env_vars(
     _device_name: &str,
     device: &crate::manifest::StorageDevice,
@@ -504,7 +679,7 @@ fn calculate_avocado_env_vars(
     );
 
     // Device Info
-    let block_size = device.block_size.unwrap_or(512);
+    let block_size = device.block_size();
 
     // Set disk-specific environment variables if present on storage device
     if let Some(device_block_size) = device.block_size {
@@ -516,62 +691,25 @@ fn calculate_avocado_env_vars(
     if let Some(device_uuid) = &device.uuid {
         env_vars.insert("AVOCADO_DISK_UUID".to_string(), device_uuid.clone());
     }
+    if let Some(capacity) = device.capacity()? {
+        env_vars.insert(
+            "AVOCADO_DISK_BLOCKS".to_string(),
+            (capacity / block_size).to_string(),
+        );
+    }
 
     // Dynamically set image environment variables with full paths
     for (image_name, image) in &device.images {
         let name_upper = image_name.to_uppercase();
         let env_var_name = format!("AVOCADO_IMAGE_{name_upper}");
 
-        // Determine the full path based on image type
-        let image_path = match image {
-            Image::String(filename) => {
-                // Input files - search in input directories
-                find_file_in_dirs(filename, input_dirs)
-                    .ok_or_else(|| {
-                        format!("Image file '{filename}' not found in any input directory")
-                    })?
-                    .to_string_lossy()
-                    .to_string()
-            }
-            Image::Object {
-                out,
-                build_args: Some(_),
-                ..
-            } => {
-                // Generated files (with build_args) are in the build directory
-                build_dir.join(out).to_string_lossy().to_string()
-            }
-            Image::Object {
-                out,
-                build_args: None,
-                ..
-            } => {
-                // Object files without build_args are input files - search in input directories
-                find_file_in_dirs(out, input_dirs)
-                    .ok_or_else(|| format!("Image file '{out}' not found in any input directory"))?
-                    .to_string_lossy()
-                    .to_string()
-            }
-        };
-
-        env_vars.insert(env_var_name, image_path);
+        let image_path = image_path(image, input_dirs, build_dir).map_err(|e| e.to_string())?;
+        env_vars.insert(env_var_name, image_path.to_string_lossy().to_string());
     }
 
     // Calculate partition offsets and sizes from the partition table
-    let mut current_offset = 0u64;
-
-    for partition in &device.partitions {
-        let partition_offset = if let Some(offset) = partition.offset {
-            convert_to_blocks(
-                offset,
-                partition.offset_unit.as_deref().unwrap_or("blocks"),
-                block_size,
-            )?
-        } else {
-            current_offset
-        };
-
-        let partition_size = convert_to_blocks(partition.size, &partition.size_unit, block_size)?;
+    for placed in device.partition_layout()? {
+        let partition = placed.partition;
 
         // Set partition variables based on the partition name
         if let Some(partition_name) = &partition.name {
@@ -580,28 +718,20 @@ fn calculate_avocado_env_vars(
             // Set offset for this partition
             env_vars.insert(
                 format!("AVOCADO_PARTITION_{name_upper}_OFFSET"),
-                partition_offset.to_string(),
+                (placed.offset / block_size).to_string(),
             );
 
             // Set size in blocks for this partition
             env_vars.insert(
                 format!("AVOCADO_PARTITION_{name_upper}_BLOCKS"),
-                partition_size.to_string(),
+                (placed.size / block_size).to_string(),
             );
 
             // Set redundant offset if present
-            if let Some(offset_redundant) = partition.offset_redundant {
-                let redundant_offset = convert_to_blocks(
-                    offset_redundant,
-                    partition
-                        .offset_redundant_unit
-                        .as_deref()
-                        .unwrap_or("blocks"),
-                    block_size,
-                )?;
+            if let Some(redundant_offset) = placed.redundant_offset {
                 env_vars.insert(
                     format!("AVOCADO_PARTITION_{name_upper}_OFFSET_REDUND"),
-                    redundant_offset.to_string(),
+                    (redundant_offset / block_size).to_string(),
                 );
             }
 
@@ -613,29 +743,11 @@ fn calculate_avocado_env_vars(
                 );
             }
         }
-
-        current_offset = partition_offset + partition_size;
     }
 
     Ok(env_vars)
 }
 
-fn convert_to_blocks(size: i64, unit: &str, block_size: u32) -> Result<u64, String> {
-    let bytes = match unit.to_lowercase().as_str() {
-        "bytes" | "byte" | "b" => size as u64,
-        "blocks" | "block" => return Ok(size as u64),
-        "kilobytes" | "kilobyte" | "kb" => (size as u64) * 1024,
-        "kibibytes" | "kibibyte" | "kib" => (size as u64) * 1024,
-        "megabytes" | "megabyte" | "mb" => (size as u64) * 1024 * 1024,
-        "mebibytes" | "mebibyte" | "mib" => (size as u64) * 1024 * 1024,
-        "gigabytes" | "gigabyte" | "gb" => (size as u64) * 1024 * 1024 * 1024,
-        "gibibytes" | "gibibyte" | "gib" => (size as u64) * 1024 * 1024 * 1024,
-        _ => return Err(format!("Unsupported size unit: {unit}")),
-    };
-
-    Ok(bytes / (block_size as u64))
-}
-
 fn read_os_release_info(
     input_dirs: &[PathBuf],
 ) -> Result<(String, String, String, String), String> {
@@ -702,7 +814,7 @@ fn execute_provision_with_profile(
     input_dirs: &[PathBuf],
     build_dir: &Path,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     // First check for legacy provision script in runtime
     if let Some(provision_file) = &manifest.runtime.provision
         && manifest.provision.is_none()
@@ -735,7 +847,9 @@ fn execute_provision_with_profile(
             .get_provision_default()
             .map(|s| s.to_string())
             .ok_or_else(|| {
-                "[ERROR] No provision profile specified and no default found.".to_string()
+                Error::InvalidManifest(
+                    "No provision profile specified and no default found.".to_string(),
+                )
             })
     })?;
 
@@ -746,10 +860,14 @@ fn execute_provision_with_profile(
     // Get the specific profile
     let profile = manifest
         .get_provision_profile(&profile_name)
-        .ok_or_else(|| format!("[ERROR] Provision profile '{profile_name}' not found."))?;
+        .ok_or_else(|| {
+            Error::InvalidManifest(format!("Provision profile '{profile_name}' not found."))
+        })?;
 
     // Resolve environment variables from the profile
-    let resolved_envs = provision.resolve_envs(profile)?;
+    let resolved_envs = provision
+        .resolve_envs(profile)
+        .map_err(Error::InvalidManifest)?;
     let expanded_envs = provision.expand_env_vars(&resolved_envs);
 
     if verbose {
@@ -780,9 +898,11 @@ fn execute_provision_script(
     build_dir: &Path,
     verbose: bool,
     additional_envs: &HashMap<String, String>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let provision_path = find_file_in_dirs(provision_file, input_dirs).ok_or_else(|| {
-        format!("[ERROR] Provision file '{provision_file}' not found in any input directory.")
+        Error::MissingInput(format!(
+            "Provision file '{provision_file}' not found in any input directory."
+        ))
     })?;
 
     // Use the directory containing the manifest as the working directory
@@ -816,13 +936,13 @@ fn execute_provision_script(
     // Set default environment variables for the provision script
     let manifest_path_canonical = manifest_path
         .canonicalize()
-        .map_err(|e| format!("[ERROR] Failed to resolve manifest path: {e}"))?;
+        .map_err(|e| format!("Failed to resolve manifest path: {e}"))?;
     let build_dir_canonical = build_dir
         .canonicalize()
-        .map_err(|e| format!("[ERROR] Failed to resolve build directory path: {e}"))?;
+        .map_err(|e| format!("Failed to resolve build directory path: {e}"))?;
     let input_dir_canonical = input_dir
         .canonicalize()
-        .map_err(|e| format!("[ERROR] Failed to resolve input directory path: {e}"))?;
+        .map_err(|e| format!("Failed to resolve input directory path: {e}"))?;
     command.env("AVOCADO_STONE_MANIFEST", manifest_path_canonical);
     command.env("AVOCADO_STONE_BUILD_DIR", build_dir_canonical);
     command.env("AVOCADO_STONE_DATA_DIR", input_dir_canonical);
@@ -855,11 +975,10 @@ fn execute_provision_script(
         .map_err(|e| format!("Failed to wait for provision script '{provision_file}': {e}"))?;
 
     if !status.success() {
-        return Err(format!(
-            "Provision script '{}' failed with exit code {}",
-            provision_file,
-            status.code().unwrap_or(-1)
-        ));
+        return Err(Error::ScriptFailed {
+            script: provision_file.to_string(),
+            code: status.code(),
+        });
     }
 
     log_success(&format!(
@@ -1,5 +1,6 @@
+use crate::error::Error;
 use crate::log::*;
-use crate::manifest::Manifest;
+use crate::manifest::{Manifest, ManifestFormat, ManifestOptions, parse_var};
 use clap::Args;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -15,6 +16,14 @@ pub struct ValidateArgs {
     )]
     pub manifest: PathBuf,
 
+    /// Manifest file format (default: from the file extension, else JSON)
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<ManifestFormat>,
+
+    /// Value for a ${NAME} reference in the manifest (can be specified multiple times)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
     /// Path to the input directory (can be specified multiple times for search priority)
     #[arg(
         short = 'i',
@@ -26,8 +35,12 @@ pub struct ValidateArgs {
 }
 
 impl ValidateArgs {
-    pub fn execute(&self) -> Result<(), String> {
-        validate_command(&self.manifest, &self.input_dirs)
+    pub fn execute(&self) -> Result<(), Error> {
+        validate_command(
+            &self.manifest,
+            &ManifestOptions::new(self.format, &self.vars),
+            &self.input_dirs,
+        )
     }
 }
 
@@ -42,16 +55,20 @@ fn find_file_in_dirs(filename: &str, input_dirs: &[PathBuf]) -> Option<PathBuf>
     None
 }
 
-pub fn validate_command(manifest_path: &Path, input_dirs: &[PathBuf]) -> Result<(), String> {
+pub fn validate_command(
+    manifest_path: &Path,
+    manifest_options: &ManifestOptions,
+    input_dirs: &[PathBuf],
+) -> Result<(), Error> {
     // Check if manifest file exists
     if !manifest_path.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "Manifest file '{}' not found.",
             manifest_path.display()
-        ));
+        )));
     }
 
-    let manifest = Manifest::from_file(manifest_path)?;
+    let manifest = Manifest::load(manifest_path, manifest_options)?;
 
     // Validate all files referenced in the manifest
     let mut missing_files = Vec::new();
@@ -69,7 +86,8 @@ pub fn validate_command(manifest_path: &Path, input_dirs: &[PathBuf]) -> Result<
         ));
     }
 
-    // Check provision profiles and their scripts
+    // Check provision profiles and their scripts. A provision_default that names no
+    // profile is reported by the manifest checks below.
     if let Some(provision) = &manifest.provision {
         for (profile_name, profile) in &provision.profiles {
             if find_file_in_dirs(&profile.script, input_dirs).is_none() {
@@ -77,25 +95,6 @@ pub fn validate_command(manifest_path: &Path, input_dirs: &[PathBuf]) -> Result<
                     .push((format!("Profile '{profile_name}'"), profile.script.clone()));
             }
         }
-
-        // Check if provision_default references a valid profile
-        if let Some(default_profile_name) = &manifest.runtime.provision_default
-            && manifest
-                .get_provision_profile(default_profile_name)
-                .is_none()
-        {
-            missing_provision_files.push((
-                "Default profile reference".to_string(),
-                format!("Profile '{default_profile_name}' not found in provision.profiles"),
-            ));
-        }
-        // Note: We don't check if the default profile's script exists here
-        // because that will already be caught when validating all profiles above
-    } else if manifest.runtime.provision_default.is_some() {
-        missing_provision_files.push((
-            "Default profile reference".to_string(),
-            "provision_default specified but no provision section found".to_string(),
-        ));
     }
 
     // Process each storage device
@@ -175,14 +174,17 @@ pub fn validate_command(manifest_path: &Path, input_dirs: &[PathBuf]) -> Result<
         }
     }
 
+    // Check references, names, GUIDs and the partition layout
+    let problems = manifest.check();
+
     // Report results
-    if !missing_files.is_empty()
-        || !missing_device_files.is_empty()
-        || !missing_provision_files.is_empty()
-    {
-        let total_missing =
-            missing_files.len() + missing_device_files.len() + missing_provision_files.len();
-        let mut error_msg = format!("Validation failed. {total_missing} file(s) not found:");
+    let total_missing =
+        missing_files.len() + missing_device_files.len() + missing_provision_files.len();
+    if total_missing > 0 || !problems.is_empty() {
+        let mut error_msg = "Validation failed.".to_string();
+        if total_missing > 0 {
+            error_msg.push_str(&format!(" {total_missing} file(s) not found:"));
+        }
 
         // Report missing provision files
         for (provision_type, filename) in missing_provision_files {
@@ -209,7 +211,15 @@ pub fn validate_command(manifest_path: &Path, input_dirs: &[PathBuf]) -> Result<
             }
         }
 
-        return Err(error_msg);
+        if problems.is_empty() {
+            return Err(Error::MissingInput(error_msg));
+        }
+        error_msg.push_str(if total_missing > 0 { "\n" } else { " " });
+        error_msg.push_str(&format!("{} problem(s) in the manifest:", problems.len()));
+        for problem in problems {
+            error_msg.push_str(&format!("\n  {problem}"));
+        }
+        return Err(Error::InvalidManifest(error_msg));
     }
 
     log_success("Validated.");
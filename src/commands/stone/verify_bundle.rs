@@ -0,0 +1,74 @@
+use super::inspect_bundle::{read_bundle, verify_bundle_entries};
+use crate::bundle::Bundle;
+use crate::error::Error;
+use crate::log::*;
+use crate::signing;
+use clap::Args;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct VerifyBundleArgs {
+    /// Path to the .aos bundle file
+    #[arg(value_name = "BUNDLE")]
+    pub bundle: PathBuf,
+
+    /// Ed25519 public key to check the bundle signature against (PEM, hex or raw)
+    #[arg(long = "pubkey", value_name = "PATH")]
+    pub pubkey: PathBuf,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+impl VerifyBundleArgs {
+    pub fn execute(&self) -> Result<(), Error> {
+        verify_bundle_command(&self.bundle, &self.pubkey, self.verbose)
+    }
+}
+
+pub fn verify_bundle_command(
+    bundle_path: &Path,
+    pubkey_path: &Path,
+    verbose: bool,
+) -> Result<(), Error> {
+    if !bundle_path.exists() {
+        return Err(Error::MissingInput(format!(
+            "Bundle file '{}' not found.",
+            bundle_path.display()
+        )));
+    }
+
+    let pubkey = signing::load_verifying_key(pubkey_path)?;
+    let contents = read_bundle(bundle_path, verbose)?;
+
+    // The signature covers the exact bundle.json bytes stored in the archive,
+    // so it must be checked before bundle.json is trusted for artifact hashes.
+    let signature = contents.signature.as_deref().ok_or_else(|| {
+        format!(
+            "Bundle '{}' is not signed (no {} entry).",
+            bundle_path.display(),
+            signing::SIGNATURE_ENTRY
+        )
+    })?;
+    signing::verify(&pubkey, &contents.bundle_json, signature)
+        .map_err(|e| format!("Bundle signature verification failed: {e}"))?;
+    log_info("Bundle signature verified.");
+
+    let bundle = Bundle::from_slice(&contents.bundle_json)?;
+
+    let problems = verify_bundle_entries(&bundle, &contents.entries);
+    if !problems.is_empty() {
+        let mut error_msg = format!(
+            "Bundle verification failed. {} problem(s) found:",
+            problems.len()
+        );
+        for problem in problems {
+            error_msg.push_str(&format!("\n  {problem}"));
+        }
+        return Err(Error::Other(error_msg));
+    }
+
+    log_success(&format!("Verified bundle '{}'.", bundle_path.display()));
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+/// Why a stone operation failed. Each kind exits the CLI with its own code;
+/// see [`Error::exit_code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The manifest could not be read, parsed or resolved
+    ManifestParse(String),
+    /// The manifest parsed but contradicts itself
+    InvalidManifest(String),
+    /// A file the operation needs does not exist
+    MissingInput(String),
+    /// An external tool is not installed
+    ToolNotFound { tool: String },
+    /// An external tool exited unsuccessfully; `code` is None when it was killed by a signal
+    ToolFailed { tool: String, code: Option<i32> },
+    /// The provision script exited unsuccessfully
+    ScriptFailed { script: String, code: Option<i32> },
+    /// Reading or writing a file failed
+    Io(String),
+    /// Any other failure
+    Other(String),
+}
+
+impl Error {
+    /// Process exit code of the CLI for this error.
+    ///
+    /// | code | error |
+    /// |------|-------|
+    /// | 1 | [`Error::Other`] |
+    /// | 2 | command line usage (reported by clap, never an `Error`) |
+    /// | 3 | [`Error::ManifestParse`] |
+    /// | 4 | [`Error::InvalidManifest`] |
+    /// | 5 | [`Error::MissingInput`] |
+    /// | 6 | [`Error::ToolNotFound`] |
+    /// | 7 | [`Error::ToolFailed`] |
+    /// | 8 | [`Error::Io`] |
+    ///
+    /// [`Error::ScriptFailed`] exits with the script's own code, or 1 when the
+    /// script was killed by a signal.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Other(_) => 1,
+            Error::ManifestParse(_) => 3,
+            Error::InvalidManifest(_) => 4,
+            Error::MissingInput(_) => 5,
+            Error::ToolNotFound { .. } => 6,
+            Error::ToolFailed { .. } => 7,
+            Error::Io(_) => 8,
+            Error::ScriptFailed { code, .. } => code.filter(|code| *code != 0).unwrap_or(1),
+        }
+    }
+
+    /// Prefix the message with what was being done, keeping the kind of error
+    pub fn context(self, context: &str) -> Self {
+        let with_context = |message: String| format!("{context}: {message}");
+        match self {
+            Error::ManifestParse(message) => Error::ManifestParse(with_context(message)),
+            Error::InvalidManifest(message) => Error::InvalidManifest(with_context(message)),
+            Error::MissingInput(message) => Error::MissingInput(with_context(message)),
+            Error::Io(message) => Error::Io(with_context(message)),
+            Error::Other(message) => Error::Other(with_context(message)),
+            // These describe themselves
+            error @ (Error::ToolNotFound { .. }
+            | Error::ToolFailed { .. }
+            | Error::ScriptFailed { .. }) => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ManifestParse(message)
+            | Error::InvalidManifest(message)
+            | Error::MissingInput(message)
+            | Error::Io(message)
+            | Error::Other(message) => f.write_str(message),
+            Error::ToolNotFound { tool } => write!(
+                f,
+                "{tool} command not found. Please install {tool} to build firmware packages."
+            ),
+            Error::ToolFailed { tool, code } => match code {
+                Some(code) => write!(f, "{tool} command failed with exit code: {code}"),
+                None => write!(f, "{tool} command was terminated by a signal"),
+            },
+            Error::ScriptFailed { script, code } => match code {
+                Some(code) => write!(
+                    f,
+                    "Provision script '{script}' failed with exit code {code}"
+                ),
+                None => write!(f, "Provision script '{script}' was terminated by a signal"),
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Errors still reported as plain messages are [`Error::Other`]
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            Error::Other(String::new()),
+            Error::ManifestParse(String::new()),
+            Error::InvalidManifest(String::new()),
+            Error::MissingInput(String::new()),
+            Error::ToolNotFound {
+                tool: "fwup".to_string(),
+            },
+            Error::ToolFailed {
+                tool: "fwup".to_string(),
+                code: Some(1),
+            },
+            Error::Io(String::new()),
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes, vec![1, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_script_exit_code_is_propagated() {
+        let failed = |code| Error::ScriptFailed {
+            script: "provision.sh".to_string(),
+            code,
+        };
+        assert_eq!(failed(Some(42)).exit_code(), 42);
+        assert_eq!(failed(None).exit_code(), 1);
+        assert_eq!(
+            failed(Some(42)).to_string(),
+            "Provision script 'provision.sh' failed with exit code 42"
+        );
+    }
+}
@@ -1,8 +1,11 @@
+use std::cell::Cell;
 use std::fs::{self, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
 
+use crate::error::Error;
+use crate::log::*;
 use serde::{Deserialize, Serialize};
 
 // Custom trait that combines Read, Write, and Seek
@@ -50,9 +53,13 @@ pub struct FatImageOptions {
     pub manifest_path: PathBuf,
     pub base_path: PathBuf,
     pub output_path: PathBuf,
-    pub size_mb: u64,
+    /// Size of the image in bytes
+    pub size: u64,
     pub label: String,
     pub fat_type: FatType,
+    /// Fixed timestamp (seconds since the Unix epoch) for every directory entry.
+    /// When unset, entries are stamped with the current local time.
+    pub timestamp: Option<u64>,
     pub verbose: bool,
 }
 
@@ -62,9 +69,10 @@ impl Default for FatImageOptions {
             manifest_path: PathBuf::from("manifest.json"),
             base_path: PathBuf::from("."),
             output_path: PathBuf::from("output.img"),
-            size_mb: 16,
+            size: 16 << 20,
             label: "FATFS".to_string(),
             fat_type: FatType::default(),
+            timestamp: None,
             verbose: false,
         }
     }
@@ -91,8 +99,8 @@ impl FatImageOptions {
         self
     }
 
-    pub fn with_size_mebibytes(mut self, size_mb: u64) -> Self {
-        self.size_mb = size_mb;
+    pub fn with_size_bytes(mut self, size: u64) -> Self {
+        self.size = size;
         self
     }
 
@@ -107,6 +115,11 @@ impl FatImageOptions {
         self
     }
 
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
     pub fn with_verbose(mut self, verbose: bool) -> Self {
         self.verbose = verbose;
         self
@@ -114,17 +127,17 @@ impl FatImageOptions {
 }
 
 #[allow(dead_code)]
-pub fn list_fat_files(fat_image_path: &Path) -> Result<Vec<String>, String> {
+pub fn list_fat_files(fat_image_path: &Path) -> Result<Vec<String>, Error> {
     let img_file = fs::File::open(fat_image_path).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to open FAT image '{}': {}",
             fat_image_path.display(),
             e
-        )
+        ))
     })?;
 
     let fs = fatfs::FileSystem::new(img_file, fatfs::FsOptions::new())
-        .map_err(|e| format!("Failed to read FAT filesystem: {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to read FAT filesystem: {e}")))?;
 
     let root_dir = fs.root_dir();
     let mut files = Vec::new();
@@ -139,9 +152,9 @@ pub fn list_directory_recursive(
     dir: &fatfs::Dir<fs::File>,
     path_prefix: &str,
     files: &mut Vec<String>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     for entry in dir.iter() {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {e}"))?;
+        let entry = entry.map_err(|e| Error::Io(format!("Failed to read directory entry: {e}")))?;
         let name = entry.file_name();
 
         if entry.is_dir() {
@@ -152,7 +165,7 @@ pub fn list_directory_recursive(
 
             let subdir = dir
                 .open_dir(&name)
-                .map_err(|e| format!("Failed to open directory '{name}': {e}"))?;
+                .map_err(|e| Error::Io(format!("Failed to open directory '{name}': {e}")))?;
 
             let new_prefix = if path_prefix.is_empty() {
                 name
@@ -175,37 +188,43 @@ pub fn list_directory_recursive(
 }
 
 #[allow(dead_code)]
-pub fn create_fat_image(options: &FatImageOptions) -> Result<(), String> {
+pub fn create_fat_image(options: &FatImageOptions) -> Result<(), Error> {
     let mut base_path = options.base_path.clone();
     if base_path.is_relative() {
         base_path = std::env::current_dir()
-            .map_err(|e| format!("Failed to get current directory: {e}"))?
+            .map_err(|e| Error::Io(format!("Failed to get current directory: {e}")))?
             .join(&base_path);
     }
 
     if options.verbose {
-        println!("Reading manifest: {}", options.manifest_path.display());
+        log_info(&format!(
+            "Reading manifest: {}",
+            options.manifest_path.display()
+        ));
     }
 
     let json_str = fs::read_to_string(&options.manifest_path).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to read manifest file '{}': {}",
             options.manifest_path.display(),
             e
-        )
+        ))
     })?;
 
     let manifest: Manifest = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Failed to parse manifest file: {e}"))?;
+        .map_err(|e| Error::Other(format!("Failed to parse manifest file: {e}")))?;
 
     if options.verbose {
-        println!("Generating FAT image: {}", options.output_path.display());
+        log_info(&format!(
+            "Generating FAT image: {}",
+            options.output_path.display()
+        ));
     }
 
     generate_fat_image(options, &manifest, &base_path)?;
 
     if options.verbose {
-        println!("FAT image generation complete.");
+        log_info("FAT image generation complete.");
     }
 
     Ok(())
@@ -216,7 +235,11 @@ fn generate_fat_image(
     options: &FatImageOptions,
     manifest: &Manifest,
     base: &Path,
-) -> Result<(), String> {
+) -> Result<(), Error> {
+    if options.size == 0 {
+        return Err("FAT image size must be positive".into());
+    }
+
     // Create and preallocate output file
     let img_file = OpenOptions::new()
         .read(true)
@@ -225,16 +248,16 @@ fn generate_fat_image(
         .truncate(true)
         .open(&options.output_path)
         .map_err(|e| {
-            format!(
+            Error::Io(format!(
                 "Failed to open output file '{}': {}",
                 options.output_path.display(),
                 e
-            )
+            ))
         })?;
 
     img_file
-        .set_len(options.size_mb * 1024 * 1024)
-        .map_err(|e| format!("Failed to set image size: {e}"))?;
+        .set_len(options.size)
+        .map_err(|e| Error::Io(format!("Failed to set image size: {e}")))?;
 
     // Keep the file in a box to satisfy the 'static lifetime requirement
     let mut boxed_file: Box<dyn ReadWriteSeek> = Box::new(img_file);
@@ -255,23 +278,28 @@ fn generate_fat_image(
         .fat_type(fat_type);
 
     fatfs::format_volume(&mut boxed_file, format_options)
-        .map_err(|e| format!("Failed to format volume: {e}"))?;
+        .map_err(|e| Error::Other(format!("Failed to format volume: {e}")))?;
 
     // Rewind the file for filesystem operations
     boxed_file
         .seek(SeekFrom::Start(0))
-        .map_err(|e| format!("Failed to seek in image file: {e}"))?;
-
-    // Create filesystem
-    let fs = fatfs::FileSystem::new(boxed_file, fatfs::FsOptions::new())
-        .map_err(|e| format!("Failed to create filesystem: {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to seek in image file: {e}")))?;
+
+    // Create filesystem, pinning entry timestamps when a fixed time was requested
+    let mut fs_options = fatfs::FsOptions::new();
+    let _pinned_time = options.timestamp.map(|timestamp| {
+        fs_options = fs_options.time_provider(&FIXED_TIME_PROVIDER);
+        PinnedTime::new(dos_date_time(timestamp))
+    });
+    let fs = fatfs::FileSystem::new(boxed_file, fs_options)
+        .map_err(|e| Error::Io(format!("Failed to create filesystem: {e}")))?;
     let root_dir = fs.root_dir();
 
     // Create directories first
     if let Some(directories) = &manifest.directories {
         for dir_path in directories {
             if options.verbose {
-                println!("Creating directory: {dir_path}");
+                log_debug(&format!("Creating directory: {dir_path}"));
             }
             create_directory_path(&root_dir, dir_path)?;
         }
@@ -289,7 +317,7 @@ fn generate_fat_image(
             .unwrap_or_else(|| entry.filename.as_ref().unwrap());
 
         if options.verbose {
-            println!("Adding file: {input_path} -> {output_path}");
+            log_debug(&format!("Adding file: {input_path} -> {output_path}"));
         }
 
         add_file_to_fat(&root_dir, base, input_path, output_path)?;
@@ -298,11 +326,94 @@ fn generate_fat_image(
     Ok(())
 }
 
+thread_local! {
+    static PINNED_DATE_TIME: Cell<Option<fatfs::DateTime>> = const { Cell::new(None) };
+}
+
+/// Pins the date and time `FIXED_TIME_PROVIDER` reports on this thread until dropped
+struct PinnedTime(Option<fatfs::DateTime>);
+
+impl PinnedTime {
+    fn new(date_time: fatfs::DateTime) -> Self {
+        Self(PINNED_DATE_TIME.replace(Some(date_time)))
+    }
+}
+
+impl Drop for PinnedTime {
+    fn drop(&mut self) {
+        PINNED_DATE_TIME.set(self.0);
+    }
+}
+
+/// Time provider that stamps every directory entry with the pinned date and
+/// time. fatfs only takes a `'static` provider, so the time lives beside it.
+#[derive(Debug)]
+struct FixedTimeProvider;
+
+static FIXED_TIME_PROVIDER: FixedTimeProvider = FixedTimeProvider;
+
+impl fatfs::TimeProvider for FixedTimeProvider {
+    fn get_current_date(&self) -> fatfs::Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> fatfs::DateTime {
+        PINNED_DATE_TIME.get().unwrap_or_else(|| dos_date_time(0))
+    }
+}
+
+/// Convert seconds since the Unix epoch (UTC) to a DOS date and time.
+/// DOS timestamps cover 1980 through 2107; values outside are clamped.
+fn dos_date_time(unix_seconds: u64) -> fatfs::DateTime {
+    let days = (unix_seconds / 86_400) as i64;
+    let secs_of_day = unix_seconds % 86_400;
+
+    // Days since 1970-01-01 to a proleptic Gregorian civil date
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u16;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u16;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let (date, time) = if year < 1980 {
+        ((1980, 1, 1), (0, 0, 0))
+    } else if year > 2107 {
+        ((2107, 12, 31), (23, 59, 58))
+    } else {
+        (
+            (year as u16, month, day),
+            (
+                (secs_of_day / 3600) as u16,
+                (secs_of_day / 60 % 60) as u16,
+                (secs_of_day % 60) as u16,
+            ),
+        )
+    };
+
+    fatfs::DateTime {
+        date: fatfs::Date {
+            year: date.0,
+            month: date.1,
+            day: date.2,
+        },
+        time: fatfs::Time {
+            hour: time.0,
+            min: time.1,
+            sec: time.2,
+            millis: 0,
+        },
+    }
+}
+
 #[allow(dead_code)]
 fn create_directory_path(
     root_dir: &fatfs::Dir<Box<dyn ReadWriteSeek>>,
     dir_path: &str,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let components_vec: Vec<_> = Path::new(dir_path).components().collect();
     let mut dir = root_dir.clone();
 
@@ -314,7 +425,7 @@ fn create_directory_path(
         dir = dir
             .create_dir(name)
             .or_else(|_| dir.open_dir(name))
-            .map_err(|e| format!("Failed to create directory '{name}': {e}"))?;
+            .map_err(|e| Error::Io(format!("Failed to create directory '{name}': {e}")))?;
     }
 
     Ok(())
@@ -326,14 +437,14 @@ fn add_file_to_fat(
     base: &Path,
     input_path: &str,
     output_path: &str,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let full_input_path = base.join(input_path);
     let file_data = fs::read(&full_input_path).map_err(|e| {
-        format!(
+        Error::Io(format!(
             "Failed to read input file '{}': {}",
             full_input_path.display(),
             e
-        )
+        ))
     })?;
 
     let components_vec: Vec<_> = Path::new(output_path).components().collect();
@@ -348,7 +459,7 @@ fn add_file_to_fat(
         dir = dir
             .create_dir(name)
             .or_else(|_| dir.open_dir(name))
-            .map_err(|e| format!("Failed to create directory '{name}': {e}"))?;
+            .map_err(|e| Error::Io(format!("Failed to create directory '{name}': {e}")))?;
     }
 
     // Create and write the file
@@ -359,11 +470,52 @@ fn add_file_to_fat(
 
     let mut fat_file = dir
         .create_file(file_name)
-        .map_err(|e| format!("Failed to create file '{file_name}': {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to create file '{file_name}': {e}")))?;
 
     fat_file
         .write_all(&file_data)
-        .map_err(|e| format!("Failed to write to file '{file_name}': {e}"))?;
+        .map_err(|e| Error::Io(format!("Failed to write to file '{file_name}': {e}")))?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dos_date_time() {
+        // 2023-11-14 22:13:20 UTC
+        let dt = dos_date_time(1_700_000_000);
+        assert_eq!((dt.date.year, dt.date.month, dt.date.day), (2023, 11, 14));
+        assert_eq!((dt.time.hour, dt.time.min, dt.time.sec), (22, 13, 20));
+
+        // 2000-02-29 00:00:00 UTC
+        let dt = dos_date_time(951_782_400);
+        assert_eq!((dt.date.year, dt.date.month, dt.date.day), (2000, 2, 29));
+
+        // Before the DOS epoch clamps to 1980-01-01
+        let dt = dos_date_time(0);
+        assert_eq!((dt.date.year, dt.date.month, dt.date.day), (1980, 1, 1));
+        assert_eq!((dt.time.hour, dt.time.min, dt.time.sec), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_pinned_time() {
+        use fatfs::TimeProvider;
+
+        let outer = dos_date_time(951_782_400);
+        let inner = dos_date_time(1_700_000_000);
+        {
+            let _outer = PinnedTime::new(outer);
+            {
+                let _inner = PinnedTime::new(inner);
+                assert_eq!(FIXED_TIME_PROVIDER.get_current_date_time(), inner);
+            }
+            // Dropping a pin restores the one before it
+            assert_eq!(FIXED_TIME_PROVIDER.get_current_date_time(), outer);
+            assert_eq!(FIXED_TIME_PROVIDER.get_current_date(), outer.date);
+        }
+        assert_eq!(PINNED_DATE_TIME.get(), None);
+    }
+}
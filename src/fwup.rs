@@ -1,6 +1,7 @@
+use crate::error::Error;
 use crate::log::*;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, ExitStatus};
 
 /// Options for creating a firmware update package with fwup
 #[derive(Debug, Clone)]
@@ -48,24 +49,24 @@ impl FwupOptions {
 }
 
 #[allow(dead_code)]
-pub fn create_firmware_package(options: &FwupOptions) -> Result<(), String> {
+pub fn create_firmware_package(options: &FwupOptions) -> Result<(), Error> {
     // Validate inputs
     if !options.config_file.exists() {
-        return Err(format!(
+        return Err(Error::MissingInput(format!(
             "Configuration file '{}' not found.",
             options.config_file.display()
-        ));
+        )));
     }
 
     // Create output directory if it doesn't exist
     if let Some(parent) = options.output_file.parent()
         && let Err(e) = std::fs::create_dir_all(parent)
     {
-        return Err(format!(
+        return Err(Error::Io(format!(
             "Failed to create output directory '{}': {}",
             parent.display(),
             e
-        ));
+        )));
     }
 
     // Build the fwup command
@@ -109,30 +110,34 @@ pub fn create_firmware_package(options: &FwupOptions) -> Result<(), String> {
                 ));
                 Ok(())
             } else {
-                Err(format!(
-                    "fwup command failed with exit code: {}",
-                    exit_status.code().unwrap_or(-1)
-                ))
+                Err(exit_error(exit_status))
             }
         }
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                Err(
-                    "fwup command not found. Please install fwup to build firmware packages."
-                        .to_string(),
-                )
-            } else {
-                Err(format!("Failed to execute fwup command: {e}"))
-            }
+        Err(e) => Err(spawn_error(e)),
+    }
+}
+
+/// Error for a fwup command that could not be started
+pub(crate) fn spawn_error(e: std::io::Error) -> Error {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        Error::ToolNotFound {
+            tool: "fwup".to_string(),
         }
+    } else {
+        Error::Other(format!("Failed to execute fwup command: {e}"))
+    }
+}
+
+/// Error for a fwup command that exited unsuccessfully
+pub(crate) fn exit_error(status: ExitStatus) -> Error {
+    Error::ToolFailed {
+        tool: "fwup".to_string(),
+        code: status.code(),
     }
 }
 
 #[allow(dead_code)]
-pub fn create_firmware_package_simple<P1, P2>(
-    config_file: P1,
-    output_file: P2,
-) -> Result<(), String>
+pub fn create_firmware_package_simple<P1, P2>(config_file: P1, output_file: P2) -> Result<(), Error>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
@@ -146,7 +151,7 @@ pub fn create_firmware_package_in_dir<P1, P2, P3>(
     config_file: P1,
     output_file: P2,
     working_dir: P3,
-) -> Result<(), String>
+) -> Result<(), Error>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
@@ -193,8 +198,9 @@ mod tests {
         let options = FwupOptions::new(&config_file, &output_file);
         let result = create_firmware_package(&options);
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found"));
+        assert!(
+            matches!(result, Err(Error::MissingInput(message)) if message.contains("not found"))
+        );
     }
 
     #[test]
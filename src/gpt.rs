@@ -0,0 +1,565 @@
+//! GUID Partition Table reader and writer, following the UEFI specification (chapter 5).
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+/// Number of entries in the partition entry array
+pub const ENTRY_COUNT: u64 = 128;
+/// Size of one partition entry in bytes
+pub const ENTRY_SIZE: u64 = 128;
+/// Longest partition name, in UTF-16 code units
+pub const MAX_NAME_LEN: usize = 36;
+
+const HEADER_SIZE: usize = 92;
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+const REVISION: u32 = 0x0001_0000;
+
+/// Linux filesystem data; the type of partitions that do not set one
+pub const LINUX_FILESYSTEM: Guid = Guid([
+    0x0f, 0xc6, 0x3d, 0xaf, 0x84, 0x83, 0x47, 0x72, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4,
+]);
+
+/// A GUID, stored in the byte order of its textual form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// A GUID derived from `seed`, so rebuilding the same manifest gives the same disk
+    pub fn from_seed(seed: &str) -> Self {
+        let digest = Sha256::digest(seed.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        // RFC 9562 version 8 (custom), variant 10
+        bytes[6] = (bytes[6] & 0x0f) | 0x80;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Guid(bytes)
+    }
+
+    /// On-disk form: the first three fields are little-endian
+    pub fn to_mixed_endian(self) -> [u8; 16] {
+        let b = self.0;
+        [
+            b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9], b[10], b[11], b[12], b[13],
+            b[14], b[15],
+        ]
+    }
+
+    /// Inverse of [`Guid::to_mixed_endian`]
+    pub fn from_mixed_endian(b: [u8; 16]) -> Self {
+        Guid([
+            b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9], b[10], b[11], b[12], b[13],
+            b[14], b[15],
+        ])
+    }
+}
+
+impl FromStr for Guid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !crate::manifest::is_guid(s) {
+            return Err(format!("'{s}' is not a valid GUID"));
+        }
+        let hex: Vec<u8> = s
+            .bytes()
+            .filter(|c| *c != b'-')
+            .map(|c| (c as char).to_digit(16).unwrap() as u8)
+            .collect();
+        let mut bytes = [0u8; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = hex[2 * i] << 4 | hex[2 * i + 1];
+        }
+        Ok(Guid(bytes))
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// One entry of the partition table. LBAs are in blocks of the disk's block size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptPartition {
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// Last block of the partition, inclusive
+    pub last_lba: u64,
+    pub attributes: u64,
+    pub name: String,
+}
+
+/// A disk with a GUID partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptDisk {
+    pub disk_guid: Guid,
+    pub block_size: u64,
+    /// Size of the whole disk in blocks
+    pub blocks: u64,
+    pub partitions: Vec<GptPartition>,
+}
+
+/// Blocks taken by the partition entry array
+pub fn entry_array_blocks(block_size: u64) -> u64 {
+    (ENTRY_COUNT * ENTRY_SIZE).div_ceil(block_size)
+}
+
+/// First block partitions may use: after the protective MBR, the primary
+/// header and the primary entry array
+pub fn first_usable_lba(block_size: u64) -> u64 {
+    2 + entry_array_blocks(block_size)
+}
+
+/// Blocks at the end of the disk taken by the backup entry array and header
+pub fn backup_blocks(block_size: u64) -> u64 {
+    entry_array_blocks(block_size) + 1
+}
+
+impl GptDisk {
+    pub fn first_usable_lba(&self) -> u64 {
+        first_usable_lba(self.block_size)
+    }
+
+    pub fn last_usable_lba(&self) -> u64 {
+        self.blocks - backup_blocks(self.block_size) - 1
+    }
+
+    /// Check that the partitions fit in the usable area, do not overlap and
+    /// have names that fit their entries
+    pub fn check(&self) -> Result<(), String> {
+        if self.block_size < 512 || !self.block_size.is_power_of_two() {
+            return Err(format!(
+                "GPT block size must be a power of two of at least 512 bytes, not {}",
+                self.block_size
+            ));
+        }
+        if self.partitions.len() as u64 > ENTRY_COUNT {
+            return Err(format!(
+                "GPT holds at most {ENTRY_COUNT} partitions, not {}",
+                self.partitions.len()
+            ));
+        }
+        if self.blocks < self.first_usable_lba() + backup_blocks(self.block_size) + 1 {
+            return Err(format!(
+                "disk of {} blocks is too small for a GPT",
+                self.blocks
+            ));
+        }
+
+        let (first_usable, last_usable) = (self.first_usable_lba(), self.last_usable_lba());
+        for partition in &self.partitions {
+            if partition.name.encode_utf16().count() > MAX_NAME_LEN {
+                return Err(format!(
+                    "GPT partition name '{}' is longer than {MAX_NAME_LEN} characters",
+                    partition.name
+                ));
+            }
+            if partition.last_lba < partition.first_lba {
+                return Err(format!("GPT partition '{}' is empty", partition.name));
+            }
+            if partition.first_lba < first_usable || partition.last_lba > last_usable {
+                return Err(format!(
+                    "GPT partition '{}' (blocks {}..={}) is outside the usable blocks {first_usable}..={last_usable}",
+                    partition.name, partition.first_lba, partition.last_lba
+                ));
+            }
+        }
+
+        let mut sorted: Vec<&GptPartition> = self.partitions.iter().collect();
+        sorted.sort_by_key(|p| p.first_lba);
+        for pair in sorted.windows(2) {
+            if pair[1].first_lba <= pair[0].last_lba {
+                return Err(format!(
+                    "GPT partitions '{}' and '{}' overlap",
+                    pair[0].name, pair[1].name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the protective MBR and both copies of the partition table. The
+    /// rest of the disk is left untouched.
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<(), String> {
+        self.check()?;
+
+        let entries = self.entry_array();
+        let entries_crc = crc32fast::hash(&entries);
+        let entry_blocks = entry_array_blocks(self.block_size);
+        let backup_header_lba = self.blocks - 1;
+        let backup_entries_lba = backup_header_lba - entry_blocks;
+
+        let primary = self.header(1, backup_header_lba, 2, entries_crc);
+        let backup = self.header(backup_header_lba, 1, backup_entries_lba, entries_crc);
+
+        let mut write_at = |lba: u64, data: &[u8]| -> Result<(), String> {
+            writer
+                .seek(SeekFrom::Start(lba * self.block_size))
+                .and_then(|_| writer.write_all(data))
+                .map_err(|e| format!("Failed to write GPT at block {lba}: {e}"))
+        };
+        write_at(0, &self.protective_mbr())?;
+        write_at(1, &primary)?;
+        write_at(2, &entries)?;
+        write_at(backup_entries_lba, &entries)?;
+        write_at(backup_header_lba, &backup)?;
+        Ok(())
+    }
+
+    /// Read the primary GPT of a disk with `block_size`-byte blocks. Returns
+    /// None when the disk has no GPT signature at block 1; the backup table is
+    /// not consulted. Partition attributes are kept, unused entries dropped.
+    pub fn read_from<R: Read + Seek>(
+        reader: &mut R,
+        block_size: u64,
+    ) -> Result<Option<Self>, String> {
+        let disk_size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|e| format!("Failed to read disk size: {e}"))?;
+        let mut read_at = |lba: u64, length: usize| -> Result<Vec<u8>, String> {
+            let mut data = vec![0u8; length];
+            let offset = lba
+                .checked_mul(block_size)
+                .ok_or_else(|| format!("GPT block {lba} is beyond 2^64 bytes"))?;
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| reader.read_exact(&mut data))
+                .map_err(|e| format!("Failed to read GPT at block {lba}: {e}"))?;
+            Ok(data)
+        };
+        if disk_size < 2 * block_size {
+            return Ok(None);
+        }
+
+        let mut header = read_at(1, block_size as usize)?;
+        if &header[0..8] != SIGNATURE {
+            return Ok(None);
+        }
+        let header_size = le_u32(&header[12..16]) as usize;
+        if header_size < HEADER_SIZE || header_size > header.len() {
+            return Err(format!("GPT header size {header_size} is invalid"));
+        }
+        let header_crc = le_u32(&header[16..20]);
+        header[16..20].fill(0);
+        if crc32fast::hash(&header[..header_size]) != header_crc {
+            return Err("GPT header checksum does not match".to_string());
+        }
+
+        let entries_lba = le_u64(&header[72..80]);
+        let entry_count = u64::from(le_u32(&header[80..84]));
+        let entry_size = u64::from(le_u32(&header[84..88]));
+        if entry_size < ENTRY_SIZE || entry_count * entry_size > disk_size {
+            return Err(format!(
+                "GPT entry array of {entry_count} entries of {entry_size} bytes is invalid"
+            ));
+        }
+        let entries = read_at(entries_lba, (entry_count * entry_size) as usize)?;
+        if crc32fast::hash(&entries) != le_u32(&header[88..92]) {
+            return Err("GPT partition entry checksum does not match".to_string());
+        }
+
+        let partitions = entries
+            .chunks_exact(entry_size as usize)
+            .filter(|entry| entry[0..16].iter().any(|b| *b != 0))
+            .map(|entry| {
+                let name: Vec<u16> = entry[56..56 + 2 * MAX_NAME_LEN]
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .take_while(|unit| *unit != 0)
+                    .collect();
+                GptPartition {
+                    type_guid: Guid::from_mixed_endian(entry[0..16].try_into().unwrap()),
+                    unique_guid: Guid::from_mixed_endian(entry[16..32].try_into().unwrap()),
+                    first_lba: le_u64(&entry[32..40]),
+                    last_lba: le_u64(&entry[40..48]),
+                    attributes: le_u64(&entry[48..56]),
+                    name: String::from_utf16_lossy(&name),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Entries come from an untrusted disk; keep them within the usable
+        // blocks the header declares, which must be on the disk
+        let blocks = disk_size / block_size;
+        let first_usable = le_u64(&header[40..48]);
+        let last_usable = le_u64(&header[48..56]);
+        if last_usable >= blocks {
+            return Err(format!(
+                "GPT usable blocks {first_usable}..={last_usable} are past the end of the disk of {blocks} blocks"
+            ));
+        }
+        for partition in &partitions {
+            if partition.last_lba < partition.first_lba
+                || partition.first_lba < first_usable
+                || partition.last_lba > last_usable
+            {
+                return Err(format!(
+                    "GPT partition '{}' (blocks {}..={}) is outside the usable blocks {first_usable}..={last_usable}",
+                    partition.name, partition.first_lba, partition.last_lba
+                ));
+            }
+        }
+
+        Ok(Some(GptDisk {
+            disk_guid: Guid::from_mixed_endian(header[56..72].try_into().unwrap()),
+            block_size,
+            blocks,
+            partitions,
+        }))
+    }
+
+    fn protective_mbr(&self) -> Vec<u8> {
+        let mut mbr = vec![0u8; self.block_size as usize];
+        let entry = &mut mbr[446..462];
+        entry[1..4].copy_from_slice(&[0x00, 0x02, 0x00]);
+        entry[4] = 0xee;
+        entry[5..8].copy_from_slice(&[0xff, 0xff, 0xff]);
+        entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+        let size = (self.blocks - 1).min(u64::from(u32::MAX)) as u32;
+        entry[12..16].copy_from_slice(&size.to_le_bytes());
+        mbr[510] = 0x55;
+        mbr[511] = 0xaa;
+        mbr
+    }
+
+    fn header(
+        &self,
+        my_lba: u64,
+        alternate_lba: u64,
+        entries_lba: u64,
+        entries_crc: u32,
+    ) -> Vec<u8> {
+        let mut header = vec![0u8; self.block_size as usize];
+        header[0..8].copy_from_slice(SIGNATURE);
+        header[8..12].copy_from_slice(&REVISION.to_le_bytes());
+        header[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&my_lba.to_le_bytes());
+        header[32..40].copy_from_slice(&alternate_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&self.first_usable_lba().to_le_bytes());
+        header[48..56].copy_from_slice(&self.last_usable_lba().to_le_bytes());
+        header[56..72].copy_from_slice(&self.disk_guid.to_mixed_endian());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&(ENTRY_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32fast::hash(&header[..HEADER_SIZE]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+        header
+    }
+
+    fn entry_array(&self) -> Vec<u8> {
+        let mut entries =
+            vec![0u8; (entry_array_blocks(self.block_size) * self.block_size) as usize];
+        for (partition, entry) in self
+            .partitions
+            .iter()
+            .zip(entries.chunks_mut(ENTRY_SIZE as usize))
+        {
+            entry[0..16].copy_from_slice(&partition.type_guid.to_mixed_endian());
+            entry[16..32].copy_from_slice(&partition.unique_guid.to_mixed_endian());
+            entry[32..40].copy_from_slice(&partition.first_lba.to_le_bytes());
+            entry[40..48].copy_from_slice(&partition.last_lba.to_le_bytes());
+            entry[48..56].copy_from_slice(&partition.attributes.to_le_bytes());
+            for (i, unit) in partition.name.encode_utf16().enumerate() {
+                entry[56 + 2 * i..58 + 2 * i].copy_from_slice(&unit.to_le_bytes());
+            }
+        }
+        entries
+    }
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_guid_round_trip() {
+        let guid: Guid = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B".parse().unwrap();
+        assert_eq!(guid.to_string(), "c12a7328-f81f-11d2-ba4b-00a0c93ec93b");
+        assert_eq!(
+            guid.to_mixed_endian()[..8],
+            [0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11]
+        );
+        assert_eq!(Guid::from_mixed_endian(guid.to_mixed_endian()), guid);
+        assert!("not-a-guid".parse::<Guid>().is_err());
+
+        let derived = Guid::from_seed("disk");
+        assert_eq!(derived, Guid::from_seed("disk"));
+        assert_ne!(derived, Guid::from_seed("other disk"));
+        assert_eq!(derived.to_string().as_bytes()[14], b'8');
+    }
+
+    #[test]
+    fn test_write_gpt() {
+        let disk = GptDisk {
+            disk_guid: Guid::from_seed("disk"),
+            block_size: 512,
+            blocks: 2048,
+            partitions: vec![GptPartition {
+                type_guid: LINUX_FILESYSTEM,
+                unique_guid: Guid::from_seed("rootfs"),
+                first_lba: 34,
+                last_lba: 2014,
+                attributes: 0,
+                name: "rootfs".to_string(),
+            }],
+        };
+        let mut image = Cursor::new(vec![0u8; 2048 * 512]);
+        disk.write_to(&mut image).unwrap();
+        let image = image.into_inner();
+
+        // Protective MBR
+        assert_eq!(image[446 + 4], 0xee);
+        assert_eq!(image[510..512], [0x55, 0xaa]);
+
+        for (header_lba, entries_lba, alternate_lba) in [(1u64, 2u64, 2047u64), (2047, 2015, 1)] {
+            let header = &image[(header_lba * 512) as usize..][..512];
+            assert_eq!(&header[0..8], SIGNATURE);
+            assert_eq!(le_u64(&header[24..32]), header_lba);
+            assert_eq!(le_u64(&header[32..40]), alternate_lba);
+            assert_eq!(le_u64(&header[40..48]), 34);
+            assert_eq!(le_u64(&header[48..56]), 2014);
+            assert_eq!(le_u64(&header[72..80]), entries_lba);
+
+            let mut zeroed = header[..HEADER_SIZE].to_vec();
+            zeroed[16..20].fill(0);
+            assert_eq!(
+                u32::from_le_bytes(header[16..20].try_into().unwrap()),
+                crc32fast::hash(&zeroed)
+            );
+            let entries = &image[(entries_lba * 512) as usize..][..16384];
+            assert_eq!(
+                u32::from_le_bytes(header[88..92].try_into().unwrap()),
+                crc32fast::hash(entries)
+            );
+            assert_eq!(entries[0..16], LINUX_FILESYSTEM.to_mixed_endian());
+            assert_eq!(le_u64(&entries[32..40]), 34);
+            assert_eq!(le_u64(&entries[40..48]), 2014);
+            assert_eq!(entries[56..60], [b'r', 0, b'o', 0]);
+        }
+    }
+
+    #[test]
+    fn test_read_gpt() {
+        let disk = GptDisk {
+            disk_guid: Guid::from_seed("disk"),
+            block_size: 512,
+            blocks: 2048,
+            partitions: vec![
+                GptPartition {
+                    type_guid: LINUX_FILESYSTEM,
+                    unique_guid: Guid::from_seed("rootfs"),
+                    first_lba: 34,
+                    last_lba: 1023,
+                    attributes: 1 << 2,
+                    name: "rootfs".to_string(),
+                },
+                GptPartition {
+                    type_guid: Guid::from_seed("type"),
+                    unique_guid: Guid::from_seed("data"),
+                    first_lba: 1024,
+                    last_lba: 2014,
+                    attributes: 0,
+                    name: "données".to_string(),
+                },
+            ],
+        };
+        let mut image = Cursor::new(vec![0u8; 2048 * 512]);
+        disk.write_to(&mut image).unwrap();
+        assert_eq!(GptDisk::read_from(&mut image, 512), Ok(Some(disk)));
+
+        // Corrupt the name of the first entry
+        image.get_mut()[2 * 512 + 56] = b'R';
+        assert_eq!(
+            GptDisk::read_from(&mut image, 512),
+            Err("GPT partition entry checksum does not match".to_string())
+        );
+        assert_eq!(
+            GptDisk::read_from(&mut Cursor::new(vec![0u8; 4096]), 512),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_read_gpt_rejects_corrupt_entries() {
+        let mut disk = GptDisk {
+            disk_guid: Guid::from_seed("disk"),
+            block_size: 512,
+            blocks: 2048,
+            partitions: vec![GptPartition {
+                type_guid: LINUX_FILESYSTEM,
+                unique_guid: Guid::from_seed("rootfs"),
+                first_lba: 100,
+                last_lba: 200,
+                attributes: 0,
+                name: "rootfs".to_string(),
+            }],
+        };
+        // A table with valid checksums, as a broken tool could write it
+        let mut corrupt = |first_lba, last_lba| {
+            disk.partitions[0].first_lba = first_lba;
+            disk.partitions[0].last_lba = last_lba;
+            let mut image = vec![0u8; 2048 * 512];
+            let entries = disk.entry_array();
+            let crc = crc32fast::hash(&entries);
+            image[512..1024].copy_from_slice(&disk.header(1, 2047, 2, crc));
+            image[1024..][..entries.len()].copy_from_slice(&entries);
+            GptDisk::read_from(&mut Cursor::new(image), 512).unwrap_err()
+        };
+
+        assert_eq!(
+            corrupt(100, 50),
+            "GPT partition 'rootfs' (blocks 100..=50) is outside the usable blocks 34..=2014"
+        );
+        assert!(corrupt(1, 50).contains("outside the usable blocks"));
+        assert!(corrupt(100, u64::MAX).contains("outside the usable blocks"));
+    }
+
+    #[test]
+    fn test_check_rejects_partitions_outside_usable_area() {
+        let partition = |name: &str, first_lba, last_lba| GptPartition {
+            type_guid: LINUX_FILESYSTEM,
+            unique_guid: Guid::from_seed(name),
+            first_lba,
+            last_lba,
+            attributes: 0,
+            name: name.to_string(),
+        };
+        let disk = |partitions| GptDisk {
+            disk_guid: Guid::from_seed("disk"),
+            block_size: 512,
+            blocks: 2048,
+            partitions,
+        };
+
+        assert!(disk(vec![partition("a", 34, 2014)]).check().is_ok());
+        assert!(disk(vec![partition("a", 0, 100)]).check().is_err());
+        assert!(disk(vec![partition("a", 34, 2015)]).check().is_err());
+        let overlap = disk(vec![partition("a", 34, 100), partition("b", 100, 200)]);
+        assert_eq!(
+            overlap.check().unwrap_err(),
+            "GPT partitions 'a' and 'b' overlap"
+        );
+        let long_name = "a".repeat(MAX_NAME_LEN + 1);
+        assert!(disk(vec![partition(&long_name, 34, 100)]).check().is_err());
+    }
+}
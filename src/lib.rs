@@ -1,7 +1,43 @@
+//! Build, bundle and provision Avocado stones from Rust.
+//!
+//! [`Stone`] runs the same operations as the `stone` CLI for one manifest:
+//!
+//! ```no_run
+//! use stone::{BundleOptions, Level, Stone};
+//!
+//! let stone = Stone::new("manifest.json")
+//!     .with_input_dirs(["build/images", "build/boot"])
+//!     .with_var("VERSION", "2025.1.0")
+//!     .with_reporter(|level, message| {
+//!         if level == Level::Warning {
+//!             eprintln!("stone: {message}");
+//!         }
+//!     });
+//! stone.validate()?;
+//! stone.bundle(&BundleOptions::new("os-release", "os-bundle.aos"))?;
+//! # Ok::<(), stone::Error>(())
+//! ```
+//!
+//! The other modules are the building blocks behind it.
+
+pub mod api;
+pub mod bundle;
+pub mod chunk_index;
+/// The CLI subcommands behind the stone binary; use [`Stone`] instead
+#[doc(hidden)]
+pub mod commands;
+pub mod error;
 pub mod fat;
 pub mod fwup;
+pub mod gpt;
 pub mod log;
 pub mod manifest;
+pub mod mbr;
+pub mod merge;
+pub mod signing;
 
 // Re-export commonly used items
+pub use api::{BundleOptions, Stone};
+pub use error::Error;
 pub use fwup::{FwupOptions, create_firmware_package};
+pub use log::{Level, Reporter};
@@ -1,21 +1,73 @@
 use simply_colored::*;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// Severity of a message stone reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Debug,
+    Info,
+    Warning,
+    Success,
+    Error,
+}
+
+/// Receives stone's progress messages and warnings in place of stdout
+pub type Reporter = Arc<dyn Fn(Level, &str) + Send + Sync>;
+
+thread_local! {
+    static REPORTER: RefCell<Option<Reporter>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the messages logged on this thread sent to `reporter` instead of printed
+pub fn with_reporter<T>(reporter: Reporter, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Reporter>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REPORTER.set(self.0.take());
+        }
+    }
+
+    let _restore = Restore(REPORTER.replace(Some(reporter)));
+    f()
+}
+
+fn report(level: Level, message: &str) -> bool {
+    match REPORTER.with_borrow(Clone::clone) {
+        Some(reporter) => {
+            reporter(level, message);
+            true
+        }
+        None => false,
+    }
+}
 
 pub fn log_debug(message: &str) {
-    println!("{WHITE}[DEBUG]{RESET} {message}");
+    if !report(Level::Debug, message) {
+        println!("{WHITE}[DEBUG]{RESET} {message}");
+    }
 }
 
 pub fn log_info(message: &str) {
-    println!("{BLUE}[INFO]{RESET} {message}");
+    if !report(Level::Info, message) {
+        println!("{BLUE}[INFO]{RESET} {message}");
+    }
 }
 
 pub fn log_warning(message: &str) {
-    println!("{YELLOW}[WARNING]{RESET} {message}");
+    if !report(Level::Warning, message) {
+        println!("{YELLOW}[WARNING]{RESET} {message}");
+    }
 }
 
 pub fn log_success(message: &str) {
-    println!("{GREEN}[SUCCESS]{RESET} {message}");
+    if !report(Level::Success, message) {
+        println!("{GREEN}[SUCCESS]{RESET} {message}");
+    }
 }
 
 pub fn log_error(message: &str) {
-    println!("{RED}[ERROR]{RESET} {message}");
+    if !report(Level::Error, message) {
+        println!("{RED}[ERROR]{RESET} {message}");
+    }
 }
@@ -1,12 +1,7 @@
-use crate::commands::stone::Commands;
-use crate::log::*;
 use clap::Parser;
-
-mod commands;
-mod fat;
-mod fwup;
-mod log;
-mod manifest;
+use stone::Error;
+use stone::commands::stone::Commands;
+use stone::log::*;
 
 #[derive(Parser, Debug)]
 #[command(name = "stone")]
@@ -19,19 +14,27 @@ struct Cli {
 
 fn main() {
     if let Err(e) = run() {
-        log_error(&e);
-        std::process::exit(1);
+        log_error(&e.to_string());
+        std::process::exit(e.exit_code());
     }
 }
 
-fn run() -> Result<(), String> {
+fn run() -> Result<(), Error> {
     let cli = Cli::parse();
 
     match cli.command {
         Commands::Validate(args) => args.execute(),
         Commands::DescribeManifest(args) => args.execute(),
+        Commands::ManifestSchema(args) => args.execute(),
+        Commands::ImportImage(args) => args.execute(),
+        Commands::Manifest(args) => args.execute(),
         Commands::Create(args) => args.execute(),
         Commands::Bundle(args) => args.execute(),
+        Commands::BundleDelta(args) => args.execute(),
+        Commands::BundleSchema(args) => args.execute(),
+        Commands::InspectBundle(args) => args.execute(),
+        Commands::VerifyBundle(args) => args.execute(),
+        Commands::ApplyBundle(args) => args.execute(),
         Commands::Provision(args) => args.execute(),
     }
 }
@@ -1,7 +1,19 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Debug, Deserialize, Serialize)]
+use crate::error::Error;
+use crate::gpt::{self, GptDisk, GptPartition, Guid};
+use crate::mbr::{self, MbrDisk, MbrPartition};
+use crate::merge;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Block size used when a storage device does not set one
+pub const DEFAULT_BLOCK_SIZE: u64 = 512;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub enum FatVariant {
     #[serde(rename = "FAT12")]
     Fat12,
@@ -11,7 +23,8 @@ pub enum FatVariant {
     Fat32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[serde(tag = "type")]
 pub enum BuildArgs {
     #[serde(rename = "fat")]
@@ -24,6 +37,12 @@ pub enum BuildArgs {
     Fwup {
         template: String, // Path to template file
     },
+    /// GUID partition table written by stone itself; storage devices only
+    #[serde(rename = "gpt")]
+    Gpt {},
+    /// DOS partition table written by stone itself; storage devices only
+    #[serde(rename = "mbr")]
+    Mbr {},
 }
 
 impl BuildArgs {
@@ -31,6 +50,8 @@ impl BuildArgs {
         match self {
             BuildArgs::Fat { .. } => "fat",
             BuildArgs::Fwup { .. } => "fwup",
+            BuildArgs::Gpt {} => "gpt",
+            BuildArgs::Mbr {} => "mbr",
         }
     }
 
@@ -57,19 +78,59 @@ impl BuildArgs {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+#[schemars(title = "Stone manifest")]
 pub struct Manifest {
+    /// Version of the manifest schema; manifests without one are version 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_version: Option<u32>,
     pub runtime: Runtime,
     pub storage_devices: std::collections::HashMap<String, StorageDevice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub provision: Option<Provision>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub update: Option<Update>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<Release>,
+    /// Default values for `${NAME}` references in manifest strings. The environment
+    /// and `--var NAME=VALUE` override them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+// --- Release section: version and compatibility metadata carried into bundle.json ---
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Release {
+    /// Release version (e.g. "2025.1.0")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Human-readable release notes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Oldest installed AVOCADO_OS_BUILD_ID this release may be installed over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_os_build_id: Option<String>,
+    /// Oldest installed release version this release may be installed over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<String>,
+    /// Hardware revisions this release is built for; empty means any revision
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hardware_revisions: Vec<String>,
+}
+
+impl Release {
+    pub fn is_empty(&self) -> bool {
+        *self == Release::default()
+    }
 }
 
 // --- Update section: declares how OS artifacts map to A/B slots for OTA ---
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Update {
     pub slot_detection: SlotDetection,
     pub os_artifacts: HashMap<String, OsArtifactRef>,
@@ -78,7 +139,8 @@ pub struct Update {
     pub rollback: Option<SlotActions>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[serde(tag = "type")]
 pub enum SlotDetection {
     #[serde(rename = "uboot-env")]
@@ -92,13 +154,15 @@ pub enum SlotDetection {
     },
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct OsArtifactRef {
     pub image_key: String,
     pub slot_partitions: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[serde(tag = "type")]
 pub enum SlotAction {
     #[serde(rename = "uboot-env")]
@@ -117,7 +181,7 @@ pub enum SlotAction {
     },
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(untagged)]
 pub enum SlotActions {
     Single(SlotAction),
@@ -133,7 +197,8 @@ impl SlotActions {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Runtime {
     pub platform: String,
     pub architecture: String,
@@ -145,28 +210,31 @@ pub struct Runtime {
     pub update_strategy: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Provision {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub envs: Option<HashMap<String, HashMap<String, String>>>,
     pub profiles: HashMap<String, ProvisionProfile>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct ProvisionProfile {
     pub script: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub envs: Option<Vec<ProvisionEnv>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum ProvisionEnv {
     Named(String),
     Inline(HashMap<String, String>),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct StorageDevice {
     pub out: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -176,11 +244,23 @@ pub struct StorageDevice {
     pub block_size: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uuid: Option<String>,
+    /// Size of the device; in blocks unless capacity_unit says otherwise. With
+    /// a capacity, expanding partitions fill the space left to them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity_unit: Option<SizeUnit>,
+    /// Boundary partitions without an offset start on; in blocks unless alignment_unit says otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alignment_unit: Option<SizeUnit>,
     pub images: std::collections::HashMap<String, Image>,
     pub partitions: Vec<Partition>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[serde(untagged)]
 pub enum Image {
     String(String),
@@ -188,8 +268,8 @@ pub enum Image {
         out: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         build_args: Option<BuildArgs>,
-        size: i64,
-        size_unit: String,
+        size: u64,
+        size_unit: SizeUnit,
         #[serde(skip_serializing_if = "Option::is_none")]
         block_size: Option<u32>,
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -197,6 +277,38 @@ pub enum Image {
     },
 }
 
+/// Deserialized by hand rather than as an untagged enum, so a bad field in an
+/// image object reports its own error instead of "did not match any variant".
+impl<'de> Deserialize<'de> for Image {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct ImageObject {
+            out: String,
+            build_args: Option<BuildArgs>,
+            size: u64,
+            size_unit: SizeUnit,
+            block_size: Option<u32>,
+            uuid: Option<String>,
+        }
+
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(filename) => Ok(Image::String(filename)),
+            value => {
+                let image = ImageObject::deserialize(value)
+                    .map_err(|e| serde::de::Error::custom(format!("invalid image: {e}")))?;
+                Ok(Image::Object {
+                    out: image.out,
+                    build_args: image.build_args,
+                    size: image.size,
+                    size_unit: image.size_unit,
+                    block_size: image.block_size,
+                    uuid: image.uuid,
+                })
+            }
+        }
+    }
+}
+
 impl Image {
     pub fn out(&self) -> &str {
         match self {
@@ -231,17 +343,12 @@ impl Image {
         }
     }
 
-    pub fn size(&self) -> Option<i64> {
-        match self {
-            Image::String(_) => None,
-            Image::Object { size, .. } => Some(*size),
-        }
-    }
-
-    pub fn size_unit(&self) -> Option<&str> {
+    pub fn size(&self) -> Option<Size> {
         match self {
             Image::String(_) => None,
-            Image::Object { size_unit, .. } => Some(size_unit),
+            Image::Object {
+                size, size_unit, ..
+            } => Some(Size::new(*size, *size_unit)),
         }
     }
 
@@ -260,7 +367,8 @@ impl Image {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[serde(untagged)]
 pub enum FileEntry {
     String(String),
@@ -281,7 +389,8 @@ impl FileEntry {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Partition {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -291,37 +400,1295 @@ pub struct Partition {
     pub partition_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub partition_uuid: Option<String>,
+    /// Partition start; in blocks unless offset_unit says otherwise
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub offset: Option<i64>,
+    pub offset: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub offset_unit: Option<String>,
+    pub offset_unit: Option<SizeUnit>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub offset_redundant: Option<i64>,
+    pub offset_redundant: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub offset_redundant_unit: Option<String>,
-    pub size: i64,
-    pub size_unit: String,
+    pub offset_redundant_unit: Option<SizeUnit>,
+    pub size: u64,
+    pub size_unit: SizeUnit,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expand: Option<String>,
+    /// Mark the partition active in an MBR partition table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bootable: Option<bool>,
+    /// Put the partition in the extended partition of an MBR partition table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logical: Option<bool>,
+}
+
+impl Partition {
+    pub fn size(&self) -> Size {
+        Size::new(self.size, self.size_unit)
+    }
+
+    /// Explicit start of the partition; offsets without a unit are in blocks
+    pub fn offset(&self) -> Option<Size> {
+        self.offset
+            .map(|value| Size::new(value, self.offset_unit.unwrap_or(SizeUnit::Blocks)))
+    }
+
+    /// Whether the partition grows to fill the space after it
+    pub fn expands(&self) -> bool {
+        self.expand.as_deref() == Some("true")
+    }
+
+    /// Start of the redundant copy; offsets without a unit are in blocks
+    pub fn offset_redundant(&self) -> Option<Size> {
+        self.offset_redundant.map(|value| {
+            Size::new(
+                value,
+                self.offset_redundant_unit.unwrap_or(SizeUnit::Blocks),
+            )
+        })
+    }
+}
+
+/// A partition placed on its storage device, in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedPartition<'a> {
+    pub partition: &'a Partition,
+    /// Start of the partition; always a whole number of blocks
+    pub offset: u64,
+    /// Partition size rounded up to a whole number of blocks; for an expanding
+    /// partition on a device with a capacity, all the space left to it
+    pub size: u64,
+    /// Start of the redundant copy, when the partition has one
+    pub redundant_offset: Option<u64>,
+}
+
+/// Bytes of a device taken by a partition, its redundant copy or a partition table
+struct Region {
+    label: String,
+    /// Index of the partition whose data this is; None for the partition
+    /// table and redundant copies
+    partition: Option<usize>,
+    start: u64,
+    end: u64,
+}
+
+impl Region {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        start < self.end && self.start < end
+    }
+}
+
+impl StorageDevice {
+    pub fn block_size(&self) -> u64 {
+        self.block_size.map(u64::from).unwrap_or(DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Where partitions without an offset may start, in bytes; every block
+    /// unless `alignment` says otherwise. Alignments without a unit are in blocks.
+    pub fn alignment(&self) -> Result<u64, String> {
+        let block_size = self.block_size();
+        let Some(alignment) = self.alignment else {
+            return Ok(block_size);
+        };
+        let alignment = Size::new(alignment, self.alignment_unit.unwrap_or(SizeUnit::Blocks))
+            .blocks(block_size)
+            .map_err(|e| format!("alignment: {e}"))?;
+        if alignment == 0 {
+            return Err("alignment must be positive".to_string());
+        }
+        Ok(alignment * block_size)
+    }
+
+    /// Size of the device in bytes, when it has a capacity
+    pub fn capacity(&self) -> Result<Option<u64>, String> {
+        let block_size = self.block_size();
+        self.capacity
+            .map(|capacity| {
+                let blocks = Size::new(capacity, self.capacity_unit.unwrap_or(SizeUnit::Blocks))
+                    .blocks(block_size)
+                    .map_err(|e| format!("capacity: {e}"))?;
+                Ok(blocks * block_size)
+            })
+            .transpose()
+    }
+
+    /// End of the space partitions may use, in bytes: the capacity, less the
+    /// backup GPT when stone writes one
+    fn usable_end(&self) -> Result<Option<u64>, String> {
+        let Some(capacity) = self.capacity()? else {
+            return Ok(None);
+        };
+        let reserved = match &self.build_args {
+            Some(BuildArgs::Gpt {}) => gpt::backup_blocks(self.block_size()) * self.block_size(),
+            _ => 0,
+        };
+        capacity.checked_sub(reserved).map(Some).ok_or_else(|| {
+            format!("capacity of {capacity} bytes is too small for the partition table")
+        })
+    }
+
+    /// The start of the device taken by the partition table stone writes for it
+    fn partition_table_region(&self) -> Option<Region> {
+        let block_size = self.block_size();
+        let (label, end) = match &self.build_args {
+            Some(BuildArgs::Gpt {}) => ("the GPT", gpt::first_usable_lba(block_size) * block_size),
+            Some(BuildArgs::Mbr {}) => ("the MBR", block_size),
+            _ => return None,
+        };
+        Some(Region {
+            label: label.to_string(),
+            partition: None,
+            start: 0,
+            end,
+        })
+    }
+
+    /// Place every partition on the device. Partitions with an offset, and
+    /// redundant copies, are placed first, exactly there. Each of the others
+    /// starts at the first aligned byte after the previous partition that has
+    /// room for it, so it never collides with a fixed one.
+    ///
+    /// With a capacity, expanding partitions then grow up to the next
+    /// partition or the end of the usable space.
+    ///
+    /// Fails when fixed partitions, their redundant copies or the partition
+    /// table overlap, or when anything ends past the capacity.
+    pub fn partition_layout(&self) -> Result<Vec<PlacedPartition<'_>>, String> {
+        let block_size = self.block_size();
+        let alignment = self.alignment()?;
+        let label = |index: usize| match &self.partitions[index].name {
+            Some(name) => format!("partition '{name}'"),
+            None => format!("partition #{}", index + 1),
+        };
+        let end_of = |start: u64, size: u64, index: usize| {
+            start
+                .checked_add(size)
+                .ok_or_else(|| format!("{} ends beyond 2^64 bytes", label(index)))
+        };
+
+        // Sizes, fixed offsets and redundant copies
+        let mut sizes = Vec::with_capacity(self.partitions.len());
+        let mut offsets = Vec::with_capacity(self.partitions.len());
+        let mut redundant_offsets = Vec::with_capacity(self.partitions.len());
+        let mut occupied: Vec<Region> = self.partition_table_region().into_iter().collect();
+        for (index, partition) in self.partitions.iter().enumerate() {
+            let size = partition
+                .size()
+                .blocks_ceil(block_size)
+                .map_err(|e| format!("{} size: {e}", label(index)))?
+                * block_size;
+            let offset = match partition.offset() {
+                Some(offset) => {
+                    let start = offset
+                        .blocks(block_size)
+                        .map_err(|e| format!("{} offset: {e}", label(index)))?
+                        * block_size;
+                    occupied.push(Region {
+                        label: label(index),
+                        partition: Some(index),
+                        start,
+                        end: end_of(start, size, index)?,
+                    });
+                    Some(start)
+                }
+                None => None,
+            };
+            let redundant_offset = match partition.offset_redundant() {
+                Some(offset) => {
+                    let start = offset
+                        .blocks(block_size)
+                        .map_err(|e| format!("{} offset_redundant: {e}", label(index)))?
+                        * block_size;
+                    occupied.push(Region {
+                        label: format!("redundant copy of {}", label(index)),
+                        partition: None,
+                        start,
+                        end: end_of(start, size, index)?,
+                    });
+                    Some(start)
+                }
+                None => None,
+            };
+            sizes.push(size);
+            offsets.push(offset);
+            redundant_offsets.push(redundant_offset);
+        }
+
+        occupied.retain(|region| region.start < region.end);
+        occupied.sort_by_key(|region| region.start);
+        for (i, first) in occupied.iter().enumerate() {
+            if let Some(second) = occupied[i + 1..]
+                .iter()
+                .find(|second| second.start < first.end)
+            {
+                return Err(format!(
+                    "{} (bytes {}..{}) overlaps {} (bytes {}..{})",
+                    first.label, first.start, first.end, second.label, second.start, second.end
+                ));
+            }
+        }
+
+        // Partitions without an offset, in order. A logical MBR partition
+        // needs a free block before it for its extended boot record.
+        let mut cursor = 0u64;
+        let mut placed = Vec::with_capacity(self.partitions.len());
+        for (index, partition) in self.partitions.iter().enumerate() {
+            let size = sizes[index];
+            let offset = match offsets[index] {
+                Some(offset) => offset,
+                None => {
+                    let lead = match (&self.build_args, partition.logical) {
+                        (Some(BuildArgs::Mbr {}), Some(true)) => block_size,
+                        _ => 0,
+                    };
+                    let mut start = align_up(cursor + lead, alignment, index, &label)?;
+                    while let Some(region) = occupied
+                        .iter()
+                        .find(|region| region.overlaps(start - lead, start.saturating_add(size)))
+                    {
+                        start = align_up(region.end + lead, alignment, index, &label)?;
+                    }
+                    occupied.push(Region {
+                        label: label(index),
+                        partition: Some(index),
+                        start: start - lead,
+                        end: end_of(start, size, index)?,
+                    });
+                    start
+                }
+            };
+
+            cursor = end_of(offset, size, index)?;
+            placed.push(PlacedPartition {
+                partition,
+                offset,
+                size,
+                redundant_offset: redundant_offsets[index],
+            });
+        }
+
+        if let Some(usable_end) = self.usable_end()? {
+            if let Some(region) = occupied.iter().find(|region| region.end > usable_end) {
+                return Err(format!(
+                    "{} (bytes {}..{}) does not fit on the device, which has {usable_end} usable bytes",
+                    region.label, region.start, region.end
+                ));
+            }
+            for (index, placed) in placed.iter_mut().enumerate() {
+                if !placed.partition.expands() {
+                    continue;
+                }
+                let end = placed.offset + placed.size;
+                let limit = occupied
+                    .iter()
+                    .filter(|region| region.partition != Some(index) && region.start >= end)
+                    .map(|region| region.start)
+                    .min()
+                    .unwrap_or(usable_end);
+                placed.size = (limit - placed.offset) / block_size * block_size;
+            }
+        }
+
+        Ok(placed)
+    }
+
+    /// The GUID partition table of this device. The disk and partitions
+    /// without a GUID get one derived from `out` and the partition's name or
+    /// position, so rebuilding the device gives the same table.
+    pub fn gpt_disk(&self) -> Result<GptDisk, String> {
+        let block_size = self.block_size();
+        let placed = self.partition_layout()?;
+
+        let blocks = match self.capacity()? {
+            Some(capacity) => capacity / block_size,
+            None => {
+                (layout_end(&placed) / block_size).max(gpt::first_usable_lba(block_size))
+                    + gpt::backup_blocks(block_size)
+            }
+        };
+
+        let disk_guid = match &self.uuid {
+            Some(uuid) => uuid.parse()?,
+            None => Guid::from_seed(&format!("{}:disk", self.out)),
+        };
+        let mut partitions = Vec::with_capacity(placed.len());
+        for (index, placed) in placed.iter().enumerate() {
+            let partition = placed.partition;
+            let name = partition.name.clone().unwrap_or_default();
+            let type_guid = match &partition.partition_type {
+                Some(partition_type) => partition_type.parse()?,
+                None => gpt::LINUX_FILESYSTEM,
+            };
+            let unique_guid = match &partition.partition_uuid {
+                Some(uuid) => uuid.parse()?,
+                None => Guid::from_seed(&format!(
+                    "{}:{}",
+                    self.out,
+                    partition.name.clone().unwrap_or_else(|| index.to_string())
+                )),
+            };
+            partitions.push(GptPartition {
+                type_guid,
+                unique_guid,
+                first_lba: placed.offset / block_size,
+                last_lba: ((placed.offset + placed.size) / block_size).saturating_sub(1),
+                attributes: 0,
+                name,
+            });
+        }
+
+        let disk = GptDisk {
+            disk_guid,
+            block_size,
+            blocks,
+            partitions,
+        };
+        disk.check()?;
+        Ok(disk)
+    }
+
+    /// The DOS partition table of this device. The disk signature is taken
+    /// from the device uuid, or derived from `out` when there is none.
+    pub fn mbr_disk(&self) -> Result<MbrDisk, String> {
+        let block_size = self.block_size();
+        let placed = self.partition_layout()?;
+
+        let disk_guid = match &self.uuid {
+            Some(uuid) => uuid.parse()?,
+            None => Guid::from_seed(&format!("{}:disk", self.out)),
+        };
+        let mut disk = MbrDisk {
+            disk_signature: u32::from_be_bytes(disk_guid.0[..4].try_into().unwrap()),
+            block_size,
+            blocks: 0,
+            primary: Vec::new(),
+            logical: Vec::new(),
+        };
+        for (index, placed) in placed.iter().enumerate() {
+            let partition = placed.partition;
+            let mbr_partition = MbrPartition {
+                name: partition
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("#{}", index + 1)),
+                partition_type: match &partition.partition_type {
+                    Some(partition_type) => mbr::parse_partition_type(partition_type)?,
+                    None => mbr::LINUX,
+                },
+                bootable: partition.bootable.unwrap_or(false),
+                first_lba: placed.offset / block_size,
+                blocks: placed.size / block_size,
+            };
+
+            if partition.logical.unwrap_or(false) {
+                disk.logical.push(mbr_partition);
+            } else {
+                disk.primary.push(mbr_partition);
+            }
+        }
+        disk.logical.sort_by_key(|p| p.first_lba);
+        disk.blocks = self.capacity()?.unwrap_or_else(|| layout_end(&placed)) / block_size;
+
+        disk.check()?;
+        Ok(disk)
+    }
+}
+
+/// End of the last partition or redundant copy, in bytes
+fn layout_end(placed: &[PlacedPartition]) -> u64 {
+    placed
+        .iter()
+        .flat_map(|placed| {
+            [Some(placed.offset), placed.redundant_offset]
+                .into_iter()
+                .flatten()
+                .map(|start| start + placed.size)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// `value` rounded up to a multiple of `alignment`
+fn align_up(
+    value: u64,
+    alignment: u64,
+    index: usize,
+    label: &impl Fn(usize) -> String,
+) -> Result<u64, String> {
+    value
+        .checked_next_multiple_of(alignment)
+        .ok_or_else(|| format!("{} ends beyond 2^64 bytes", label(index)))
+}
+
+// --- Sizes: every size and offset in the manifest is a value plus a unit ---
+
+/// Unit of a size or offset. SI units (kilobytes, megabytes, ...) are powers of
+/// 1000; IEC units (kibibytes, mebibytes, ...) are powers of 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeUnit {
+    Bytes,
+    /// Blocks of the storage device's block_size
+    Blocks,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+    Terabytes,
+    Kibibytes,
+    Mebibytes,
+    Gibibytes,
+    Tebibytes,
+}
+
+/// Accepted spellings of each unit; matching is case-insensitive. The first
+/// spelling of each unit is its canonical name.
+const SIZE_UNIT_NAMES: &[(&str, SizeUnit)] = &[
+    ("bytes", SizeUnit::Bytes),
+    ("byte", SizeUnit::Bytes),
+    ("b", SizeUnit::Bytes),
+    ("blocks", SizeUnit::Blocks),
+    ("block", SizeUnit::Blocks),
+    ("kilobytes", SizeUnit::Kilobytes),
+    ("kilobyte", SizeUnit::Kilobytes),
+    ("kb", SizeUnit::Kilobytes),
+    ("megabytes", SizeUnit::Megabytes),
+    ("megabyte", SizeUnit::Megabytes),
+    ("mb", SizeUnit::Megabytes),
+    ("gigabytes", SizeUnit::Gigabytes),
+    ("gigabyte", SizeUnit::Gigabytes),
+    ("gb", SizeUnit::Gigabytes),
+    ("terabytes", SizeUnit::Terabytes),
+    ("terabyte", SizeUnit::Terabytes),
+    ("tb", SizeUnit::Terabytes),
+    ("kibibytes", SizeUnit::Kibibytes),
+    ("kibibyte", SizeUnit::Kibibytes),
+    ("kib", SizeUnit::Kibibytes),
+    ("mebibytes", SizeUnit::Mebibytes),
+    ("mebibyte", SizeUnit::Mebibytes),
+    ("mib", SizeUnit::Mebibytes),
+    ("gibibytes", SizeUnit::Gibibytes),
+    ("gibibyte", SizeUnit::Gibibytes),
+    ("gib", SizeUnit::Gibibytes),
+    ("tebibytes", SizeUnit::Tebibytes),
+    ("tebibyte", SizeUnit::Tebibytes),
+    ("tib", SizeUnit::Tebibytes),
+];
+
+impl SizeUnit {
+    /// Canonical name, as written in manifests and bundle.json
+    pub fn name(self) -> &'static str {
+        SIZE_UNIT_NAMES
+            .iter()
+            .find(|(_, unit)| *unit == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    /// Short symbol for display (e.g. "MB", "MiB")
+    pub fn symbol(self) -> &'static str {
+        match self {
+            SizeUnit::Bytes => "bytes",
+            SizeUnit::Blocks => "blocks",
+            SizeUnit::Kilobytes => "KB",
+            SizeUnit::Megabytes => "MB",
+            SizeUnit::Gigabytes => "GB",
+            SizeUnit::Terabytes => "TB",
+            SizeUnit::Kibibytes => "KiB",
+            SizeUnit::Mebibytes => "MiB",
+            SizeUnit::Gibibytes => "GiB",
+            SizeUnit::Tebibytes => "TiB",
+        }
+    }
+
+    /// Bytes per unit, given the device block size
+    pub fn bytes(self, block_size: u64) -> u64 {
+        match self {
+            SizeUnit::Bytes => 1,
+            SizeUnit::Blocks => block_size,
+            SizeUnit::Kilobytes => 1000,
+            SizeUnit::Megabytes => 1000_u64.pow(2),
+            SizeUnit::Gigabytes => 1000_u64.pow(3),
+            SizeUnit::Terabytes => 1000_u64.pow(4),
+            SizeUnit::Kibibytes => 1 << 10,
+            SizeUnit::Mebibytes => 1 << 20,
+            SizeUnit::Gibibytes => 1 << 30,
+            SizeUnit::Tebibytes => 1 << 40,
+        }
+    }
+}
+
+impl fmt::Display for SizeUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SizeUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SIZE_UNIT_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, unit)| *unit)
+            .ok_or_else(|| {
+                format!(
+                    "Unsupported size unit: {s}. Supported units: bytes, blocks, kilobytes, megabytes, gigabytes, terabytes, kibibytes, mebibytes, gibibytes, tebibytes."
+                )
+            })
+    }
+}
+
+impl Serialize for SizeUnit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for SizeUnit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for SizeUnit {
+    fn schema_name() -> Cow<'static, str> {
+        "SizeUnit".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let names: Vec<&str> = SIZE_UNIT_NAMES.iter().map(|(name, _)| *name).collect();
+        json_schema!({
+            "description": "Size unit. SI units are powers of 1000, IEC units powers of 1024; blocks use the device block_size.",
+            "type": "string",
+            "enum": names,
+        })
+    }
+}
+
+/// A size or offset from the manifest, with exact conversion to bytes and blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub value: u64,
+    pub unit: SizeUnit,
+}
+
+impl Size {
+    pub fn new(value: u64, unit: SizeUnit) -> Self {
+        Size { value, unit }
+    }
+
+    /// `bytes` in the largest binary unit that expresses it exactly
+    pub fn from_bytes(bytes: u64) -> Self {
+        [
+            SizeUnit::Tebibytes,
+            SizeUnit::Gibibytes,
+            SizeUnit::Mebibytes,
+            SizeUnit::Kibibytes,
+        ]
+        .into_iter()
+        .map(|unit| (unit, unit.bytes(1)))
+        .find(|(_, unit_bytes)| bytes != 0 && bytes.is_multiple_of(*unit_bytes))
+        .map_or(Size::new(bytes, SizeUnit::Bytes), |(unit, unit_bytes)| {
+            Size::new(bytes / unit_bytes, unit)
+        })
+    }
+
+    /// Size in bytes; `block_size` is only used for sizes in blocks
+    pub fn bytes(&self, block_size: u64) -> Result<u64, String> {
+        self.value
+            .checked_mul(self.unit.bytes(block_size))
+            .ok_or_else(|| format!("{self} does not fit in 64 bits of bytes"))
+    }
+
+    /// Size in whole blocks; fails when the size is not a multiple of the block size
+    pub fn blocks(&self, block_size: u64) -> Result<u64, String> {
+        let bytes = self.bytes(block_size)?;
+        if bytes % block_size != 0 {
+            return Err(format!(
+                "{self} is not a multiple of the {block_size}-byte block size"
+            ));
+        }
+        Ok(bytes / block_size)
+    }
+
+    /// Size in blocks, rounded up to a whole block
+    pub fn blocks_ceil(&self, block_size: u64) -> Result<u64, String> {
+        Ok(self.bytes(block_size)?.div_ceil(block_size))
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit.symbol())
+    }
+}
+
+/// File format of a manifest. All formats describe the same model; only the syntax differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ManifestFormat {
+    /// Strict JSON (.json)
+    Json,
+    /// JSON with comments and trailing commas (.json5, .jsonc)
+    Json5,
+    /// YAML (.yaml, .yml)
+    Yaml,
+    /// TOML (.toml)
+    Toml,
+}
+
+impl ManifestFormat {
+    /// File names searched for when only a directory is given, in priority order
+    pub const FILE_NAMES: [&'static str; 6] = [
+        "manifest.json",
+        "manifest.yaml",
+        "manifest.yml",
+        "manifest.toml",
+        "manifest.json5",
+        "manifest.jsonc",
+    ];
+
+    /// Format implied by the file extension, if it is one stone knows
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(ManifestFormat::Json),
+            "json5" | "jsonc" => Some(ManifestFormat::Json5),
+            "yaml" | "yml" => Some(ManifestFormat::Yaml),
+            "toml" => Some(ManifestFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Format of a manifest file: `format` when given, else the file extension, else JSON
+    pub fn resolve(format: Option<Self>, path: &Path) -> Self {
+        format
+            .or_else(|| Self::from_extension(path))
+            .unwrap_or(ManifestFormat::Json)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ManifestFormat::Json => "JSON",
+            ManifestFormat::Json5 => "JSON5",
+            ManifestFormat::Yaml => "YAML",
+            ManifestFormat::Toml => "TOML",
+        }
+    }
+
+    /// Whether files in this format can hold comments, which are lost when
+    /// the manifest is written back
+    pub fn allows_comments(&self) -> bool {
+        !matches!(self, ManifestFormat::Json)
+    }
+
+    /// Parse `content` written in this format
+    pub fn parse<T: serde::de::DeserializeOwned>(self, content: &str) -> Result<T, String> {
+        match self {
+            ManifestFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+            ManifestFormat::Json5 => json5::from_str(content).map_err(|e| e.to_string()),
+            ManifestFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+            ManifestFormat::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Write `value` in this format. TOML has no null, so null values are left out.
+    pub fn to_string(self, value: &serde_json::Value) -> Result<String, String> {
+        let result = match self {
+            ManifestFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ManifestFormat::Json5 => {
+                let mut content = String::new();
+                write_json5(value, 0, &mut content);
+                Ok(content)
+            }
+            ManifestFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            ManifestFormat::Toml => {
+                toml::to_string_pretty(&without_nulls(value)).map_err(|e| e.to_string())
+            }
+        };
+        result.map(|mut content| {
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content
+        })
+    }
+}
+
+impl fmt::Display for ManifestFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Write `value` as JSON5: keys that are identifiers are left unquoted and
+/// every member and element ends with a comma
+fn write_json5(value: &serde_json::Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent + 1);
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            out.push_str("{\n");
+            for (key, value) in map {
+                out.push_str(&pad);
+                let identifier = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if identifier {
+                    out.push_str(key);
+                } else {
+                    out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                }
+                out.push_str(": ");
+                write_json5(value, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push('}');
+        }
+        serde_json::Value::Array(items) if !items.is_empty() => {
+            out.push_str("[\n");
+            for item in items {
+                out.push_str(&pad);
+                write_json5(item, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+/// Copy of `value` with every null object member and array element removed
+fn without_nulls(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k.clone(), without_nulls(v)))
+            .collect(),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .filter(|v| !v.is_null())
+            .map(without_nulls)
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Read and parse one manifest file, without resolving `extends`
+fn read_manifest_file<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    format: ManifestFormat,
+) -> Result<T, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read manifest file '{}': {}", path.display(), e))?;
+
+    format.parse(&content).map_err(|e| {
+        format!(
+            "Failed to parse manifest {} '{}': {}",
+            format,
+            path.display(),
+            e
+        )
+    })
+}
+
+/// Merge `value`, read from `path`, over the base manifest it extends, recursively.
+/// `chain` holds the canonical paths of the manifests being resolved, to detect cycles.
+fn resolve_extends(
+    path: &Path,
+    mut value: serde_json::Value,
+    chain: &mut Vec<PathBuf>,
+) -> Result<serde_json::Value, String> {
+    let Some(extends) = value.as_object_mut().and_then(|map| map.remove("extends")) else {
+        return Ok(value);
+    };
+    let base_name = extends.as_str().ok_or_else(|| {
+        format!(
+            "'extends' in manifest '{}' must be the path of the base manifest.",
+            path.display()
+        )
+    })?;
+
+    // Base paths are relative to the manifest that extends them
+    let base_path = path.parent().unwrap_or(Path::new(".")).join(base_name);
+    let canonical = base_path.canonicalize().map_err(|e| {
+        format!(
+            "Failed to find base manifest '{}' extended by '{}': {}",
+            base_path.display(),
+            path.display(),
+            e
+        )
+    })?;
+    if chain.contains(&canonical) {
+        let cycle: Vec<String> = chain
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(format!(
+            "Manifest inheritance cycle: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    chain.push(canonical);
+    let base = read_manifest_file(&base_path, ManifestFormat::resolve(None, &base_path))?;
+    check_manifest_version(&base, &base_path)?;
+    let base = resolve_extends(&base_path, base, chain)?;
+    chain.pop();
+
+    Ok(merge::merge(base, value))
+}
+
+/// Manifest schema version written by `stone manifest upgrade`.
+///
+/// History:
+/// - 1: no `manifest_version` field; the provision script may be set with `runtime.provision`
+/// - 2: `manifest_version` field; provision scripts are set with `provision.profiles`
+pub const MANIFEST_VERSION: u32 = 2;
+
+/// Reject manifests written for a newer stone
+fn check_manifest_version(value: &serde_json::Value, path: &Path) -> Result<(), String> {
+    match value.get("manifest_version") {
+        None => Ok(()),
+        Some(version) => match version.as_u64() {
+            Some(version) if version <= u64::from(MANIFEST_VERSION) => Ok(()),
+            Some(version) => Err(format!(
+                "Manifest '{}' has version {version}, newer than this stone supports ({MANIFEST_VERSION}). Upgrade stone.",
+                path.display()
+            )),
+            None => Err(format!(
+                "Manifest '{}': manifest_version must be a positive integer.",
+                path.display()
+            )),
+        },
+    }
+}
+
+/// Rewrite a manifest, as read from its file, to the current schema version.
+/// Returns a description of each change; nothing is changed when the manifest is current.
+pub fn upgrade_manifest(manifest: &mut serde_json::Value) -> Result<Vec<String>, String> {
+    let map = manifest
+        .as_object_mut()
+        .ok_or("The manifest is not an object")?;
+    let mut changes = Vec::new();
+
+    // runtime.provision becomes a provision profile
+    let legacy_script = map
+        .get_mut("runtime")
+        .and_then(serde_json::Value::as_object_mut)
+        .and_then(|runtime| runtime.remove("provision"));
+    if let Some(script) = legacy_script {
+        let had_provision = map.contains_key("provision");
+        let provision = map
+            .entry("provision")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or("provision is not an object")?;
+        let profiles = provision
+            .entry("profiles")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or("provision.profiles is not an object")?;
+        let profile_name = if profiles.contains_key("default") {
+            "legacy"
+        } else {
+            "default"
+        };
+        if profiles.contains_key(profile_name) {
+            return Err(format!(
+                "Cannot move runtime.provision into provision.profiles: profiles 'default' and '{profile_name}' both exist."
+            ));
+        }
+        profiles.insert(
+            profile_name.to_string(),
+            serde_json::json!({ "script": script }),
+        );
+        changes.push(format!(
+            "Moved runtime.provision {script} to provision profile '{profile_name}'."
+        ));
+
+        // Before, the script only ran when there was no provision section
+        if !had_provision
+            && let Some(runtime) = map
+                .get_mut("runtime")
+                .and_then(serde_json::Value::as_object_mut)
+            && !runtime.contains_key("provision_default")
+        {
+            runtime.insert(
+                "provision_default".to_string(),
+                serde_json::json!(profile_name),
+            );
+            changes.push(format!(
+                "Set runtime.provision_default to '{profile_name}'."
+            ));
+        }
+    }
+
+    if map.get("manifest_version") != Some(&serde_json::json!(MANIFEST_VERSION)) {
+        map.insert(
+            "manifest_version".to_string(),
+            serde_json::json!(MANIFEST_VERSION),
+        );
+        changes.push(format!("Set manifest_version to {MANIFEST_VERSION}."));
+    }
+
+    Ok(changes)
+}
+
+/// Canonical form of a manifest, as read from its file: keys sorted, unit names
+/// spelled the canonical way and defaults made explicit by the manifest types.
+///
+/// A manifest that `extends` another is usually incomplete on its own, so it is
+/// not run through the manifest types; only its keys are sorted and units renamed.
+pub fn canonical_manifest(manifest: &serde_json::Value) -> Result<serde_json::Value, String> {
+    if manifest.get("extends").is_some() {
+        let mut canonical = manifest.clone();
+        canonicalize_units(&mut canonical);
+        return Ok(canonical);
+    }
+
+    let parsed: Manifest = serde_json::from_value(manifest.clone()).map_err(|e| e.to_string())?;
+    let canonical = serde_json::to_value(&parsed).map_err(|e| e.to_string())?;
+
+    // Unknown fields are ignored on load; formatting must not silently delete them
+    let mut dropped = Vec::new();
+    dropped_keys(manifest, &canonical, "", &mut dropped);
+    if !dropped.is_empty() {
+        return Err(format!("unknown field(s): {}", dropped.join(", ")));
+    }
+    Ok(canonical)
+}
+
+/// Rename every `*_unit` value to the unit's canonical name
+fn canonicalize_units(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key.ends_with("_unit")
+                    && let Some(unit) = value.as_str().and_then(|s| s.parse::<SizeUnit>().ok())
+                {
+                    *value = serde_json::Value::String(unit.name().to_string());
+                } else {
+                    canonicalize_units(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(canonicalize_units),
+        _ => {}
+    }
+}
+
+/// Paths of the non-null object members of `original` missing from `canonical`
+fn dropped_keys(
+    original: &serde_json::Value,
+    canonical: &serde_json::Value,
+    path: &str,
+    dropped: &mut Vec<String>,
+) {
+    let child_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match (original, canonical) {
+        (serde_json::Value::Object(original), serde_json::Value::Object(canonical)) => {
+            for (key, value) in original {
+                match canonical.get(key) {
+                    Some(canonical_value) => {
+                        dropped_keys(value, canonical_value, &child_path(key), dropped)
+                    }
+                    None if !value.is_null() => dropped.push(child_path(key)),
+                    None => {}
+                }
+            }
+        }
+        (serde_json::Value::Array(original), serde_json::Value::Array(canonical)) => {
+            for (index, (value, canonical_value)) in original.iter().zip(canonical).enumerate() {
+                dropped_keys(value, canonical_value, &format!("{path}[{index}]"), dropped);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// How a manifest file is read
+#[derive(Debug, Clone, Default)]
+pub struct ManifestOptions {
+    /// File format; taken from the file extension when unset
+    pub format: Option<ManifestFormat>,
+    /// Values for `${NAME}` references given on the command line
+    pub vars: BTreeMap<String, String>,
+}
+
+impl ManifestOptions {
+    pub fn new(format: Option<ManifestFormat>, vars: &[(String, String)]) -> Self {
+        ManifestOptions {
+            format,
+            vars: vars.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Parse a `--var KEY=VALUE` argument
+pub fn parse_var(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("'{arg}' is not NAME=VALUE"))?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "Invalid variable name '{name}': use letters, digits and underscores"
+        ));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Expand `${NAME}` references in `value`; `${NAME:-default}` falls back to
+/// `default` when NAME has no value, and `$${` stands for a literal `${`.
+/// Returns the expanded string and the names `lookup` had no value and no
+/// default for, which expand to nothing.
+fn expand_references(
+    value: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> (String, Vec<String>) {
+    let mut result = String::with_capacity(value.len());
+    let mut missing = Vec::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            result.push_str("${");
+            rest = after;
+        } else if let Some((reference, after)) =
+            tail.strip_prefix("${").and_then(|t| t.split_once('}'))
+        {
+            let (name, default) = match reference.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (reference, None),
+            };
+            match lookup(name).or_else(|| default.map(str::to_string)) {
+                Some(replacement) => result.push_str(&replacement),
+                None => missing.push(name.to_string()),
+            }
+            rest = after;
+        } else {
+            result.push('$');
+            rest = &tail[1..];
+        }
+    }
+    result.push_str(rest);
+
+    (result, missing)
+}
+
+/// Expand `${NAME}` references in every string value of a resolved manifest.
+/// Values come from `cli_vars` first, then the environment, then the manifest's
+/// `variables` block. Provision env values are left alone: they are expanded
+/// from the environment of the provision run, as before.
+///
+/// Fails listing the references that have no value and no default, since an
+/// empty file name or device path is never what the manifest meant.
+fn expand_manifest_variables(
+    manifest: &mut serde_json::Value,
+    cli_vars: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let variables: BTreeMap<String, String> = manifest
+        .get("variables")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    let lookup = |name: &str| {
+        cli_vars
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok().filter(|v| !v.is_empty()))
+            .or_else(|| variables.get(name).cloned())
+    };
+
+    let mut missing = Vec::new();
+    for_each_expanded_string(manifest, &mut |s| {
+        let (expanded, names) = expand_references(s, &lookup);
+        *s = expanded;
+        missing.extend(names);
+    });
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    missing.sort();
+    missing.dedup();
+    Err(format!(
+        "The following variables are referenced in the manifest but not set: {}. Set them with --var NAME=VALUE, in the environment or in the variables block, or give a default with ${{NAME:-default}}.",
+        missing.join(", ")
+    ))
+}
+
+/// Call `f` on every string of a manifest that `${NAME}` references are
+/// expanded in: all but the `variables` block and provision env values
+fn for_each_expanded_string(value: &mut serde_json::Value, f: &mut dyn FnMut(&mut String)) {
+    fn walk(value: &mut serde_json::Value, path: &mut Vec<String>, f: &mut dyn FnMut(&mut String)) {
+        let skipped = matches!(
+            path.iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            ["variables"] | ["provision", "envs"] | ["provision", "profiles", _, "envs"]
+        );
+        if skipped {
+            return;
+        }
+        match value {
+            serde_json::Value::String(s) => f(s),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    walk(item, path, f);
+                }
+            }
+            serde_json::Value::Object(map) => {
+                for (key, item) in map {
+                    path.push(key.clone());
+                    walk(item, path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    walk(value, &mut Vec::new(), f);
+}
+
+/// Whether any string in `value` contains a `${` reference
+pub fn has_references(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => s.contains("${"),
+        serde_json::Value::Array(items) => items.iter().any(has_references),
+        serde_json::Value::Object(map) => map.values().any(has_references),
+        _ => false,
+    }
 }
 
 impl Manifest {
-    pub fn from_file(path: &std::path::Path) -> Result<Self, String> {
-        let content = std::fs::read_to_string(path).map_err(|e| {
+    /// Load a manifest, choosing the format from the file extension (JSON when unknown)
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        Self::load(path, &ManifestOptions::default())
+    }
+
+    /// Load a manifest merged over the manifests it extends, with `${NAME}` references expanded
+    pub fn load(path: &Path, options: &ManifestOptions) -> Result<Self, Error> {
+        Self::parse_file(path, options).map_err(Error::ManifestParse)
+    }
+
+    fn parse_file(path: &Path, options: &ManifestOptions) -> Result<Self, String> {
+        let format = ManifestFormat::resolve(options.format, path);
+        let value: serde_json::Value = read_manifest_file(path, format)?;
+        check_manifest_version(&value, path)?;
+
+        let manifest: Manifest = if value.get("extends").is_none() && !has_references(&value) {
+            // Parse the file directly, so errors point at the offending line
+            read_manifest_file(path, format)?
+        } else {
+            let resolved = Self::resolve_value(path, options)?;
+            serde_json::from_value(resolved).map_err(|e| {
+                format!(
+                    "Invalid manifest '{}' after resolving extends and variables: {}",
+                    path.display(),
+                    e
+                )
+            })?
+        };
+
+        for deprecation in manifest.deprecations() {
+            crate::log::log_warning(&format!("Manifest '{}': {deprecation}", path.display()));
+        }
+        Ok(manifest)
+    }
+
+    /// Deprecated shapes this manifest still uses, each with what replaces it
+    pub fn deprecations(&self) -> Vec<String> {
+        let mut deprecations = Vec::new();
+        if self.runtime.provision.is_some() {
+            deprecations.push(
+                "runtime.provision is deprecated; use provision.profiles instead. Run 'stone manifest upgrade' to convert it."
+                    .to_string(),
+            );
+        }
+        deprecations
+    }
+
+    /// Read a manifest merged over the manifests it extends, with `${NAME}` references
+    /// expanded, as a JSON value. The result no longer has an `extends` key.
+    pub fn resolve_file(
+        path: &Path,
+        options: &ManifestOptions,
+    ) -> Result<serde_json::Value, Error> {
+        Self::resolve_value(path, options).map_err(Error::ManifestParse)
+    }
+
+    fn resolve_value(path: &Path, options: &ManifestOptions) -> Result<serde_json::Value, String> {
+        let mut value = Self::merge_file(path, options.format)?;
+        expand_manifest_variables(&mut value, &options.vars)?;
+        Ok(value)
+    }
+
+    /// Read a manifest merged over the manifests it extends
+    fn merge_file(
+        path: &Path,
+        format: Option<ManifestFormat>,
+    ) -> Result<serde_json::Value, String> {
+        let value = read_manifest_file(path, ManifestFormat::resolve(format, path))?;
+        let canonical = path.canonicalize().map_err(|e| {
             format!(
-                "[ERROR] Failed to read manifest file '{}': {}",
+                "Failed to resolve manifest path '{}': {}",
                 path.display(),
                 e
             )
         })?;
+        resolve_extends(path, value, &mut vec![canonical])
+    }
 
-        serde_json::from_str(&content).map_err(|e| {
-            format!(
-                "[ERROR] Failed to parse manifest JSON '{}': {}",
-                path.display(),
-                e
-            )
-        })
+    /// Write a manifest file to `dest` as JSON, for build outputs that are read back later.
+    /// Plain JSON manifests without `extends` or `${NAME}` references are copied unchanged.
+    /// Others are written merged, with references expanded the way this build saw them,
+    /// so reloading the copy does not depend on the environment of a later run.
+    pub fn copy_as_json(path: &Path, options: &ManifestOptions, dest: &Path) -> Result<(), String> {
+        let format = ManifestFormat::resolve(options.format, path);
+        let value: serde_json::Value = read_manifest_file(path, format)?;
+
+        if format == ManifestFormat::Json
+            && value.get("extends").is_none()
+            && !has_references(&value)
+        {
+            std::fs::copy(path, dest).map_err(|e| {
+                format!(
+                    "Failed to copy manifest '{}' to '{}': {}",
+                    path.display(),
+                    dest.display(),
+                    e
+                )
+            })?;
+            return Ok(());
+        }
+
+        // Expansion turned `$${` escapes into a literal `${`; escape it again
+        let mut resolved = Self::resolve_value(path, options)?;
+        for_each_expanded_string(&mut resolved, &mut |s| {
+            if s.contains("${") {
+                *s = s.replace("${", "$${");
+            }
+        });
+
+        let json = ManifestFormat::Json.to_string(&resolved)?;
+        std::fs::write(dest, json)
+            .map_err(|e| format!("Failed to write manifest '{}': {}", dest.display(), e))
+    }
+
+    /// JSON Schema describing the manifest, generated from the types above
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Manifest)).unwrap_or_default()
     }
 
     pub fn get_provision_profile(&self, profile_name: &str) -> Option<&ProvisionProfile> {
@@ -331,6 +1698,177 @@ impl Manifest {
     pub fn get_provision_default(&self) -> Option<&str> {
         self.runtime.provision_default.as_deref()
     }
+
+    /// Structural problems that would otherwise only show up on a device:
+    /// broken references, duplicate names, overlapping partitions and malformed GUIDs.
+    /// File existence is not checked here.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(default_profile) = &self.runtime.provision_default {
+            match &self.provision {
+                Some(_) if self.get_provision_profile(default_profile).is_none() => {
+                    problems.push(format!(
+                        "runtime.provision_default: Profile '{default_profile}' not found in provision.profiles"
+                    ))
+                }
+                Some(_) => {}
+                None => problems.push(
+                    "runtime.provision_default: provision_default specified but no provision section found"
+                        .to_string(),
+                ),
+            }
+        }
+
+        let mut device_names: Vec<&String> = self.storage_devices.keys().collect();
+        device_names.sort();
+        for device_name in device_names {
+            let device = &self.storage_devices[device_name];
+            problems.extend(
+                device
+                    .check()
+                    .into_iter()
+                    .map(|problem| format!("device '{device_name}': {problem}")),
+            );
+        }
+
+        if let Some(update) = &self.update {
+            let mut artifact_names: Vec<&String> = update.os_artifacts.keys().collect();
+            artifact_names.sort();
+            for artifact_name in artifact_names {
+                let artifact = &update.os_artifacts[artifact_name];
+                let has_image = self
+                    .storage_devices
+                    .values()
+                    .any(|device| device.images.contains_key(&artifact.image_key));
+                if !has_image {
+                    problems.push(format!(
+                        "update.os_artifacts '{artifact_name}': image_key '{}' is not an image of any storage device",
+                        artifact.image_key
+                    ));
+                }
+                for partition in &artifact.slot_partitions {
+                    let has_partition = self.storage_devices.values().any(|device| {
+                        device
+                            .partitions
+                            .iter()
+                            .any(|p| p.name.as_deref() == Some(partition))
+                    });
+                    if !has_partition {
+                        problems.push(format!(
+                            "update.os_artifacts '{artifact_name}': slot partition '{partition}' is not a partition of any storage device"
+                        ));
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+impl StorageDevice {
+    /// Structural problems of this device; see [`Manifest::check`]
+    fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let label = |index: usize| match &self.partitions[index].name {
+            Some(name) => format!("partition '{name}'"),
+            None => format!("partition #{}", index + 1),
+        };
+
+        if let Some(uuid) = &self.uuid
+            && !is_guid(uuid)
+        {
+            problems.push(format!("uuid '{uuid}' is not a valid GUID"));
+        }
+
+        let mut image_names: Vec<&String> = self.images.keys().collect();
+        image_names.sort();
+        for image_name in image_names {
+            if let Some(uuid) = self.images[image_name].uuid()
+                && !is_guid(uuid)
+            {
+                problems.push(format!(
+                    "image '{image_name}': uuid '{uuid}' is not a valid GUID"
+                ));
+            }
+        }
+
+        let mut seen_names = std::collections::HashSet::new();
+        for (index, partition) in self.partitions.iter().enumerate() {
+            if let Some(name) = &partition.name
+                && !seen_names.insert(name)
+            {
+                problems.push(format!("partition name '{name}' is used more than once"));
+            }
+            if let Some(image) = &partition.image
+                && !self.images.contains_key(image)
+            {
+                problems.push(format!(
+                    "{} uses image '{image}', which is not defined in images",
+                    label(index)
+                ));
+            }
+            if let Some(uuid) = &partition.partition_uuid
+                && !is_guid(uuid)
+            {
+                problems.push(format!(
+                    "{}: partition_uuid '{uuid}' is not a valid GUID",
+                    label(index)
+                ));
+            }
+            // An MBR partition type is a byte, a GPT one a GUID; fwup templates may use either
+            if let Some(partition_type) = &partition.partition_type {
+                let is_mbr_type = mbr::parse_partition_type(partition_type).is_ok();
+                match &self.build_args {
+                    Some(BuildArgs::Mbr {}) if !is_mbr_type => problems.push(format!(
+                        "{}: partition_type '{partition_type}' is not an MBR partition type byte, e.g. 0x83",
+                        label(index)
+                    )),
+                    Some(BuildArgs::Mbr {}) => {}
+                    Some(BuildArgs::Gpt {}) if !is_guid(partition_type) => {
+                        problems.push(format!(
+                            "{}: partition_type '{partition_type}' is not a valid GUID",
+                            label(index)
+                        ))
+                    }
+                    _ if !is_guid(partition_type) && !is_mbr_type => problems.push(format!(
+                        "{}: partition_type '{partition_type}' is neither a GUID nor an MBR partition type byte",
+                        label(index)
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        // The layout reports collisions between fixed partitions
+        if let Err(e) = self.partition_layout() {
+            problems.push(e);
+            return problems;
+        }
+
+        if problems.is_empty() {
+            let table = match &self.build_args {
+                Some(BuildArgs::Gpt {}) => self.gpt_disk().map(drop),
+                Some(BuildArgs::Mbr {}) => self.mbr_disk().map(drop),
+                _ => Ok(()),
+            };
+            if let Err(e) = table {
+                problems.push(e);
+            }
+        }
+
+        problems
+    }
+}
+
+/// Whether `value` is a GUID in its textual form, e.g. 0fc63daf-8483-4772-8e79-3d69d8477de4
+pub fn is_guid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
 }
 
 impl Provision {
@@ -351,12 +1889,12 @@ impl Provision {
                                 }
                             } else {
                                 return Err(format!(
-                                    "[ERROR] Named environment block '{env_name}' not found in provision.envs."
+                                    "Named environment block '{env_name}' not found in provision.envs."
                                 ));
                             }
                         } else {
                             return Err(format!(
-                                "[ERROR] Named environment block '{env_name}' referenced but no provision.envs defined."
+                                "Named environment block '{env_name}' referenced but no provision.envs defined."
                             ));
                         }
                     }
@@ -481,7 +2019,7 @@ mod tests {
                 files: vec![],
             }),
             size: 100,
-            size_unit: "megabytes".to_string(),
+            size_unit: SizeUnit::Megabytes,
             block_size: None,
             uuid: None,
         };
@@ -501,7 +2039,7 @@ mod tests {
                 template: "disk.conf".to_string(),
             }),
             size: 512,
-            size_unit: "megabytes".to_string(),
+            size_unit: SizeUnit::Megabytes,
             block_size: Some(4096),
             uuid: Some("12345678-1234-1234-1234-123456789abc".to_string()),
         };
@@ -517,7 +2055,7 @@ mod tests {
             out: "simple.img".to_string(),
             build_args: None,
             size: 256,
-            size_unit: "megabytes".to_string(),
+            size_unit: SizeUnit::Megabytes,
             block_size: None,
             uuid: None,
         };
@@ -635,14 +2173,573 @@ mod tests {
         assert_eq!(partition.name, Some("uboot-env".to_string()));
         assert_eq!(partition.image, Some("uboot_env".to_string()));
         assert_eq!(partition.offset, Some(1));
-        assert_eq!(partition.offset_unit, Some("mebibytes".to_string()));
+        assert_eq!(partition.offset_unit, Some(SizeUnit::Mebibytes));
         assert_eq!(partition.offset_redundant, Some(1152));
+        assert_eq!(partition.offset_redundant_unit, Some(SizeUnit::Kibibytes));
+        assert_eq!(partition.size, 128);
+        assert_eq!(partition.size_unit, SizeUnit::Kibibytes);
+    }
+
+    #[test]
+    fn test_size_units() {
+        assert_eq!(Size::new(1, SizeUnit::Kilobytes).bytes(512).unwrap(), 1000);
+        assert_eq!(Size::new(1, SizeUnit::Kibibytes).bytes(512).unwrap(), 1024);
         assert_eq!(
-            partition.offset_redundant_unit,
-            Some("kibibytes".to_string())
+            Size::new(2, SizeUnit::Megabytes).bytes(512).unwrap(),
+            2_000_000
+        );
+        assert_eq!(
+            Size::new(2, SizeUnit::Mebibytes).bytes(512).unwrap(),
+            2 << 20
+        );
+        assert_eq!(
+            Size::new(3, SizeUnit::Terabytes).bytes(512).unwrap(),
+            3_000_000_000_000
+        );
+        assert_eq!(Size::new(8, SizeUnit::Blocks).bytes(4096).unwrap(), 32768);
+        assert!(Size::new(u64::MAX, SizeUnit::Kibibytes).bytes(512).is_err());
+
+        assert_eq!(Size::new(1, SizeUnit::Mebibytes).blocks(512).unwrap(), 2048);
+        assert!(Size::new(1, SizeUnit::Kilobytes).blocks(512).is_err());
+        assert_eq!(
+            Size::new(1, SizeUnit::Kilobytes).blocks_ceil(512).unwrap(),
+            2
+        );
+
+        assert_eq!(Size::new(16, SizeUnit::Megabytes).to_string(), "16 MB");
+        assert_eq!(Size::new(16, SizeUnit::Mebibytes).to_string(), "16 MiB");
+    }
+
+    #[test]
+    fn test_size_unit_parsing() {
+        assert_eq!(
+            "mebibytes".parse::<SizeUnit>().unwrap(),
+            SizeUnit::Mebibytes
+        );
+        assert_eq!("MiB".parse::<SizeUnit>().unwrap(), SizeUnit::Mebibytes);
+        assert_eq!("MB".parse::<SizeUnit>().unwrap(), SizeUnit::Megabytes);
+        assert_eq!("block".parse::<SizeUnit>().unwrap(), SizeUnit::Blocks);
+        assert!(
+            "parsecs"
+                .parse::<SizeUnit>()
+                .unwrap_err()
+                .contains("Unsupported size unit: parsecs")
+        );
+
+        let unit: SizeUnit = serde_json::from_str(r#""KiB""#).unwrap();
+        assert_eq!(serde_json::to_string(&unit).unwrap(), r#""kibibytes""#);
+    }
+
+    #[test]
+    fn test_partition_layout() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "block_size": 512,
+            "images": {},
+            "partitions": [
+                { "name": "boot", "offset": 1, "offset_unit": "mebibytes", "size": 1, "size_unit": "megabytes" },
+                { "name": "rootfs", "size": 4, "size_unit": "mebibytes" },
+                { "name": "data", "offset": 16384, "size": 8, "size_unit": "blocks" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        let layout = device.partition_layout().unwrap();
+
+        let placed: Vec<_> = layout.iter().map(|p| (p.offset, p.size)).collect();
+        assert_eq!(
+            placed,
+            [
+                (1 << 20, 1_000_448),
+                ((1 << 20) + 1_000_448, 4 << 20),
+                (16384 * 512, 4096)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partition_layout_alignment() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "build_args": { "type": "gpt" },
+            "alignment": 1,
+            "alignment_unit": "mebibytes",
+            "images": {},
+            "partitions": [
+                { "name": "boot", "size": 1, "size_unit": "mebibytes" },
+                { "name": "env", "offset": 2048, "offset_unit": "kibibytes", "size": 64, "size_unit": "kibibytes",
+                  "offset_redundant": 3, "offset_redundant_unit": "mebibytes" },
+                { "name": "rootfs", "size": 2, "size_unit": "mebibytes" },
+                { "name": "data", "size": 512, "size_unit": "kibibytes" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        let layout = device.partition_layout().unwrap();
+
+        let placed: Vec<_> = layout
+            .iter()
+            .map(|p| (p.offset >> 10, p.redundant_offset.map(|o| o >> 10)))
+            .collect();
+        // boot skips the GPT; rootfs skips env and its redundant copy
+        assert_eq!(
+            placed,
+            [(1024, None), (2048, Some(3072)), (4096, None), (6144, None)]
+        );
+    }
+
+    #[test]
+    fn test_partition_layout_collision() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "images": {},
+            "partitions": [
+                { "name": "boot", "offset": 2048, "size": 1, "size_unit": "mebibytes" },
+                { "name": "env", "offset": 4000, "size": 64, "size_unit": "kibibytes" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        assert_eq!(
+            device.partition_layout().unwrap_err(),
+            "partition 'boot' (bytes 1048576..2097152) overlaps partition 'env' (bytes 2048000..2113536)"
+        );
+    }
+
+    #[test]
+    fn test_partition_layout_expand_to_capacity() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "capacity": 64,
+            "capacity_unit": "mebibytes",
+            "alignment": 1,
+            "alignment_unit": "mebibytes",
+            "build_args": { "type": "gpt" },
+            "images": {},
+            "partitions": [
+                { "name": "boot", "size": 16, "size_unit": "mebibytes" },
+                { "name": "rootfs", "size": 8, "size_unit": "mebibytes", "expand": "true" },
+                { "name": "data", "offset": 48, "offset_unit": "mebibytes", "size": 8, "size_unit": "mebibytes", "expand": "true" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        assert_eq!(device.capacity(), Ok(Some(64 << 20)));
+        let sizes: Vec<(u64, u64)> = device
+            .partition_layout()
+            .unwrap()
+            .iter()
+            .map(|placed| (placed.offset, placed.size))
+            .collect();
+        // rootfs stops at data; data stops at the backup GPT
+        assert_eq!(
+            sizes,
+            vec![
+                (1 << 20, 16 << 20),
+                (17 << 20, 31 << 20),
+                (48 << 20, (16 << 20) - 33 * 512)
+            ]
+        );
+        assert_eq!(device.gpt_disk().unwrap().blocks, 64 * 2048);
+    }
+
+    #[test]
+    fn test_partition_layout_expand_zero_size() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "capacity": 64,
+            "capacity_unit": "mebibytes",
+            "build_args": { "type": "gpt" },
+            "images": {},
+            "partitions": [
+                { "name": "boot", "offset": 2048, "size": 8, "size_unit": "mebibytes" },
+                { "name": "rootfs", "size": 0, "size_unit": "mebibytes", "expand": "true" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        let layout = device.partition_layout().unwrap();
+        assert_eq!(layout[1].offset, 9 << 20);
+        assert_eq!(layout[1].size, (55 << 20) - 33 * 512);
+    }
+
+    #[test]
+    fn test_partition_layout_exceeds_capacity() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "capacity": 4096,
+            "images": {},
+            "partitions": [
+                { "name": "rootfs", "offset": 2048, "size": 2, "size_unit": "mebibytes" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        assert_eq!(
+            device.partition_layout().unwrap_err(),
+            "partition 'rootfs' (bytes 1048576..3145728) does not fit on the device, which has 2097152 usable bytes"
+        );
+    }
+
+    #[test]
+    fn test_size_from_bytes() {
+        assert_eq!(Size::from_bytes(3 << 20).to_string(), "3 MiB");
+        assert_eq!(Size::from_bytes(1536).to_string(), "1536 bytes");
+        assert_eq!(Size::from_bytes(0).to_string(), "0 bytes");
+    }
+
+    #[test]
+    fn test_partition_layout_misaligned_offset() {
+        let json_str = r#"{
+            "out": "disk.img",
+            "devpath": "/dev/sda",
+            "images": {},
+            "partitions": [
+                { "name": "boot", "offset": 1, "offset_unit": "kilobytes", "size": 1, "size_unit": "mebibytes" }
+            ]
+        }"#;
+        let device: StorageDevice = serde_json::from_str(json_str).unwrap();
+        let err = device.partition_layout().unwrap_err();
+        assert!(err.contains("partition 'boot' offset"), "{err}");
+        assert!(err.contains("512-byte block size"), "{err}");
+    }
+
+    #[test]
+    fn test_manifest_check() {
+        let json_str = r#"{
+            "runtime": { "platform": "rpi4", "architecture": "aarch64", "provision_default": "factory" },
+            "storage_devices": {
+                "rootdisk": {
+                    "out": "rootdisk.img",
+                    "devpath": "/dev/mmcblk0",
+                    "uuid": "not-a-guid",
+                    "images": { "boot": "boot.img", "rootfs": { "out": "rootfs.img", "size": 1, "size_unit": "mebibytes", "uuid": "4bc367b3-5d70-4289-b24d-9b09cb79685c" } },
+                    "partitions": [
+                        { "name": "env", "offset": 2048, "size": 64, "size_unit": "kibibytes", "offset_redundant": 4100 },
+                        { "name": "boot", "image": "boot", "offset": 2048, "offset_unit": "kibibytes", "size": 64, "size_unit": "mebibytes",
+                          "partition_type": "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" },
+                        { "name": "rootfs_a", "image": "rootfs", "size": 1, "size_unit": "gibibytes",
+                          "partition_uuid": "5dfbf5f4-2848-4bac-aa5e-0d9a20b745a66" },
+                        { "name": "rootfs_a", "image": "kernel", "size": 1, "size_unit": "gibibytes" }
+                    ]
+                }
+            },
+            "update": {
+                "slot_detection": { "type": "uboot-env", "var": "boot_slot" },
+                "os_artifacts": { "rootfs": { "image_key": "rootfs", "slot_partitions": ["rootfs_a", "rootfs_b"] },
+                                  "kernel": { "image_key": "zimage", "slot_partitions": [] } },
+                "activate": { "type": "uboot-env", "set": { "boot_slot": "{slot}" } }
+            }
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json_str).unwrap();
+
+        assert_eq!(
+            manifest.check(),
+            [
+                "runtime.provision_default: provision_default specified but no provision section found",
+                "device 'rootdisk': uuid 'not-a-guid' is not a valid GUID",
+                "device 'rootdisk': partition 'rootfs_a': partition_uuid '5dfbf5f4-2848-4bac-aa5e-0d9a20b745a66' is not a valid GUID",
+                "device 'rootdisk': partition name 'rootfs_a' is used more than once",
+                "device 'rootdisk': partition 'rootfs_a' uses image 'kernel', which is not defined in images",
+                "device 'rootdisk': partition 'boot' (bytes 2097152..69206016) overlaps redundant copy of partition 'env' (bytes 2099200..2164736)",
+                "update.os_artifacts 'kernel': image_key 'zimage' is not an image of any storage device",
+                "update.os_artifacts 'rootfs': slot partition 'rootfs_b' is not a partition of any storage device",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partition_type_check() {
+        let problems = |build_args: &str, partition_type: &str| {
+            let json_str = format!(
+                r#"{{
+                    "out": "disk.img",
+                    "devpath": "/dev/sda",
+                    {build_args}
+                    "images": {{}},
+                    "partitions": [
+                        {{ "name": "boot", "partition_type": "{partition_type}", "offset": 2048, "size": 1, "size_unit": "mebibytes" }}
+                    ]
+                }}"#
+            );
+            serde_json::from_str::<StorageDevice>(&json_str)
+                .unwrap()
+                .check()
+        };
+        let esp = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+
+        assert!(problems(r#""build_args": { "type": "mbr" },"#, "0x0c").is_empty());
+        assert_eq!(
+            problems(r#""build_args": { "type": "mbr" },"#, esp),
+            [format!(
+                "partition 'boot': partition_type '{esp}' is not an MBR partition type byte, e.g. 0x83"
+            )]
+        );
+        assert!(problems(r#""build_args": { "type": "gpt" },"#, esp).is_empty());
+        assert_eq!(
+            problems(r#""build_args": { "type": "gpt" },"#, "0x0c"),
+            ["partition 'boot': partition_type '0x0c' is not a valid GUID"]
+        );
+        assert!(problems("", "0x0c").is_empty());
+        assert!(problems("", esp).is_empty());
+        assert_eq!(
+            problems("", "fat32"),
+            [
+                "partition 'boot': partition_type 'fat32' is neither a GUID nor an MBR partition type byte"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_guid() {
+        assert!(is_guid("0fc63daf-8483-4772-8e79-3d69d8477de4"));
+        assert!(is_guid("C12A7328-F81F-11D2-BA4B-00A0C93EC93B"));
+        assert!(!is_guid("0fc63daf848347728e793d69d8477de4"));
+        assert!(!is_guid("{0fc63daf-8483-4772-8e79-3d69d8477de4}"));
+        assert!(!is_guid("0fc63daf-8483-4772-8e79-3d69d8477dg4"));
+        assert!(!is_guid("0x83"));
+    }
+
+    #[test]
+    fn test_manifest_format_from_extension() {
+        let cases = [
+            ("manifest.json", Some(ManifestFormat::Json)),
+            ("manifest.JSONC", Some(ManifestFormat::Json5)),
+            ("manifest.json5", Some(ManifestFormat::Json5)),
+            ("board/manifest.yml", Some(ManifestFormat::Yaml)),
+            ("manifest.yaml", Some(ManifestFormat::Yaml)),
+            ("manifest.toml", Some(ManifestFormat::Toml)),
+            ("stone.manifest", None),
+            ("manifest", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(
+                ManifestFormat::from_extension(Path::new(path)),
+                expected,
+                "{path}"
+            );
+        }
+        assert_eq!(
+            ManifestFormat::resolve(None, Path::new("stone.manifest")),
+            ManifestFormat::Json
+        );
+        assert_eq!(
+            ManifestFormat::resolve(Some(ManifestFormat::Yaml), Path::new("manifest.json")),
+            ManifestFormat::Yaml
+        );
+    }
+
+    #[test]
+    fn test_manifest_formats_parse_the_same_model() {
+        let json = r#"{
+            "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+            "storage_devices": {
+                "rootdisk": {
+                    "out": "rootdisk.img",
+                    "devpath": "/dev/mmcblk0",
+                    "images": { "boot": "boot.img" },
+                    "partitions": [
+                        { "image": "boot", "offset": 8192, "size": 64, "size_unit": "mebibytes" }
+                    ]
+                }
+            }
+        }"#;
+        let json5 = r#"{
+            // Same manifest, with comments and trailing commas
+            runtime: { platform: "rpi4", architecture: "aarch64", },
+            storage_devices: {
+                rootdisk: {
+                    out: "rootdisk.img",
+                    devpath: "/dev/mmcblk0",
+                    images: { boot: "boot.img" },
+                    partitions: [
+                        // 4 MiB in, after the bootloader
+                        { image: "boot", offset: 8192, size: 64, size_unit: "mebibytes" },
+                    ],
+                },
+            },
+        }"#;
+        let yaml = r#"
+runtime:
+  platform: rpi4
+  architecture: aarch64
+storage_devices:
+  rootdisk:
+    out: rootdisk.img
+    devpath: /dev/mmcblk0
+    images:
+      boot: boot.img
+    partitions:
+      # 4 MiB in, after the bootloader
+      - image: boot
+        offset: 8192
+        size: 64
+        size_unit: mebibytes
+"#;
+        let toml = r#"
+[runtime]
+platform = "rpi4"
+architecture = "aarch64"
+
+[storage_devices.rootdisk]
+out = "rootdisk.img"
+devpath = "/dev/mmcblk0"
+images = { boot = "boot.img" }
+
+# 4 MiB in, after the bootloader
+[[storage_devices.rootdisk.partitions]]
+image = "boot"
+offset = 8192
+size = 64
+size_unit = "mebibytes"
+"#;
+
+        let expected: serde_json::Value = ManifestFormat::Json.parse(json).unwrap();
+        for (format, content) in [
+            (ManifestFormat::Json5, json5),
+            (ManifestFormat::Yaml, yaml),
+            (ManifestFormat::Toml, toml),
+        ] {
+            let value: serde_json::Value = format.parse(content).unwrap();
+            assert_eq!(value, expected, "{format}");
+
+            let manifest: Manifest = format.parse(content).unwrap();
+            let partition = &manifest.storage_devices["rootdisk"].partitions[0];
+            assert_eq!(partition.size().bytes(512).unwrap(), 64 << 20);
+        }
+    }
+
+    #[test]
+    fn test_expand_references() {
+        let lookup = |name: &str| match name {
+            "CHANNEL" => Some("beta".to_string()),
+            "EMPTY" => Some(String::new()),
+            "LOOP" => Some("${CHANNEL}".to_string()),
+            _ => None,
+        };
+
+        let cases = [
+            ("rootfs-${CHANNEL}.img", "rootfs-beta.img", vec![]),
+            ("${CHANNEL}/${CHANNEL}", "beta/beta", vec![]),
+            ("a${EMPTY}b", "ab", vec![]),
+            ("${LOOP}", "${CHANNEL}", vec![]),
+            ("$${CHANNEL} costs $5", "${CHANNEL} costs $5", vec![]),
+            ("${UNSET}-${CHANNEL}", "-beta", vec!["UNSET"]),
+            ("${UNSET:-stable}/${CHANNEL:-stable}", "stable/beta", vec![]),
+            ("a${UNSET:-}b", "ab", vec![]),
+            ("unterminated ${CHANNEL", "unterminated ${CHANNEL", vec![]),
+        ];
+        for (input, expected, missing) in cases {
+            let (expanded, names) = expand_references(input, &lookup);
+            assert_eq!(expanded, expected, "{input}");
+            assert_eq!(names, missing, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_expand_manifest_variables() {
+        let mut manifest = serde_json::json!({
+            "variables": { "STONE_TEST_CHANNEL": "stable", "STONE_TEST_DEVICE": "mmcblk0" },
+            "storage_devices": {
+                "rootdisk": {
+                    "out": "rootdisk-${STONE_TEST_CHANNEL}.img",
+                    "devpath": "/dev/${STONE_TEST_DEVICE}",
+                    "images": { "rootfs": "rootfs-${STONE_TEST_CHANNEL}.img" },
+                    "partitions": [{ "size": 1, "size_unit": "mebibytes" }]
+                }
+            },
+            "provision": {
+                "envs": { "base": { "HOST": "${STONE_TEST_CHANNEL}" } },
+                "profiles": {
+                    "default": { "script": "${STONE_TEST_CHANNEL}.sh", "envs": [{ "A": "${STONE_TEST_CHANNEL}" }] }
+                }
+            }
+        });
+        let cli_vars = BTreeMap::from([("STONE_TEST_CHANNEL".to_string(), "beta".to_string())]);
+        expand_manifest_variables(&mut manifest, &cli_vars).unwrap();
+
+        let device = &manifest["storage_devices"]["rootdisk"];
+        assert_eq!(device["out"], "rootdisk-beta.img");
+        assert_eq!(device["devpath"], "/dev/mmcblk0");
+        assert_eq!(device["images"]["rootfs"], "rootfs-beta.img");
+        assert_eq!(
+            manifest["provision"]["profiles"]["default"]["script"],
+            "beta.sh"
+        );
+
+        // Provision env values are expanded when the profile runs, not on load
+        assert_eq!(
+            manifest["provision"]["envs"]["base"]["HOST"],
+            "${STONE_TEST_CHANNEL}"
+        );
+        assert_eq!(
+            manifest["provision"]["profiles"]["default"]["envs"][0]["A"],
+            "${STONE_TEST_CHANNEL}"
+        );
+    }
+
+    #[test]
+    fn test_expand_manifest_variables_unset() {
+        let mut manifest = serde_json::json!({
+            "storage_devices": {
+                "rootdisk": {
+                    "out": "${STONE_TEST_UNSET_OUT}",
+                    "devpath": "/dev/${STONE_TEST_UNSET_DISK}",
+                    "images": { "rootfs": "rootfs${STONE_TEST_UNSET_SUFFIX:-}.img" }
+                }
+            }
+        });
+        assert_eq!(
+            expand_manifest_variables(&mut manifest, &BTreeMap::new()).unwrap_err(),
+            "The following variables are referenced in the manifest but not set: STONE_TEST_UNSET_DISK, STONE_TEST_UNSET_OUT. Set them with --var NAME=VALUE, in the environment or in the variables block, or give a default with ${NAME:-default}."
+        );
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(
+            parse_var("CHANNEL=beta=2").unwrap(),
+            ("CHANNEL".to_string(), "beta=2".to_string())
+        );
+        assert_eq!(
+            parse_var("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_var("CHANNEL").is_err());
+        assert!(parse_var("=beta").is_err());
+        assert!(parse_var("MY-VAR=1").is_err());
+    }
+
+    #[test]
+    fn test_manifest_format_round_trip() {
+        let value = serde_json::json!({
+            "runtime": { "platform": "rpi4", "architecture": "aarch64", "provision": null },
+            "storage_devices": {}
+        });
+        for format in [
+            ManifestFormat::Json,
+            ManifestFormat::Json5,
+            ManifestFormat::Yaml,
+            ManifestFormat::Toml,
+        ] {
+            let content = format.to_string(&value).unwrap();
+            let parsed: serde_json::Value = format.parse(&content).unwrap();
+            assert_eq!(parsed["runtime"]["platform"], "rpi4", "{format}: {content}");
+            assert_eq!(parsed["storage_devices"], serde_json::json!({}), "{format}");
+        }
+
+        let value = serde_json::json!({ "images": { "boot-fw": "boot.img", "rootfs": [1, 2] } });
+        let content = ManifestFormat::Json5.to_string(&value).unwrap();
+        assert_eq!(
+            content,
+            "{\n  images: {\n    \"boot-fw\": \"boot.img\",\n    rootfs: [\n      1,\n      2,\n    ],\n  },\n}\n"
+        );
+        assert_eq!(
+            ManifestFormat::Json5
+                .parse::<serde_json::Value>(&content)
+                .unwrap(),
+            value
         );
-        assert_eq!(partition.size, 128);
-        assert_eq!(partition.size_unit, "kibibytes");
     }
 
     #[test]
@@ -913,4 +3010,104 @@ mod tests {
         assert!(manifest.get_provision_profile("nonexistent").is_none());
         assert_eq!(manifest.get_provision_default(), Some("default_profile"));
     }
+
+    #[test]
+    fn test_upgrade_manifest() {
+        let mut value = serde_json::json!({
+            "runtime": { "platform": "test", "architecture": "x86_64", "provision": "provision.sh" },
+            "storage_devices": {}
+        });
+        let changes = upgrade_manifest(&mut value).unwrap();
+        assert_eq!(changes.len(), 3, "{changes:?}");
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "manifest_version": MANIFEST_VERSION,
+                "runtime": {
+                    "platform": "test",
+                    "architecture": "x86_64",
+                    "provision_default": "default"
+                },
+                "provision": { "profiles": { "default": { "script": "provision.sh" } } },
+                "storage_devices": {}
+            })
+        );
+        let manifest: Manifest = serde_json::from_value(value.clone()).unwrap();
+        assert!(manifest.deprecations().is_empty());
+
+        // Already current
+        assert!(upgrade_manifest(&mut value).unwrap().is_empty());
+
+        // The legacy script never ran next to a provision section, so it is not made the default
+        let mut value = serde_json::json!({
+            "runtime": { "platform": "test", "architecture": "x86_64", "provision": "old.sh" },
+            "provision": { "profiles": { "default": { "script": "new.sh" } } },
+            "storage_devices": {}
+        });
+        upgrade_manifest(&mut value).unwrap();
+        assert_eq!(
+            value["provision"]["profiles"]["legacy"],
+            serde_json::json!({ "script": "old.sh" })
+        );
+        assert!(value["runtime"].get("provision_default").is_none());
+        assert!(value["runtime"].get("provision").is_none());
+    }
+
+    #[test]
+    fn test_check_manifest_version() {
+        let path = Path::new("manifest.json");
+        assert!(check_manifest_version(&serde_json::json!({}), path).is_ok());
+        assert!(
+            check_manifest_version(&serde_json::json!({ "manifest_version": 2 }), path).is_ok()
+        );
+        let err = check_manifest_version(
+            &serde_json::json!({ "manifest_version": MANIFEST_VERSION + 1 }),
+            path,
+        )
+        .unwrap_err();
+        assert!(err.contains("newer than this stone supports"), "{err}");
+        assert!(
+            check_manifest_version(&serde_json::json!({ "manifest_version": "2" }), path).is_err()
+        );
+    }
+
+    #[test]
+    fn test_canonical_manifest() {
+        let manifest = serde_json::json!({
+            "storage_devices": {
+                "rootdisk": {
+                    "out": "rootdisk.img",
+                    "devpath": "/dev/sda",
+                    "images": { "boot": "boot.img" },
+                    "partitions": [
+                        { "image": "boot", "size": 64, "size_unit": "MiB", "offset_unit": null }
+                    ]
+                }
+            },
+            "runtime": { "platform": "test", "architecture": "x86_64" }
+        });
+        let canonical = canonical_manifest(&manifest).unwrap();
+        assert_eq!(
+            canonical["storage_devices"]["rootdisk"]["partitions"][0],
+            serde_json::json!({ "image": "boot", "size": 64, "size_unit": "mebibytes" })
+        );
+        assert_eq!(canonical_manifest(&canonical).unwrap(), canonical);
+
+        let mut unknown = manifest.clone();
+        unknown["runtime"]["platfrom"] = "typo".into();
+        let err = canonical_manifest(&unknown).unwrap_err();
+        assert_eq!(err, "unknown field(s): runtime.platfrom");
+
+        // Manifests that extend another are only partly checked
+        let variant = serde_json::json!({
+            "extends": "base.json",
+            "storage_devices": { "rootdisk": { "partitions": [{ "name": "data", "size_unit": "GB" }] } }
+        });
+        let canonical = canonical_manifest(&variant).unwrap();
+        assert_eq!(
+            canonical["storage_devices"]["rootdisk"]["partitions"][0]["size_unit"],
+            "gigabytes"
+        );
+        assert_eq!(canonical["extends"], "base.json");
+    }
 }
@@ -0,0 +1,469 @@
+//! DOS (MBR) partition table reader and writer, with primary, extended and logical partitions.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Linux; the type of partitions that do not set one
+pub const LINUX: u8 = 0x83;
+/// Extended partition addressed by LBA, holding the logical partitions
+pub const EXTENDED_LBA: u8 = 0x0f;
+/// Type of the entries that link one extended boot record to the next
+const EXTENDED_LINK: u8 = 0x05;
+/// Status byte of a bootable (active) partition
+const BOOTABLE: u8 = 0x80;
+/// Extended partition types other tools write: CHS-addressed and Linux
+const EXTENDED_TYPES: [u8; 3] = [EXTENDED_LINK, EXTENDED_LBA, 0x85];
+/// Type of the single partition of a GPT disk's protective MBR
+const GPT_PROTECTIVE: u8 = 0xee;
+/// Entries in the partition table of the MBR and of each extended boot record
+pub const PRIMARY_COUNT: usize = 4;
+
+/// Parse an MBR partition type byte such as `0x83`, `0C` or `ef`
+pub fn parse_partition_type(value: &str) -> Result<u8, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    if hex.is_empty() || hex.len() > 2 {
+        return Err(format!(
+            "'{value}' is not an MBR partition type byte, e.g. 0x83"
+        ));
+    }
+    u8::from_str_radix(hex, 16)
+        .map_err(|_| format!("'{value}' is not an MBR partition type byte, e.g. 0x83"))
+}
+
+/// Fields of one 16-byte partition table entry: status, type, start and sectors
+fn read_entry(entry: &[u8]) -> (u8, u8, u64, u64) {
+    let le_u32 = |bytes: &[u8]| u64::from(u32::from_le_bytes(bytes.try_into().unwrap()));
+    (
+        entry[0],
+        entry[4],
+        le_u32(&entry[8..12]),
+        le_u32(&entry[12..16]),
+    )
+}
+
+/// Fill one 16-byte partition table entry. CHS fields are unused; mark them as LBA-only.
+pub fn write_entry(entry: &mut [u8], status: u8, partition_type: u8, start: u32, sectors: u32) {
+    entry[0] = status;
+    entry[1..4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[4] = partition_type;
+    entry[5..8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[8..12].copy_from_slice(&start.to_le_bytes());
+    entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+}
+
+/// One partition. LBAs are in blocks of the disk's block size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbrPartition {
+    /// Name used in error messages; an MBR does not store names
+    pub name: String,
+    pub partition_type: u8,
+    pub bootable: bool,
+    pub first_lba: u64,
+    pub blocks: u64,
+}
+
+impl MbrPartition {
+    fn end_lba(&self) -> u64 {
+        self.first_lba + self.blocks
+    }
+}
+
+/// A disk with a DOS partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbrDisk {
+    pub disk_signature: u32,
+    pub block_size: u64,
+    /// Size of the whole disk in blocks
+    pub blocks: u64,
+    pub primary: Vec<MbrPartition>,
+    /// Logical partitions, in order of their start. Each is preceded by its
+    /// extended boot record in the block before it.
+    pub logical: Vec<MbrPartition>,
+}
+
+impl MbrDisk {
+    /// The extended partition holding the logical partitions and their
+    /// extended boot records, as (first LBA, blocks)
+    pub fn extended(&self) -> Option<(u64, u64)> {
+        let first = self.logical.first()?.first_lba.checked_sub(1)?;
+        let end = self.logical.iter().map(MbrPartition::end_lba).max()?;
+        Some((first, end - first))
+    }
+
+    /// Check that the partitions fit in the table and on the disk, are
+    /// addressable with 32-bit LBAs and do not overlap
+    pub fn check(&self) -> Result<(), String> {
+        if self.block_size < 512 {
+            return Err(format!(
+                "MBR block size must be at least 512 bytes, not {}",
+                self.block_size
+            ));
+        }
+        let primary_entries = self.primary.len() + usize::from(!self.logical.is_empty());
+        if primary_entries > PRIMARY_COUNT {
+            return Err(format!(
+                "an MBR holds at most {PRIMARY_COUNT} primary partitions, counting the extended partition for logical ones; this layout needs {primary_entries}"
+            ));
+        }
+
+        for partition in self.primary.iter().chain(&self.logical) {
+            if partition.blocks == 0 {
+                return Err(format!("MBR partition '{}' is empty", partition.name));
+            }
+            if partition.first_lba == 0 {
+                return Err(format!(
+                    "MBR partition '{}' starts at block 0, over the partition table",
+                    partition.name
+                ));
+            }
+            if partition.end_lba() > self.blocks {
+                return Err(format!(
+                    "MBR partition '{}' ends past the end of the disk",
+                    partition.name
+                ));
+            }
+            if u32::try_from(partition.end_lba()).is_err() {
+                return Err(format!(
+                    "MBR partition '{}' ends beyond the 2^32 blocks an MBR can address",
+                    partition.name
+                ));
+            }
+        }
+
+        let mut previous_end = 1;
+        for partition in &self.logical {
+            if partition.first_lba - 1 < previous_end {
+                return Err(format!(
+                    "logical partition '{}' needs a free block before it for its extended boot record",
+                    partition.name
+                ));
+            }
+            previous_end = partition.end_lba();
+        }
+
+        let mut regions: Vec<(&str, u64, u64)> = self
+            .primary
+            .iter()
+            .map(|p| (p.name.as_str(), p.first_lba, p.end_lba()))
+            .collect();
+        if let Some((first, blocks)) = self.extended() {
+            regions.push(("extended partition", first, first + blocks));
+        }
+        regions.sort_by_key(|(_, start, _)| *start);
+        for pair in regions.windows(2) {
+            if pair[1].1 < pair[0].2 {
+                return Err(format!(
+                    "MBR partitions '{}' and '{}' overlap",
+                    pair[0].0, pair[1].0
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the MBR and the extended boot records of the logical partitions.
+    /// The rest of the disk is left untouched.
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<(), String> {
+        self.check()?;
+
+        let mut mbr = self.boot_record();
+        mbr[440..444].copy_from_slice(&self.disk_signature.to_le_bytes());
+        let mut entries = mbr[446..510].chunks_exact_mut(16);
+        for (partition, entry) in self.primary.iter().zip(&mut entries) {
+            write_entry(
+                entry,
+                if partition.bootable { BOOTABLE } else { 0 },
+                partition.partition_type,
+                partition.first_lba as u32,
+                partition.blocks as u32,
+            );
+        }
+        let extended = self.extended();
+        if let (Some((first, blocks)), Some(entry)) = (extended, entries.next()) {
+            write_entry(entry, 0, EXTENDED_LBA, first as u32, blocks as u32);
+        }
+
+        let mut write_at = |lba: u64, data: &[u8]| -> Result<(), String> {
+            writer
+                .seek(SeekFrom::Start(lba * self.block_size))
+                .and_then(|_| writer.write_all(data))
+                .map_err(|e| format!("Failed to write partition table at block {lba}: {e}"))
+        };
+        write_at(0, &mbr)?;
+
+        // Each extended boot record describes its logical partition relative to
+        // itself, and links to the next one relative to the extended partition.
+        let extended_first = extended.map_or(0, |(first, _)| first);
+        for (index, partition) in self.logical.iter().enumerate() {
+            let ebr_lba = partition.first_lba - 1;
+            let mut ebr = self.boot_record();
+            write_entry(
+                &mut ebr[446..462],
+                if partition.bootable { BOOTABLE } else { 0 },
+                partition.partition_type,
+                1,
+                partition.blocks as u32,
+            );
+            if let Some(next) = self.logical.get(index + 1) {
+                let next_ebr_lba = next.first_lba - 1;
+                write_entry(
+                    &mut ebr[462..478],
+                    0,
+                    EXTENDED_LINK,
+                    (next_ebr_lba - extended_first) as u32,
+                    (next.end_lba() - next_ebr_lba) as u32,
+                );
+            }
+            write_at(ebr_lba, &ebr)?;
+        }
+        Ok(())
+    }
+
+    /// Read the MBR of a disk with `block_size`-byte blocks, following the
+    /// chain of extended boot records for logical partitions. Returns None
+    /// when block 0 has no boot signature. MBRs do not store names, so
+    /// partitions are named the way Linux numbers them: `part1` to `part4`
+    /// for primary partitions, `part5` on for logical ones.
+    pub fn read_from<R: Read + Seek>(
+        reader: &mut R,
+        block_size: u64,
+    ) -> Result<Option<Self>, String> {
+        let disk_size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|e| format!("Failed to read disk size: {e}"))?;
+        let blocks = disk_size / block_size;
+        let mut read_record = |lba: u64| -> Result<Option<Vec<u8>>, String> {
+            let mut record = vec![0u8; 512];
+            reader
+                .seek(SeekFrom::Start(lba * block_size))
+                .and_then(|_| reader.read_exact(&mut record))
+                .map_err(|e| format!("Failed to read partition table at block {lba}: {e}"))?;
+            Ok((record[510..512] == [0x55, 0xAA]).then_some(record))
+        };
+        if blocks == 0 {
+            return Ok(None);
+        }
+        let Some(mbr) = read_record(0)? else {
+            return Ok(None);
+        };
+
+        let mut disk = MbrDisk {
+            disk_signature: u32::from_le_bytes(mbr[440..444].try_into().unwrap()),
+            block_size,
+            blocks,
+            primary: Vec::new(),
+            logical: Vec::new(),
+        };
+        for (index, entry) in mbr[446..510].chunks_exact(16).enumerate() {
+            let (status, partition_type, start, sectors) = read_entry(entry);
+            if partition_type == 0 || sectors == 0 {
+                continue;
+            }
+            if partition_type == GPT_PROTECTIVE {
+                return Err("the MBR is the protective MBR of a GPT disk".to_string());
+            }
+            if !EXTENDED_TYPES.contains(&partition_type) {
+                disk.primary.push(MbrPartition {
+                    name: format!("part{}", index + 1),
+                    partition_type,
+                    bootable: status == BOOTABLE,
+                    first_lba: start,
+                    blocks: sectors,
+                });
+                continue;
+            }
+
+            // Each extended boot record places its logical partition relative
+            // to itself and the next record relative to the extended partition
+            let mut ebr_lba = start;
+            loop {
+                let record = read_record(ebr_lba)?.ok_or_else(|| {
+                    format!("extended boot record at block {ebr_lba} has no boot signature")
+                })?;
+                let (status, partition_type, offset, sectors) = read_entry(&record[446..462]);
+                if partition_type != 0 && sectors != 0 {
+                    disk.logical.push(MbrPartition {
+                        name: format!("part{}", PRIMARY_COUNT + disk.logical.len() + 1),
+                        partition_type,
+                        bootable: status == BOOTABLE,
+                        first_lba: ebr_lba + offset,
+                        blocks: sectors,
+                    });
+                }
+                let (_, link_type, next, _) = read_entry(&record[462..478]);
+                if link_type == 0 {
+                    break;
+                }
+                // Records only move forward, so a corrupt chain cannot loop
+                if start + next <= ebr_lba || start + next >= blocks {
+                    return Err(format!(
+                        "extended boot record at block {ebr_lba} links to block {}, outside the extended partition",
+                        start + next
+                    ));
+                }
+                ebr_lba = start + next;
+            }
+        }
+        Ok(Some(disk))
+    }
+
+    /// An empty boot record with its signature
+    fn boot_record(&self) -> Vec<u8> {
+        let mut record = vec![0u8; self.block_size as usize];
+        record[510] = 0x55;
+        record[511] = 0xAA;
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn partition(name: &str, first_lba: u64, blocks: u64) -> MbrPartition {
+        MbrPartition {
+            name: name.to_string(),
+            partition_type: LINUX,
+            bootable: false,
+            first_lba,
+            blocks,
+        }
+    }
+
+    fn le_u32(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    #[test]
+    fn test_parse_partition_type() {
+        assert_eq!(parse_partition_type("0x83"), Ok(0x83));
+        assert_eq!(parse_partition_type("0C"), Ok(0x0c));
+        assert_eq!(parse_partition_type("ef"), Ok(0xef));
+        assert!(parse_partition_type("0x").is_err());
+        assert!(parse_partition_type("0x183").is_err());
+        assert!(parse_partition_type("0fc63daf-8483-4772-8e79-3d69d8477de4").is_err());
+    }
+
+    #[test]
+    fn test_write_mbr_with_logical_partitions() {
+        let disk = MbrDisk {
+            disk_signature: 0x1234_5678,
+            block_size: 512,
+            blocks: 1000,
+            primary: vec![MbrPartition {
+                partition_type: 0x0c,
+                bootable: true,
+                ..partition("boot", 8, 100)
+            }],
+            logical: vec![partition("data", 200, 100), partition("log", 400, 50)],
+        };
+        let mut image = Cursor::new(vec![0u8; 1000 * 512]);
+        disk.write_to(&mut image).unwrap();
+        let image = image.into_inner();
+
+        assert_eq!(le_u32(&image[440..444]), 0x1234_5678);
+        assert_eq!(image[510..512], [0x55, 0xAA]);
+        let boot = &image[446..462];
+        assert_eq!((boot[0], boot[4]), (0x80, 0x0c));
+        assert_eq!((le_u32(&boot[8..12]), le_u32(&boot[12..16])), (8, 100));
+        let extended = &image[462..478];
+        assert_eq!(extended[4], EXTENDED_LBA);
+        assert_eq!(
+            (le_u32(&extended[8..12]), le_u32(&extended[12..16])),
+            (199, 251)
+        );
+        assert!(image[478..510].iter().all(|b| *b == 0));
+
+        let first_ebr = &image[199 * 512..200 * 512];
+        assert_eq!(first_ebr[510..512], [0x55, 0xAA]);
+        assert_eq!(first_ebr[446 + 4], LINUX);
+        assert_eq!(
+            (le_u32(&first_ebr[454..458]), le_u32(&first_ebr[458..462])),
+            (1, 100)
+        );
+        assert_eq!(first_ebr[462 + 4], EXTENDED_LINK);
+        assert_eq!(
+            (le_u32(&first_ebr[470..474]), le_u32(&first_ebr[474..478])),
+            (200, 51)
+        );
+
+        let last_ebr = &image[399 * 512..400 * 512];
+        assert_eq!(
+            (le_u32(&last_ebr[454..458]), le_u32(&last_ebr[458..462])),
+            (1, 50)
+        );
+        assert!(last_ebr[462..478].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_read_mbr() {
+        let disk = MbrDisk {
+            disk_signature: 0x1234_5678,
+            block_size: 512,
+            blocks: 1000,
+            primary: vec![MbrPartition {
+                partition_type: 0x0c,
+                bootable: true,
+                ..partition("part1", 8, 100)
+            }],
+            logical: vec![partition("part5", 200, 100), partition("part6", 400, 50)],
+        };
+        let mut image = Cursor::new(vec![0u8; 1000 * 512]);
+        disk.write_to(&mut image).unwrap();
+        assert_eq!(MbrDisk::read_from(&mut image, 512), Ok(Some(disk)));
+
+        // Point the first extended boot record back at itself
+        image.get_mut()[199 * 512 + 470..][..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(
+            MbrDisk::read_from(&mut image, 512)
+                .unwrap_err()
+                .contains("outside the extended partition")
+        );
+        assert_eq!(
+            MbrDisk::read_from(&mut Cursor::new(vec![0u8; 4096]), 512),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_check_mbr() {
+        let disk = |primary, logical| MbrDisk {
+            disk_signature: 0,
+            block_size: 512,
+            blocks: 1000,
+            primary,
+            logical,
+        };
+        let four = || {
+            (0..4)
+                .map(|i| partition(&format!("p{i}"), 1 + i * 10, 10))
+                .collect()
+        };
+
+        assert!(disk(four(), vec![]).check().is_ok());
+        assert!(disk(four(), vec![partition("l", 500, 10)]).check().is_err());
+        assert!(disk(vec![partition("p", 0, 10)], vec![]).check().is_err());
+        assert!(disk(vec![partition("p", 990, 20)], vec![]).check().is_err());
+        assert!(
+            disk(
+                vec![],
+                vec![partition("a", 100, 10), partition("b", 110, 10)]
+            )
+            .check()
+            .unwrap_err()
+            .contains("extended boot record")
+        );
+        assert_eq!(
+            disk(
+                vec![partition("p", 150, 10)],
+                vec![partition("a", 100, 100)]
+            )
+            .check()
+            .unwrap_err(),
+            "MBR partitions 'extended partition' and 'p' overlap"
+        );
+    }
+}
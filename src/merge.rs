@@ -0,0 +1,187 @@
+use serde_json::{Map, Value};
+
+/// Marker that removes an inherited entry: `{"$delete": true}`
+pub const DELETE_KEY: &str = "$delete";
+
+/// Merge a manifest over the base manifest it extends.
+///
+/// - Objects merge key by key. A `null` value or `{"$delete": true}` removes the inherited key.
+/// - Objects with a different `type` (build args, slot actions, ...) replace the base object.
+/// - `partitions` arrays merge by partition `name`; see [`merge_partitions`].
+/// - Every other value, arrays included, replaces the base value.
+pub fn merge(base: Value, overlay: Value) -> Value {
+    merge_value(base, overlay, None)
+}
+
+fn merge_value(base: Value, overlay: Value, key: Option<&str>) -> Value {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay))
+            if base.get("type") == overlay.get("type") || !overlay.contains_key("type") =>
+        {
+            Value::Object(merge_objects(base, overlay))
+        }
+        (Value::Array(base), Value::Array(overlay)) if key == Some("partitions") => {
+            Value::Array(merge_partitions(base, overlay))
+        }
+        (_, overlay) => strip_deletes(overlay),
+    }
+}
+
+fn merge_objects(mut base: Map<String, Value>, overlay: Map<String, Value>) -> Map<String, Value> {
+    for (key, value) in overlay {
+        if is_delete(&value) {
+            base.remove(&key);
+            continue;
+        }
+        let merged = match base.remove(&key) {
+            Some(base_value) => merge_value(base_value, value, Some(&key)),
+            None => strip_deletes(value),
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
+/// Merge partition lists. An overlay partition whose `name` matches an inherited
+/// partition is merged into it in place, or removes it when it carries `"$delete": true`.
+/// Any other overlay partition is appended after the inherited ones.
+fn merge_partitions(mut base: Vec<Value>, overlay: Vec<Value>) -> Vec<Value> {
+    for partition in overlay {
+        let name = partition.get("name").and_then(Value::as_str);
+        let position = name.and_then(|name| {
+            base.iter()
+                .position(|p| p.get("name").and_then(Value::as_str) == Some(name))
+        });
+
+        let delete = partition.get(DELETE_KEY) == Some(&Value::Bool(true));
+        match (position, delete) {
+            (Some(index), true) => {
+                base.remove(index);
+            }
+            (None, true) => {}
+            (Some(index), false) => {
+                let inherited = std::mem::take(&mut base[index]);
+                base[index] = merge_value(inherited, partition, None);
+            }
+            (None, false) => base.push(strip_deletes(partition)),
+        }
+    }
+    base
+}
+
+fn is_delete(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(map) => map.len() == 1 && map.get(DELETE_KEY) == Some(&Value::Bool(true)),
+        _ => false,
+    }
+}
+
+/// Drop delete markers from a value that has nothing to delete from
+fn strip_deletes(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !is_delete(v))
+                .map(|(k, v)| (k, strip_deletes(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .filter(|v| v.get(DELETE_KEY) != Some(&Value::Bool(true)))
+                .map(strip_deletes)
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_objects() {
+        let base = json!({
+            "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+            "storage_devices": {
+                "rootdisk": {
+                    "devpath": "/dev/mmcblk0",
+                    "images": { "boot": "boot.img", "rootfs": "rootfs.img", "data": "data.img" }
+                }
+            }
+        });
+        let overlay = json!({
+            "runtime": { "platform": "rpi4-8gb" },
+            "storage_devices": {
+                "rootdisk": {
+                    "images": { "rootfs": "rootfs-8gb.img", "data": null, "extra": { "$delete": true } }
+                }
+            },
+            "release": { "version": "1.0.0" }
+        });
+
+        assert_eq!(
+            merge(base, overlay),
+            json!({
+                "runtime": { "platform": "rpi4-8gb", "architecture": "aarch64" },
+                "storage_devices": {
+                    "rootdisk": {
+                        "devpath": "/dev/mmcblk0",
+                        "images": { "boot": "boot.img", "rootfs": "rootfs-8gb.img" }
+                    }
+                },
+                "release": { "version": "1.0.0" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_replaces_objects_of_another_type() {
+        let base = json!({ "activate": { "type": "uboot-env", "set": { "boot_slot": "{slot}" } } });
+        let overlay = json!({ "activate": { "type": "command", "command": ["switch", "{slot}"] } });
+        assert_eq!(merge(base, overlay.clone()), overlay);
+
+        let base = json!({ "build_args": { "type": "fat", "variant": "FAT16", "files": ["a"] } });
+        let overlay = json!({ "build_args": { "variant": "FAT32" } });
+        assert_eq!(
+            merge(base, overlay),
+            json!({ "build_args": { "type": "fat", "variant": "FAT32", "files": ["a"] } })
+        );
+    }
+
+    #[test]
+    fn test_merge_partitions_by_name() {
+        let base = json!({ "partitions": [
+            { "offset": 0, "size": 1, "size_unit": "mebibytes" },
+            { "name": "boot", "image": "boot", "size": 64, "size_unit": "mebibytes" },
+            { "name": "rootfs", "image": "rootfs", "size": 1, "size_unit": "gibibytes" },
+            { "name": "data", "size": 1, "size_unit": "gibibytes" }
+        ]});
+        let overlay = json!({ "partitions": [
+            { "name": "rootfs", "size": 2 },
+            { "name": "data", "$delete": true },
+            { "name": "missing", "$delete": true },
+            { "name": "logs", "size": 256, "size_unit": "mebibytes" }
+        ]});
+
+        assert_eq!(
+            merge(base, overlay),
+            json!({ "partitions": [
+                { "offset": 0, "size": 1, "size_unit": "mebibytes" },
+                { "name": "boot", "image": "boot", "size": 64, "size_unit": "mebibytes" },
+                { "name": "rootfs", "image": "rootfs", "size": 2, "size_unit": "gibibytes" },
+                { "name": "logs", "size": 256, "size_unit": "mebibytes" }
+            ]})
+        );
+    }
+
+    #[test]
+    fn test_merge_replaces_other_arrays() {
+        let base = json!({ "hardware_revisions": ["rev-a", "rev-b"] });
+        let overlay = json!({ "hardware_revisions": ["rev-c"] });
+        assert_eq!(merge(base, overlay.clone()), overlay);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use stone::{BundleOptions, Error, Level, Stone};
+use tempfile::TempDir;
+
+/// Copy the bundle fixture into `dir`
+fn write_inputs(dir: &Path) {
+    for file in ["stone.json", "os-release", "boot.img", "rootfs.img"] {
+        fs::copy(
+            Path::new("tests/fixtures/bundle").join(file),
+            dir.join(file),
+        )
+        .unwrap();
+    }
+}
+
+type Messages = Arc<Mutex<Vec<(Level, String)>>>;
+
+fn recording_stone(manifest_path: &Path) -> (Stone, Messages) {
+    let messages = Messages::default();
+    let recorded = messages.clone();
+    let stone = Stone::new(manifest_path).with_reporter(move |level, message| {
+        recorded.lock().unwrap().push((level, message.to_string()));
+    });
+    (stone, messages)
+}
+
+#[test]
+fn test_validate_and_bundle_report_through_callback() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    write_inputs(dir);
+
+    let (stone, messages) = recording_stone(&dir.join("stone.json"));
+    assert_eq!(stone.input_dirs(), vec![dir.to_path_buf()]);
+    stone.validate().unwrap();
+    assert!(
+        messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(level, _)| *level == Level::Success),
+        "{messages:?}"
+    );
+
+    let output = dir.join("os-bundle.aos");
+    stone
+        .bundle(&BundleOptions::new(dir.join("os-release"), &output))
+        .unwrap();
+    assert!(output.exists());
+}
+
+#[test]
+fn test_manifest_warnings_reach_callback() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    write_inputs(dir);
+    let manifest_path = dir.join("stone.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    manifest["runtime"]["provision"] = "provision.sh".into();
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (stone, messages) = recording_stone(&manifest_path);
+    let manifest = stone.manifest().unwrap();
+    assert_eq!(manifest.runtime.provision.as_deref(), Some("provision.sh"));
+    let messages = messages.lock().unwrap();
+    assert!(
+        messages
+            .iter()
+            .any(|(level, message)| *level == Level::Warning
+                && message.contains("runtime.provision is deprecated")),
+        "{messages:?}"
+    );
+}
+
+#[test]
+fn test_errors_are_returned() {
+    let temp_dir = TempDir::new().unwrap();
+    let stone = Stone::new(temp_dir.path().join("missing.json"))
+        .with_input_dirs([temp_dir.path()])
+        .with_var("VERSION", "1.0.0");
+    let err = stone.validate().unwrap_err();
+    assert!(matches!(err, Error::MissingInput(_)), "{err:?}");
+    assert!(err.to_string().contains("not found"), "{err}");
+}
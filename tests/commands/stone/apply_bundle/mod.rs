@@ -0,0 +1,253 @@
+use super::{build_bundle, write_bundle_inputs};
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+const DISK_SIZE: u64 = 6 * 1024 * 1024;
+const ROOTFS_A_OFFSET: usize = 2 * 1024 * 1024;
+const ROOTFS_B_OFFSET: usize = 4 * 1024 * 1024;
+
+fn create_disk(path: &Path, size: u64) {
+    fs::File::create(path).unwrap().set_len(size).unwrap();
+}
+
+fn apply_bundle(bundle: &Path, target: &Path, slot: &str) -> Command {
+    let mut cmd = Command::cargo_bin("stone").unwrap();
+    cmd.args([
+        "apply-bundle",
+        &bundle.to_string_lossy(),
+        "--target",
+        &target.to_string_lossy(),
+        "--slot",
+        slot,
+    ]);
+    cmd
+}
+
+#[test]
+fn test_apply_bundle_to_slot_b() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, DISK_SIZE);
+    let env_path = temp_dir.path().join("uboot.env");
+    fs::write(&env_path, "bootdelay=2\nboot_slot=a\n").unwrap();
+
+    apply_bundle(&bundle, &disk, "b")
+        .args(["--uboot-env", &env_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains(
+            "Writing artifact 'rootfs' to partition 'rootfs_b' on 'rootdisk' at offset 4194304.",
+        ))
+        .stdout(contains("Applied bundle"));
+
+    let rootfs = fs::read("tests/fixtures/bundle/rootfs.img").unwrap();
+    let disk_data = fs::read(&disk).unwrap();
+    assert_eq!(disk_data.len() as u64, DISK_SIZE);
+    assert_eq!(
+        &disk_data[ROOTFS_B_OFFSET..ROOTFS_B_OFFSET + rootfs.len()],
+        rootfs.as_slice()
+    );
+    // Slot a is left untouched
+    assert!(
+        disk_data[ROOTFS_A_OFFSET..ROOTFS_A_OFFSET + rootfs.len()]
+            .iter()
+            .all(|b| *b == 0)
+    );
+
+    assert_eq!(
+        fs::read_to_string(&env_path).unwrap(),
+        "bootdelay=2\nboot_slot=b\nupgrade_available=1\n"
+    );
+}
+
+#[test]
+fn test_apply_bundle_default_uboot_env() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, DISK_SIZE);
+
+    apply_bundle(&bundle, &disk, "a").assert().success();
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("disk.img.uboot-env")).unwrap(),
+        "boot_slot=a\nupgrade_available=1\n"
+    );
+}
+
+#[test]
+fn test_apply_bundle_mbr_switch_and_efibootmgr() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |manifest| {
+        manifest["update"]["activate"] = serde_json::json!([
+            {
+                "type": "mbr-switch",
+                "devpath": "/dev/mmcblk0",
+                "slot_layouts": {
+                    "a": ["boot", "rootfs_a"],
+                    "b": ["boot", "rootfs_b"]
+                }
+            },
+            {
+                "type": "efibootmgr",
+                "slot_entries": { "a": "boot-a", "b": "boot-b" }
+            },
+            {
+                "type": "command",
+                "command": ["reboot"]
+            }
+        ]);
+    });
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, DISK_SIZE);
+
+    apply_bundle(&bundle, &disk, "b")
+        .assert()
+        .success()
+        .stdout(contains("Switched MBR"))
+        .stdout(contains("Skipping command action"));
+
+    let disk_data = fs::read(&disk).unwrap();
+    let entry = |idx: usize| &disk_data[446 + idx * 16..446 + (idx + 1) * 16];
+    let lba = |e: &[u8]| u32::from_le_bytes(e[8..12].try_into().unwrap());
+    let sectors = |e: &[u8]| u32::from_le_bytes(e[12..16].try_into().unwrap());
+
+    assert_eq!((lba(entry(0)), sectors(entry(0))), (2048, 2048));
+    assert_eq!((lba(entry(1)), sectors(entry(1))), (8192, 4096));
+    assert_eq!(entry(1)[4], 0x83);
+    assert!(entry(2).iter().all(|b| *b == 0));
+    assert_eq!(&disk_data[510..512], &[0x55, 0xAA]);
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("disk.img.efivars")).unwrap(),
+        "BootNext=boot-b\n"
+    );
+}
+
+#[test]
+fn test_apply_bundle_unknown_slot() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, DISK_SIZE);
+
+    apply_bundle(&bundle, &disk, "c")
+        .assert()
+        .failure()
+        .stdout(contains(
+            "Slot 'c' is not targeted by any artifact in the bundle (available: a, b).",
+        ));
+}
+
+#[test]
+fn test_apply_bundle_target_too_small() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, 5 * 1024 * 1024);
+
+    apply_bundle(&bundle, &disk, "b")
+        .assert()
+        .failure()
+        .stdout(contains("is too small for partition 'rootfs_b'"));
+
+    // Nothing was written
+    assert!(fs::read(&disk).unwrap().iter().all(|b| *b == 0));
+}
+
+#[test]
+fn test_apply_bundle_missing_target() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle = build_bundle(temp_dir.path());
+
+    apply_bundle(&bundle, &temp_dir.path().join("missing.img"), "a")
+        .assert()
+        .failure()
+        .stdout(contains("Target image"))
+        .stdout(contains("not found."));
+}
+
+/// Add a SPI NOR device holding an A/B bootloader next to the fixture's eMMC
+fn add_nor_device(manifest: &mut serde_json::Value) {
+    manifest["storage_devices"]["nor"] = serde_json::json!({
+        "out": "nor.img",
+        "devpath": "/dev/mtdblock0",
+        "block_size": 512,
+        "images": { "bootloader": "boot.img" },
+        "partitions": [
+            { "name": "bootloader_a", "image": "bootloader", "size": 64, "size_unit": "kibibytes" },
+            { "name": "bootloader_b", "image": "bootloader", "size": 64, "size_unit": "kibibytes" }
+        ]
+    });
+    manifest["update"]["os_artifacts"]["bootloader"] = serde_json::json!({
+        "image_key": "bootloader",
+        "slot_partitions": ["bootloader_a", "bootloader_b"]
+    });
+}
+
+#[test]
+fn test_apply_bundle_multiple_devices() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), add_nor_device);
+    let bundle = build_bundle(temp_dir.path());
+    let disk = temp_dir.path().join("disk.img");
+    create_disk(&disk, DISK_SIZE);
+    let nor = temp_dir.path().join("nor.img");
+    create_disk(&nor, 128 * 1024);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Layout: nor (/dev/mtdblock0)"))
+        .stdout(contains("Layout: rootdisk (/dev/mmcblk0)"))
+        .stdout(contains("b: nor / bootloader_b"));
+
+    apply_bundle(&bundle, &disk, "b")
+        .assert()
+        .failure()
+        .stdout(contains(
+            "No target image for device 'nor'. The bundle writes to nor, rootdisk; pass --target DEVICE=PATH for each.",
+        ));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "apply-bundle",
+            &bundle.to_string_lossy(),
+            "--target",
+            &format!("rootdisk={}", disk.display()),
+            "--target",
+            &format!("nor={}", nor.display()),
+            "--slot",
+            "b",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            "Writing artifact 'bootloader' to partition 'bootloader_b' on 'nor' at offset 65536.",
+        ));
+
+    let boot = fs::read("tests/fixtures/bundle/boot.img").unwrap();
+    let nor_data = fs::read(&nor).unwrap();
+    assert_eq!(&nor_data[65536..65536 + boot.len()], boot.as_slice());
+    assert!(nor_data[..65536].iter().all(|b| *b == 0));
+
+    let rootfs = fs::read("tests/fixtures/bundle/rootfs.img").unwrap();
+    let disk_data = fs::read(&disk).unwrap();
+    assert_eq!(
+        &disk_data[ROOTFS_B_OFFSET..ROOTFS_B_OFFSET + rootfs.len()],
+        rootfs.as_slice()
+    );
+}
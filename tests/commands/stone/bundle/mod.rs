@@ -0,0 +1,387 @@
+use super::{bundle_command, write_bundle_inputs};
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// Copy the bundle fixture into `dir` with every input file modified at `mtime`,
+/// adding a FAT image to the update artifacts
+fn write_inputs(dir: &Path, mtime: SystemTime) {
+    write_bundle_inputs(dir, |manifest| {
+        manifest["storage_devices"]["rootdisk"]["images"]["efi"] = serde_json::json!({
+            "out": "efi.img",
+            "size": 16,
+            "size_unit": "megabytes",
+            "build_args": {
+                "type": "fat",
+                "variant": "FAT32",
+                "files": ["efi.txt"]
+            }
+        });
+        manifest["update"]["os_artifacts"]["efi"] = serde_json::json!({
+            "image_key": "efi",
+            "slot_partitions": []
+        });
+    });
+    fs::write(dir.join("efi.txt"), "efi payload\n").unwrap();
+    for file in ["os-release", "boot.img", "rootfs.img", "efi.txt"] {
+        fs::File::options()
+            .write(true)
+            .open(dir.join(file))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+}
+
+fn build_bundle(dir: &Path, source_date_epoch: Option<&str>) -> PathBuf {
+    let mut cmd = bundle_command(dir);
+    match source_date_epoch {
+        Some(epoch) => cmd.env("SOURCE_DATE_EPOCH", epoch),
+        None => cmd.env_remove("SOURCE_DATE_EPOCH"),
+    };
+    cmd.assert().success();
+    dir.join("os-bundle.aos")
+}
+
+#[test]
+fn test_bundle_is_reproducible() {
+    let temp_dir = TempDir::new().unwrap();
+    let first_dir = temp_dir.path().join("first");
+    let second_dir = temp_dir.path().join("second");
+    write_inputs(
+        &first_dir,
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+    );
+    write_inputs(&second_dir, SystemTime::now());
+
+    let first = fs::read(build_bundle(&first_dir, None)).unwrap();
+    let second = fs::read(build_bundle(&second_dir, None)).unwrap();
+
+    assert!(
+        first == second,
+        "bundles built from identical inputs differ"
+    );
+}
+
+#[test]
+fn test_bundle_normalized_headers() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    let bundle_path = build_bundle(temp_dir.path(), Some("1700000000"));
+
+    let decoder = zstd::Decoder::new(fs::File::open(&bundle_path).unwrap()).unwrap();
+    let mut archive = tar::Archive::new(decoder);
+    let mut paths = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let header = entry.header();
+        assert_eq!(header.mtime().unwrap(), 1_700_000_000);
+        assert_eq!(header.uid().unwrap(), 0);
+        assert_eq!(header.gid().unwrap(), 0);
+        assert_eq!(header.mode().unwrap(), 0o644);
+        assert_eq!(header.username().unwrap(), Some(""));
+        paths.push(entry.path().unwrap().to_string_lossy().to_string());
+    }
+
+    // bundle.json first, then artifacts sorted by name
+    assert_eq!(
+        paths,
+        ["bundle.json", "images/efi.img", "images/rootfs.img"]
+    );
+
+    // Files inside the FAT image carry SOURCE_DATE_EPOCH as well
+    let efi = fatfs::FileSystem::new(
+        fs::File::open(temp_dir.path().join("_build/images/efi.img")).unwrap(),
+        fatfs::FsOptions::new(),
+    )
+    .unwrap();
+    let entry = efi
+        .root_dir()
+        .iter()
+        .map(|e| e.unwrap())
+        .find(|e| e.file_name() == "efi.txt")
+        .unwrap();
+    let modified = entry.modified();
+    assert_eq!(
+        (modified.date.year, modified.date.month, modified.date.day),
+        (2023, 11, 14)
+    );
+}
+
+#[test]
+fn test_bundle_invalid_source_date_epoch() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .env("SOURCE_DATE_EPOCH", "yesterday")
+        .assert()
+        .failure()
+        .stdout(contains("Invalid SOURCE_DATE_EPOCH 'yesterday'"));
+}
+
+#[test]
+fn test_bundle_output_is_a_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+    fs::create_dir(temp_dir.path().join("os-bundle.aos")).unwrap();
+
+    bundle_command(temp_dir.path())
+        .assert()
+        .code(8)
+        .stdout(contains("Failed to create output file"));
+}
+
+fn inspect_bundle(bundle_path: &Path) {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Verified bundle"));
+}
+
+#[test]
+fn test_bundle_without_compression() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args(["--compression", "none"])
+        .assert()
+        .success();
+
+    // A plain tar starts with the name of its first entry
+    let bundle_path = temp_dir.path().join("os-bundle.aos");
+    let data = fs::read(&bundle_path).unwrap();
+    assert!(data.starts_with(b"bundle.json\0"));
+
+    inspect_bundle(&bundle_path);
+}
+
+#[test]
+fn test_bundle_compression_options() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args(["--compression-level", "19", "--threads", "2", "--long"])
+        .assert()
+        .success();
+
+    let bundle_path = temp_dir.path().join("os-bundle.aos");
+    let data = fs::read(&bundle_path).unwrap();
+    assert!(data.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]));
+
+    inspect_bundle(&bundle_path);
+}
+
+#[test]
+fn test_bundle_invalid_compression_level() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args(["--compression-level", "30"])
+        .assert()
+        .failure()
+        .stderr(contains("--compression-level"));
+}
+
+#[test]
+fn test_bundle_chunk_index() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args(["--chunk-index", "--chunk-size", "8192"])
+        .assert()
+        .success();
+
+    let bundle_path = temp_dir.path().join("os-bundle.aos");
+    let decoder = zstd::Decoder::new(fs::File::open(&bundle_path).unwrap()).unwrap();
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = std::collections::BTreeMap::new();
+    let mut paths = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().to_string();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+        paths.push(path.clone());
+        entries.insert(path, data);
+    }
+
+    // Chunk indexes precede the artifacts they describe
+    assert_eq!(
+        paths,
+        [
+            "bundle.json",
+            "chunks/efi.json",
+            "chunks/rootfs.json",
+            "images/efi.img",
+            "images/rootfs.img"
+        ]
+    );
+
+    let bundle: serde_json::Value = serde_json::from_slice(&entries["bundle.json"]).unwrap();
+    let rootfs = &bundle["update"]["artifacts"][1];
+    assert_eq!(rootfs["name"], "rootfs");
+    assert_eq!(rootfs["chunks"]["algorithm"], "fastcdc-2020");
+    assert_eq!(rootfs["chunks"]["avg_size"], 8192);
+    assert_eq!(rootfs["chunks"]["index"], "chunks/rootfs.json");
+
+    let index: serde_json::Value = serde_json::from_slice(&entries["chunks/rootfs.json"]).unwrap();
+    let chunks = index["chunks"].as_array().unwrap();
+    assert_eq!(rootfs["chunks"]["count"], chunks.len());
+    assert!(chunks.len() > 1);
+    let total: u64 = chunks.iter().map(|c| c["length"].as_u64().unwrap()).sum();
+    assert_eq!(total, rootfs["size"].as_u64().unwrap());
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Chunks: "))
+        .stdout(contains("Verified bundle"));
+}
+
+#[test]
+fn test_bundle_chunk_size_requires_chunk_index() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args(["--chunk-size", "8192"])
+        .assert()
+        .failure()
+        .stderr(contains("--chunk-index"));
+}
+
+#[test]
+fn test_bundle_release_metadata() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    let manifest_path = temp_dir.path().join("stone.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    manifest["release"] = serde_json::json!({
+        "version": "1.0.0",
+        "notes": "From the manifest",
+        "min_version": "0.9.0",
+        "hardware_revisions": ["rev-a"]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+    fs::write(
+        temp_dir.path().join("NOTES.md"),
+        "Fixes boot on rev-c.\nFaster updates.\n",
+    )
+    .unwrap();
+
+    bundle_command(temp_dir.path())
+        .args([
+            "--release-version",
+            "1.1.0",
+            "--release-notes-file",
+            &temp_dir.path().join("NOTES.md").to_string_lossy(),
+            "--min-os-build-id",
+            "build-0000",
+            "--hardware-revision",
+            "rev-b",
+            "--hardware-revision",
+            "rev-c",
+        ])
+        .assert()
+        .success();
+
+    let bundle: serde_json::Value =
+        serde_json::from_slice(&fs::read(temp_dir.path().join("_build/bundle.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        bundle["release"],
+        serde_json::json!({
+            "version": "1.1.0",
+            "notes": "Fixes boot on rev-c.\nFaster updates.",
+            "min_os_build_id": "build-0000",
+            "min_version": "0.9.0",
+            "hardware_revisions": ["rev-b", "rev-c"]
+        })
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "inspect-bundle",
+            &temp_dir.path().join("os-bundle.aos").to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Release        : 1.1.0"))
+        .stdout(contains("Hardware       : rev-b, rev-c"))
+        .stdout(contains("  Faster updates."));
+}
+
+#[test]
+fn test_bundle_without_release_metadata() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path()).assert().success();
+
+    let bundle: serde_json::Value =
+        serde_json::from_slice(&fs::read(temp_dir.path().join("_build/bundle.json")).unwrap())
+            .unwrap();
+    assert!(bundle.get("release").is_none());
+}
+
+#[test]
+fn test_bundle_duplicate_hardware_revision() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    bundle_command(temp_dir.path())
+        .args([
+            "--hardware-revision",
+            "rev-a",
+            "--hardware-revision",
+            "rev-a",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains(
+            "Hardware revision 'rev-a' is listed more than once.",
+        ));
+}
+
+#[test]
+fn test_bundle_layout_uses_alignment() {
+    let temp_dir = TempDir::new().unwrap();
+    write_inputs(temp_dir.path(), SystemTime::now());
+
+    let manifest_path = temp_dir.path().join("stone.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    manifest["storage_devices"]["rootdisk"]["alignment"] = serde_json::json!(4);
+    manifest["storage_devices"]["rootdisk"]["alignment_unit"] = serde_json::json!("mebibytes");
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    bundle_command(temp_dir.path()).assert().success();
+
+    let bundle: serde_json::Value =
+        serde_json::from_slice(&fs::read(temp_dir.path().join("_build/bundle.json")).unwrap())
+            .unwrap();
+    let offsets: Vec<u64> = bundle["layout"]["rootdisk"]["partitions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|partition| partition["offset"].as_u64().unwrap())
+        .collect();
+    // boot keeps its fixed offset; the slots start on 4 MiB boundaries
+    assert_eq!(offsets, [1 << 20, 4 << 20, 8 << 20]);
+}
@@ -0,0 +1,149 @@
+use super::{build_bundle, write_bundle_inputs};
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Copy the bundle fixture into `dir`, replacing the rootfs image and build ID
+fn write_inputs(dir: &Path, rootfs: &[u8], build_id: &str) {
+    write_bundle_inputs(dir, |_| {});
+    fs::write(dir.join("rootfs.img"), rootfs).unwrap();
+    fs::write(
+        dir.join("os-release"),
+        format!("VERSION_ID=\"1.0.0\"\nAVOCADO_OS_BUILD_ID=\"{build_id}\"\n"),
+    )
+    .unwrap();
+}
+
+/// Read every entry of a .aos archive into memory
+fn read_entries(bundle_path: &Path) -> Vec<(String, Vec<u8>)> {
+    let decoder = zstd::Decoder::new(fs::File::open(bundle_path).unwrap()).unwrap();
+    let mut archive = tar::Archive::new(decoder);
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            (path, data)
+        })
+        .collect()
+}
+
+fn pseudo_random_image(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_bundle_delta() {
+    let temp_dir = TempDir::new().unwrap();
+    let old_rootfs = pseudo_random_image(512 * 1024, 1);
+    let mut new_rootfs = old_rootfs.clone();
+    new_rootfs[1000..1100].copy_from_slice(&[0xAA; 100]);
+    new_rootfs.extend_from_slice(b"appended data");
+
+    let old_dir = temp_dir.path().join("old");
+    let new_dir = temp_dir.path().join("new");
+    write_inputs(&old_dir, &old_rootfs, "build-0001");
+    write_inputs(&new_dir, &new_rootfs, "build-0002");
+    let old_bundle = build_bundle(&old_dir);
+    let new_bundle = build_bundle(&new_dir);
+
+    let delta_path = temp_dir.path().join("delta.aos");
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "bundle-delta",
+            "--from",
+            &old_bundle.to_string_lossy(),
+            "--to",
+            &new_bundle.to_string_lossy(),
+            "--output",
+            &delta_path.to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Delta OS bundle created"));
+
+    // The delta is a regular bundle as far as inspect-bundle is concerned
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &delta_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("images/rootfs.img.zstpatch"));
+
+    let entries = read_entries(&delta_path);
+    let bundle_json = &entries.iter().find(|(p, _)| p == "bundle.json").unwrap().1;
+    let bundle: serde_json::Value = serde_json::from_slice(bundle_json).unwrap();
+    assert_eq!(bundle["os_build_id"], "build-0002");
+    assert_eq!(bundle["delta"]["base_os_build_id"], "build-0001");
+
+    let artifact = &bundle["update"]["artifacts"][0];
+    assert_eq!(artifact["name"], "rootfs");
+    assert_eq!(artifact["file"], "images/rootfs.img.zstpatch");
+    assert_eq!(artifact["delta"]["type"], "zstd-patch");
+    assert_eq!(artifact["delta"]["base_size"], old_rootfs.len());
+    assert_eq!(artifact["delta"]["target_size"], new_rootfs.len());
+    assert_eq!(artifact["slot_targets"]["b"]["device"], "rootdisk");
+    assert_eq!(artifact["slot_targets"]["b"]["partition"], "rootfs_b");
+
+    // The patch must be much smaller than the image and reconstruct it exactly
+    let patch = &entries
+        .iter()
+        .find(|(p, _)| p == "images/rootfs.img.zstpatch")
+        .unwrap()
+        .1;
+    assert!(patch.len() < new_rootfs.len() / 10);
+
+    let mut decoder = zstd::Decoder::with_ref_prefix(&patch[..], &old_rootfs).unwrap();
+    decoder.window_log_max(31).unwrap();
+    let mut reconstructed = Vec::new();
+    decoder.read_to_end(&mut reconstructed).unwrap();
+    assert_eq!(reconstructed, new_rootfs);
+
+    use sha2::{Digest, Sha256};
+    assert_eq!(
+        artifact["delta"]["base_sha256"],
+        format!("{:x}", Sha256::digest(&old_rootfs))
+    );
+    assert_eq!(
+        artifact["delta"]["target_sha256"],
+        format!("{:x}", Sha256::digest(&new_rootfs))
+    );
+}
+
+#[test]
+fn test_bundle_delta_missing_base() {
+    let temp_dir = TempDir::new().unwrap();
+    let new_dir = temp_dir.path().join("new");
+    write_inputs(&new_dir, b"rootfs", "build-0002");
+    let new_bundle = build_bundle(&new_dir);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "bundle-delta",
+            "--from",
+            &temp_dir.path().join("missing.aos").to_string_lossy(),
+            "--to",
+            &new_bundle.to_string_lossy(),
+            "--output",
+            &temp_dir.path().join("delta.aos").to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("not found"));
+}
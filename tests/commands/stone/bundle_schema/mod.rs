@@ -0,0 +1,71 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use tempfile::TempDir;
+
+#[test]
+fn test_bundle_schema_stdout() {
+    let output = Command::cargo_bin("stone")
+        .unwrap()
+        .arg("bundle-schema")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let schema: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(schema["title"], "Avocado OS bundle (bundle.json)");
+    for field in [
+        "format_version",
+        "update",
+        "layout",
+        "verify",
+        "verify_initramfs",
+    ] {
+        assert!(schema["properties"][field].is_object(), "missing {field}");
+    }
+}
+
+#[test]
+fn test_bundle_schema_describes_built_bundle() {
+    let temp_dir = TempDir::new().unwrap();
+    let schema_path = temp_dir.path().join("bundle.schema.json");
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["bundle-schema", "-o", &schema_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Wrote bundle.json schema"));
+
+    // Every top-level field of a real bundle.json is described by the schema
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "bundle",
+            "--manifest-path",
+            "tests/fixtures/bundle/stone.json",
+            "--os-release",
+            "tests/fixtures/bundle/os-release",
+            "--input-dir",
+            "tests/fixtures/bundle",
+            "--output",
+            &temp_dir.path().join("os-bundle.aos").to_string_lossy(),
+            "--build-dir",
+            &temp_dir.path().join("_build").to_string_lossy(),
+        ])
+        .assert()
+        .success();
+
+    let schema: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&schema_path).unwrap()).unwrap();
+    let bundle_json: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp_dir.path().join("_build/bundle.json")).unwrap())
+            .unwrap();
+    for field in bundle_json.as_object().unwrap().keys() {
+        assert!(
+            schema["properties"][field].is_object(),
+            "bundle.json field '{field}' is not in the schema"
+        );
+    }
+}
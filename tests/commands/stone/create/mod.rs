@@ -594,3 +594,75 @@ fn test_create_with_multiple_input_dirs_not_found() {
         .failure()
         .stdout(str::contains("not found in any input directory"));
 }
+
+#[test]
+fn test_create_with_vars() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir_all(&input_dir).unwrap();
+
+    let manifest = r#"{
+        "variables": { "CHANNEL": "stable" },
+        "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk.img",
+                "devpath": "/dev/disk/by-label/$${LABEL}",
+                "images": { "rootfs": "rootfs-${CHANNEL}.img" },
+                "partitions": []
+            }
+        }
+    }"#;
+    std::fs::write(input_dir.join("manifest.json"), manifest).unwrap();
+    std::fs::write(input_dir.join("os-release"), "ID=avocado\n").unwrap();
+    std::fs::write(input_dir.join("rootfs-beta.img"), "beta rootfs").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "create",
+            "--manifest-path",
+            &input_dir.join("manifest.json").to_string_lossy(),
+            "--os-release",
+            &input_dir.join("os-release").to_string_lossy(),
+            "--input-dir",
+            &input_dir.to_string_lossy(),
+            "--output-dir",
+            &output_dir.to_string_lossy(),
+            "--var",
+            "CHANNEL=beta",
+        ])
+        .assert()
+        .success();
+
+    assert!(output_dir.join("rootfs-beta.img").exists());
+
+    // The copied manifest has the values this build used, whatever the
+    // environment says when it is read back
+    let copied: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(output_dir.join("manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        copied["storage_devices"]["rootdisk"]["images"]["rootfs"],
+        "rootfs-beta.img"
+    );
+    assert_eq!(
+        copied["storage_devices"]["rootdisk"]["devpath"],
+        "/dev/disk/by-label/$${LABEL}"
+    );
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &output_dir.join("manifest.json").to_string_lossy(),
+        ])
+        .env("CHANNEL", "gamma")
+        .assert()
+        .success()
+        .stdout(str::contains("rootfs-beta.img"))
+        .stdout(str::contains(
+            "Device Path    : /dev/disk/by-label/${LABEL}",
+        ));
+}
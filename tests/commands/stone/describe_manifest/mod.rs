@@ -12,3 +12,306 @@ fn test_describe_manifest() {
         .assert()
         .success();
 }
+
+fn write_variant_manifests(dir: &std::path::Path) {
+    let base = r#"{
+        "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk.img",
+                "devpath": "/dev/mmcblk0",
+                "images": { "boot": "boot.img", "rootfs": "rootfs.img", "factory": "factory.img" },
+                "partitions": [
+                    { "name": "boot", "image": "boot", "size": 64, "size_unit": "mebibytes" },
+                    { "name": "rootfs", "image": "rootfs", "size": 1, "size_unit": "gibibytes" },
+                    { "name": "factory", "image": "factory", "size": 16, "size_unit": "mebibytes" }
+                ]
+            }
+        }
+    }"#;
+    std::fs::create_dir_all(dir.join("common")).unwrap();
+    std::fs::write(dir.join("common/base.json"), base).unwrap();
+
+    let variant = r#"
+extends: common/base.json
+runtime:
+  platform: rpi4-8gb
+storage_devices:
+  rootdisk:
+    images:
+      rootfs: rootfs-8gb.img
+      factory: null
+    partitions:
+      - name: rootfs
+        size: 2
+      - name: factory
+        $delete: true
+      - name: data
+        size: 512
+        size_unit: mebibytes
+"#;
+    std::fs::write(dir.join("variant.yaml"), variant).unwrap();
+}
+
+#[test]
+fn test_describe_manifest_resolved() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    write_variant_manifests(temp_dir.path());
+
+    let output = Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &temp_dir.path().join("variant.yaml").to_string_lossy(),
+            "--resolved",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    // Printed in the format of the manifest itself
+    let resolved: serde_json::Value = serde_yaml::from_slice(&output).unwrap();
+
+    assert!(resolved.get("extends").is_none());
+    assert_eq!(resolved["runtime"]["platform"], "rpi4-8gb");
+    assert_eq!(resolved["runtime"]["architecture"], "aarch64");
+    let device = &resolved["storage_devices"]["rootdisk"];
+    assert_eq!(
+        device["images"],
+        serde_json::json!({ "boot": "boot.img", "rootfs": "rootfs-8gb.img" })
+    );
+    let partitions: Vec<(&str, u64)> = device["partitions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| (p["name"].as_str().unwrap(), p["size"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(partitions, [("boot", 64), ("rootfs", 2), ("data", 512)]);
+    assert_eq!(device["partitions"][1]["size_unit"], "gibibytes");
+}
+
+#[test]
+fn test_describe_manifest_with_extends() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    write_variant_manifests(temp_dir.path());
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &temp_dir.path().join("variant.yaml").to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Platform: rpi4-8gb (aarch64)"))
+        .stdout(predicates::str::contains("Images (2 total)"))
+        .stdout(predicates::str::contains("Partition Layout (3 partitions)"));
+}
+
+#[test]
+fn test_describe_manifest_extends_cycle() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a.json"), r#"{ "extends": "b.json" }"#).unwrap();
+    std::fs::write(dir.join("b.json"), r#"{ "extends": "a.json" }"#).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("a.json").to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("Manifest inheritance cycle"))
+        .stdout(predicates::str::contains("b.json -> "));
+}
+
+#[test]
+fn test_describe_manifest_missing_base() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("variant.json"), r#"{ "extends": "base.json" }"#).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("variant.json").to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("Failed to find base manifest"));
+}
+
+#[test]
+fn test_describe_manifest_variables() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = r#"{
+        "variables": { "CHANNEL": "stable", "BOARD": "rpi4", "DISK": "mmcblk0" },
+        "runtime": { "platform": "${BOARD}", "architecture": "aarch64" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk-${CHANNEL}.img",
+                "devpath": "/dev/${DISK}",
+                "images": { "rootfs": "rootfs-${CHANNEL}.img" },
+                "partitions": []
+            }
+        }
+    }"#;
+    std::fs::write(&manifest_path, manifest).unwrap();
+
+    let resolved = |args: &[&str], envs: &[(&str, &str)]| -> serde_json::Value {
+        let output = Command::cargo_bin("stone")
+            .unwrap()
+            .args([
+                "describe-manifest",
+                "--manifest-path",
+                &manifest_path.to_string_lossy(),
+                "--resolved",
+            ])
+            .args(args)
+            .envs(envs.iter().copied())
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        serde_json::from_slice(&output).unwrap()
+    };
+
+    // Defaults from the variables block
+    let manifest = resolved(&[], &[]);
+    assert_eq!(manifest["runtime"]["platform"], "rpi4");
+    assert_eq!(
+        manifest["storage_devices"]["rootdisk"]["out"],
+        "rootdisk-stable.img"
+    );
+    assert_eq!(
+        manifest["storage_devices"]["rootdisk"]["images"]["rootfs"],
+        "rootfs-stable.img"
+    );
+
+    // The environment overrides the variables block, --var overrides both
+    let manifest = resolved(
+        &["--var", "CHANNEL=beta"],
+        &[("CHANNEL", "nightly"), ("DISK", "sda")],
+    );
+    assert_eq!(
+        manifest["storage_devices"]["rootdisk"]["out"],
+        "rootdisk-beta.img"
+    );
+    assert_eq!(
+        manifest["storage_devices"]["rootdisk"]["devpath"],
+        "/dev/sda"
+    );
+}
+
+#[test]
+fn test_describe_manifest_undefined_variable() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = r#"{
+        "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk${STONE_TEST_UNDEFINED_SUFFIX}.img",
+                "devpath": "/dev/mmcblk0",
+                "images": {},
+                "partitions": []
+            }
+        }
+    }"#;
+    std::fs::write(&manifest_path, manifest).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ])
+        .assert()
+        .code(3)
+        .stdout(predicates::str::contains(
+            "referenced in the manifest but not set: STONE_TEST_UNDEFINED_SUFFIX",
+        ));
+
+    // An explicit empty default is allowed
+    std::fs::write(
+        &manifest_path,
+        manifest.replace(
+            "${STONE_TEST_UNDEFINED_SUFFIX}",
+            "${STONE_TEST_UNDEFINED_SUFFIX:-}",
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Output File    : rootdisk.img"));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+            "--var",
+            "not a var",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("is not NAME=VALUE"));
+}
+
+#[test]
+fn test_describe_manifest_capacity() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = r#"{
+        "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk.img",
+                "devpath": "/dev/mmcblk0",
+                "capacity": 8,
+                "capacity_unit": "gibibytes",
+                "alignment": 4,
+                "alignment_unit": "mebibytes",
+                "images": { "boot": "boot.img", "rootfs": "rootfs.img" },
+                "partitions": [
+                    { "name": "boot", "image": "boot", "size": 64, "size_unit": "mebibytes" },
+                    { "name": "rootfs", "image": "rootfs", "size": 1, "size_unit": "gibibytes", "expand": "true" }
+                ]
+            }
+        }
+    }"#;
+    std::fs::write(&manifest_path, manifest).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Capacity       : 8 GiB"))
+        .stdout(predicates::str::contains(
+            "2  rootfs       64 MiB       8128 MiB       expandable",
+        ));
+}
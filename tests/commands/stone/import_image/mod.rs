@@ -0,0 +1,298 @@
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn provision(input_path: &Path) {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_import_image_missing_image() {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["import-image", "does-not-exist.img"])
+        .assert()
+        .code(5)
+        .stdout(predicates::str::contains(
+            "Disk image 'does-not-exist.img' not found.",
+        ));
+}
+
+#[test]
+fn test_import_image_without_partition_table() {
+    let temp_dir = TempDir::new().unwrap();
+    let image_path = temp_dir.path().join("blank.img");
+    fs::write(&image_path, vec![0u8; 64 * 1024]).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["import-image", &image_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "no GPT or MBR partition table found",
+        ));
+}
+
+#[test]
+fn test_import_image_gpt_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let vendor_path = temp_dir.path().join("vendor");
+    fs::create_dir(&vendor_path).unwrap();
+
+    let manifest_content = r#"{
+        "runtime": { "platform": "generic-platform", "architecture": "test-arch" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "uuid": "4bc367b3-5d70-4289-b24d-9b09cb79685c",
+                "images": { "boot": "boot.img", "rootfs": "rootfs.img" },
+                "partitions": [
+                    {
+                        "name": "boot",
+                        "image": "boot",
+                        "partition_type": "c12a7328-f81f-11d2-ba4b-00a0c93ec93b",
+                        "offset": 2048,
+                        "size": 1,
+                        "size_unit": "mebibytes"
+                    },
+                    { "name": "rootfs", "image": "rootfs", "size": 2, "size_unit": "mebibytes" }
+                ]
+            }
+        }
+    }"#;
+    fs::write(vendor_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(vendor_path.join("boot.img"), "BOOT").unwrap();
+    fs::write(vendor_path.join("rootfs.img"), "ROOTFS").unwrap();
+    provision(&vendor_path);
+    let vendor_disk = vendor_path.join("_build/disk.img");
+
+    let import_path = temp_dir.path().join("import");
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "import-image",
+            &vendor_disk.to_string_lossy(),
+            "--extract",
+            &import_path.to_string_lossy(),
+            "--output",
+            &import_path.join("manifest.json").to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Imported 2 partition(s)"));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(import_path.join("manifest.json")).unwrap())
+            .unwrap();
+    let device = &manifest["storage_devices"]["rootdisk"];
+    assert_eq!(device["build_args"]["type"], "gpt");
+    assert_eq!(device["uuid"], "4bc367b3-5d70-4289-b24d-9b09cb79685c");
+    assert_eq!(device["images"]["boot"], "boot.img");
+    assert_eq!(device["images"]["rootfs"], "rootfs.img");
+    let boot = &device["partitions"][0];
+    assert_eq!(boot["name"], "boot");
+    assert_eq!(
+        boot["partition_type"],
+        "c12a7328-f81f-11d2-ba4b-00a0c93ec93b"
+    );
+    assert_eq!(
+        (&boot["offset"], &boot["offset_unit"]),
+        (&1.into(), &"mebibytes".into())
+    );
+    assert_eq!(
+        (&boot["size"], &boot["size_unit"]),
+        (&1.into(), &"mebibytes".into())
+    );
+    let rootfs = &device["partitions"][1];
+    assert_eq!(rootfs["name"], "rootfs");
+    assert_eq!((&rootfs["offset"], &rootfs["size"]), (&2.into(), &2.into()));
+
+    let boot_image = fs::read(import_path.join("boot.img")).unwrap();
+    assert_eq!(boot_image.len(), 1024 * 1024);
+    assert_eq!(&boot_image[..4], b"BOOT");
+
+    // Provisioning the imported manifest gives back the same disk
+    provision(&import_path);
+    assert!(
+        fs::read(import_path.join("_build/disk.img")).unwrap() == fs::read(&vendor_disk).unwrap(),
+        "Re-provisioned disk differs from the imported one"
+    );
+}
+
+#[test]
+fn test_import_image_mbr() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": { "platform": "generic-platform", "architecture": "test-arch" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "mbr" },
+                "devpath": "/dev/generic",
+                "images": { "boot": "boot.img", "data": "data.img" },
+                "partitions": [
+                    {
+                        "name": "boot",
+                        "image": "boot",
+                        "partition_type": "0x0c",
+                        "bootable": true,
+                        "offset": 2048,
+                        "size": 1,
+                        "size_unit": "mebibytes"
+                    },
+                    { "name": "data", "image": "data", "logical": true, "offset": 6144, "size": 1, "size_unit": "mebibytes" }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(input_path.join("boot.img"), "BOOT").unwrap();
+    fs::write(input_path.join("data.img"), "DATA").unwrap();
+    provision(input_path);
+
+    let output = Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "import-image",
+            &input_path.join("_build/disk.img").to_string_lossy(),
+            "--device-name",
+            "sdcard",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let manifest: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let device = &manifest["storage_devices"]["sdcard"];
+    assert_eq!(device["build_args"]["type"], "mbr");
+    assert_eq!(device["capacity"], 4);
+    assert_eq!(device["capacity_unit"], "mebibytes");
+
+    let boot = &device["partitions"][0];
+    assert_eq!(boot["name"], "part1");
+    assert_eq!(boot["partition_type"], "0x0c");
+    assert_eq!(boot["bootable"], true);
+    let data = &device["partitions"][1];
+    assert_eq!(data["name"], "part5");
+    assert_eq!(data["partition_type"], "0x83");
+    assert_eq!(data["logical"], true);
+    assert_eq!(
+        (&data["offset"], &data["offset_unit"]),
+        (&3.into(), &"mebibytes".into())
+    );
+}
+
+#[test]
+fn test_import_image_corrupt_gpt() {
+    let temp_dir = TempDir::new().unwrap();
+    let image_path = temp_dir.path().join("corrupt.img");
+    let mut image = vec![0u8; 2048 * 512];
+
+    // A GPT whose only entry ends before it starts, with valid checksums
+    let mut entries = vec![0u8; 128 * 128];
+    entries[0] = 0xaf;
+    entries[32..40].copy_from_slice(&100u64.to_le_bytes());
+    entries[40..48].copy_from_slice(&50u64.to_le_bytes());
+    let header = &mut image[512..1024];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[40..48].copy_from_slice(&34u64.to_le_bytes());
+    header[48..56].copy_from_slice(&2014u64.to_le_bytes());
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&crc32fast::hash(&entries).to_le_bytes());
+    let header_crc = crc32fast::hash(&header[..92]);
+    header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    image[1024..][..entries.len()].copy_from_slice(&entries);
+    fs::write(&image_path, image).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "import-image",
+            &image_path.to_string_lossy(),
+            "--extract",
+            &temp_dir.path().join("parts").to_string_lossy(),
+        ])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(
+            "(blocks 100..=50) is outside the usable blocks 34..=2014",
+        ));
+    assert!(!temp_dir.path().join("parts").exists());
+}
+
+#[test]
+fn test_import_image_colliding_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let vendor_path = temp_dir.path().join("vendor");
+    fs::create_dir(&vendor_path).unwrap();
+
+    // An unnamed partition whose generated name is taken, and two names
+    // that give the same image file
+    let manifest_content = r#"{
+        "runtime": { "platform": "generic-platform", "architecture": "test-arch" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "images": { "a": "a.img", "b": "b.img", "c": "c.img", "d": "d.img" },
+                "partitions": [
+                    { "name": "part2", "image": "a", "offset": 2048, "size": 1, "size_unit": "mebibytes" },
+                    { "image": "b", "size": 1, "size_unit": "mebibytes" },
+                    { "name": "boot a", "image": "c", "size": 1, "size_unit": "mebibytes" },
+                    { "name": "boot_a", "image": "d", "size": 1, "size_unit": "mebibytes" }
+                ]
+            }
+        }
+    }"#;
+    fs::write(vendor_path.join("manifest.json"), manifest_content).unwrap();
+    for name in ["a", "b", "c", "d"] {
+        fs::write(vendor_path.join(format!("{name}.img")), name.to_uppercase()).unwrap();
+    }
+    provision(&vendor_path);
+
+    let import_path = temp_dir.path().join("import");
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "import-image",
+            &vendor_path.join("_build/disk.img").to_string_lossy(),
+            "--extract",
+            &import_path.to_string_lossy(),
+            "--output",
+            &import_path.join("manifest.json").to_string_lossy(),
+        ])
+        .assert()
+        .success();
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(import_path.join("manifest.json")).unwrap())
+            .unwrap();
+    let device = &manifest["storage_devices"]["rootdisk"];
+    let names: Vec<&str> = device["partitions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|partition| partition["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["part2", "part2_1", "boot a", "part4"]);
+    for (name, content) in names.iter().zip(["A", "B", "C", "D"]) {
+        let image_file = device["images"][name].as_str().unwrap();
+        let image = fs::read(import_path.join(image_file)).unwrap();
+        assert_eq!(&image[..1], content.as_bytes(), "{name}");
+    }
+}
@@ -0,0 +1,212 @@
+use super::{build_bundle, write_bundle_inputs};
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Write a .aos archive by hand so tests can produce inconsistent bundles
+fn write_archive(path: &Path, entries: &[(&str, &[u8])]) {
+    let file = std::fs::File::create(path).unwrap();
+    let encoder = zstd::Encoder::new(file, 3).unwrap();
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn bundle_json_for(file: &str, data: &[u8]) -> Vec<u8> {
+    use sha2::{Digest, Sha256};
+
+    serde_json::to_vec(&serde_json::json!({
+        "format_version": 2,
+        "platform": "test-platform",
+        "architecture": "noarch",
+        "os_build_id": "build-0001",
+        "update": {
+            "strategy": "uboot-ab",
+            "slot_detection": { "type": "uboot-env", "var": "boot_slot" },
+            "activate": [],
+            "artifacts": [{
+                "name": "rootfs",
+                "file": file,
+                "sha256": format!("{:x}", Sha256::digest(data)),
+                "size": data.len(),
+            }],
+        },
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_inspect_bundle_success() {
+    let temp_dir = TempDir::new().unwrap();
+    write_bundle_inputs(temp_dir.path(), |_| {});
+    let bundle_path = build_bundle(temp_dir.path());
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("avocado-qemux86-64"))
+        .stdout(contains("build-0001"))
+        .stdout(contains("uboot-ab"))
+        .stdout(contains("images/rootfs.img"))
+        .stdout(contains("rootfs_b"))
+        .stdout(contains("Verified bundle"));
+}
+
+#[test]
+fn test_inspect_bundle_missing_file() {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", "does-not-exist.aos"])
+        .assert()
+        .failure()
+        .stdout(contains("Bundle file 'does-not-exist.aos' not found"));
+}
+
+#[test]
+fn test_inspect_bundle_sha256_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("bad.aos");
+    let bundle_json = bundle_json_for("images/rootfs.img", b"original contents");
+    write_archive(
+        &bundle_path,
+        &[
+            ("bundle.json", &bundle_json),
+            ("images/rootfs.img", b"tampered contents"),
+        ],
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains("sha256 mismatch: 'images/rootfs.img'"));
+}
+
+#[test]
+fn test_inspect_bundle_size_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("bad.aos");
+    let bundle_json = bundle_json_for("images/rootfs.img", b"original contents");
+    write_archive(
+        &bundle_path,
+        &[
+            ("bundle.json", &bundle_json),
+            ("images/rootfs.img", b"short"),
+        ],
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains("size mismatch: 'images/rootfs.img'"));
+}
+
+#[test]
+fn test_inspect_bundle_missing_and_unexpected_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("bad.aos");
+    let bundle_json = bundle_json_for("images/rootfs.img", b"original contents");
+    write_archive(
+        &bundle_path,
+        &[
+            ("bundle.json", &bundle_json),
+            ("images/extra.img", b"original contents"),
+        ],
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains("missing: 'images/rootfs.img'"))
+        .stdout(contains("unexpected entry: 'images/extra.img'"));
+}
+
+#[test]
+fn test_inspect_bundle_without_bundle_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("bad.aos");
+    write_archive(&bundle_path, &[("images/rootfs.img", b"contents")]);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains("does not contain a bundle.json entry"));
+}
+
+#[test]
+fn test_inspect_bundle_newer_format_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("future.aos");
+    let mut bundle_json: serde_json::Value =
+        serde_json::from_slice(&bundle_json_for("images/rootfs.img", b"contents")).unwrap();
+    bundle_json["format_version"] = serde_json::json!(99);
+    write_archive(
+        &bundle_path,
+        &[
+            ("bundle.json", &serde_json::to_vec(&bundle_json).unwrap()),
+            ("images/rootfs.img", b"contents"),
+        ],
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains(
+            "format version 99 is newer than this stone supports",
+        ));
+}
+
+#[test]
+fn test_inspect_bundle_format_version_1() {
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_path = temp_dir.path().join("v1.aos");
+    let mut bundle_json: serde_json::Value =
+        serde_json::from_slice(&bundle_json_for("images/rootfs.img", b"contents")).unwrap();
+    // As written by stone before bundle.json format 2
+    bundle_json["format_version"] = serde_json::json!(1);
+    bundle_json["update"]["artifacts"][0]["slot_targets"] =
+        serde_json::json!({ "a": { "partition": "rootfs_a" } });
+    bundle_json["layout"] = serde_json::json!({
+        "device": "/dev/mmcblk0",
+        "partitions": [{
+            "name": "rootfs_a",
+            "offset": 1048576,
+            "offset_unit": "bytes",
+            "size": 64,
+            "size_unit": "mebibytes"
+        }]
+    });
+    write_archive(
+        &bundle_path,
+        &[
+            ("bundle.json", &serde_json::to_vec(&bundle_json).unwrap()),
+            ("images/rootfs.img", b"contents"),
+        ],
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("a: mmcblk0 / rootfs_a"))
+        .stdout(contains("Layout: mmcblk0 (/dev/mmcblk0)"));
+}
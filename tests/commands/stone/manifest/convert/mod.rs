@@ -0,0 +1,212 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use tempfile::TempDir;
+
+fn convert(input: &str, output: &str) {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "convert", input, "--output", output])
+        .assert()
+        .success()
+        .stdout(contains("Converted"));
+}
+
+fn read_json(path: &str) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_convert_round_trip_through_every_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+    let fixture = "tests/fixtures/coverage/stone.json";
+    convert(fixture, &path("manifest.yaml"));
+    convert(&path("manifest.yaml"), &path("manifest.toml"));
+    convert(&path("manifest.toml"), &path("manifest.json5"));
+    convert(&path("manifest.json5"), &path("manifest.json"));
+
+    assert_eq!(read_json(&path("manifest.json")), read_json(fixture));
+
+    let yaml = fs::read_to_string(path("manifest.yaml")).unwrap();
+    assert!(yaml.contains("storage_devices:"), "{yaml}");
+    let toml = fs::read_to_string(path("manifest.toml")).unwrap();
+    assert!(toml.contains("[runtime]"), "{toml}");
+}
+
+#[test]
+fn test_convert_to_stdout() {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "manifest",
+            "convert",
+            "tests/fixtures/partition_without_image/stone.json",
+            "--to",
+            "yaml",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("platform: avocado-portable"))
+        .stdout(contains("size_unit: blocks"));
+}
+
+#[test]
+fn test_convert_requires_target_format() {
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "manifest",
+            "convert",
+            "tests/fixtures/partition_without_image/stone.json",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("Cannot tell which format to convert to"));
+}
+
+#[test]
+fn test_convert_rejects_invalid_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("manifest.yaml");
+    fs::write(&input, "runtime:\n  platform: rpi4\n").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "manifest",
+            "convert",
+            &input.to_string_lossy(),
+            "--to",
+            "json",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("Invalid manifest"))
+        .stdout(contains("architecture"));
+}
+
+#[test]
+fn test_commented_manifests_are_loaded_by_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    let yaml = r#"
+# Board manifest
+runtime:
+  platform: rpi4
+  architecture: aarch64
+storage_devices:
+  rootdisk:
+    out: rootdisk.img
+    devpath: /dev/mmcblk0
+    images: {}
+    partitions:
+      # Leave 4 MiB for the bootloader
+      - offset: 4
+        offset_unit: mebibytes
+        size: 64
+        size_unit: mebibytes
+"#;
+    fs::write(dir.join("manifest.yaml"), yaml).unwrap();
+
+    let jsonc = r#"{
+    // Board manifest
+    "runtime": { "platform": "rpi4", "architecture": "aarch64" },
+    "storage_devices": {},
+}"#;
+    fs::write(dir.join("board.jsonc"), jsonc).unwrap();
+    fs::write(dir.join("board.manifest"), yaml).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("manifest.yaml").to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Platform: rpi4 (aarch64)"))
+        .stdout(contains("4 MiB"));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("board.jsonc").to_string_lossy(),
+        ])
+        .assert()
+        .success();
+
+    // Unknown extensions are read as JSON unless --format says otherwise
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("board.manifest").to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("Failed to parse manifest JSON"));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "describe-manifest",
+            "--manifest-path",
+            &dir.join("board.manifest").to_string_lossy(),
+            "--format",
+            "yaml",
+        ])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_create_writes_yaml_manifest_as_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let output_dir = dir.join("out");
+
+    let yaml = r#"
+runtime:
+  platform: rpi4
+  architecture: aarch64
+storage_devices:
+  rootdisk:
+    out: rootdisk.img
+    devpath: /dev/mmcblk0
+    images: {}
+    partitions: []
+"#;
+    fs::write(dir.join("manifest.yaml"), yaml).unwrap();
+    fs::write(dir.join("os-release"), "ID=avocado\n").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "create",
+            "--manifest-path",
+            &dir.join("manifest.yaml").to_string_lossy(),
+            "--os-release",
+            &dir.join("os-release").to_string_lossy(),
+            "--input-dir",
+            &dir.to_string_lossy(),
+            "--output-dir",
+            &output_dir.to_string_lossy(),
+        ])
+        .assert()
+        .success();
+
+    let copied = read_json(&output_dir.join("manifest.json").to_string_lossy());
+    assert_eq!(copied["runtime"]["platform"], "rpi4");
+    assert_eq!(
+        copied["storage_devices"]["rootdisk"]["partitions"],
+        serde_json::json!([])
+    );
+}
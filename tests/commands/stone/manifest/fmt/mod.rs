@@ -0,0 +1,170 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use tempfile::TempDir;
+
+const UNFORMATTED_MANIFEST: &str = r#"{"storage_devices": {"rootdisk": {"partitions": [
+    {"size": 64, "size_unit": "MiB", "image": "boot"}],
+    "images": {"rootfs": "rootfs.img", "boot": "boot.img"},
+    "devpath": "/dev/sda", "out": "rootdisk.img"}},
+  "runtime": {"platform": "test-platform", "architecture": "noarch"}}"#;
+
+#[test]
+fn test_fmt_rewrites_and_check_passes() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    fs::write(&manifest_path, UNFORMATTED_MANIFEST).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--check"])
+        .arg(&manifest_path)
+        .assert()
+        .failure()
+        .stdout(contains("is not formatted"))
+        .stdout(contains("1 manifest(s) not formatted"));
+    assert_eq!(
+        fs::read_to_string(&manifest_path).unwrap(),
+        UNFORMATTED_MANIFEST
+    );
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("1 reformatted"));
+
+    let formatted = fs::read_to_string(&manifest_path).unwrap();
+    assert!(
+        formatted.contains("\"size_unit\": \"mebibytes\""),
+        "{formatted}"
+    );
+    assert!(
+        formatted.find("\"boot\"").unwrap() < formatted.find("\"rootfs\"").unwrap(),
+        "{formatted}"
+    );
+    let original: serde_json::Value = serde_json::from_str(UNFORMATTED_MANIFEST).unwrap();
+    let mut reformatted: serde_json::Value = serde_json::from_str(&formatted).unwrap();
+    reformatted["storage_devices"]["rootdisk"]["partitions"][0]["size_unit"] = "MiB".into();
+    assert_eq!(reformatted, original);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--check"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("0 unformatted"));
+}
+
+#[test]
+fn test_fmt_yaml_keeps_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.yaml");
+    let value: serde_json::Value = serde_json::from_str(UNFORMATTED_MANIFEST).unwrap();
+    let content = format!("# Root disk\n{}", serde_yaml::to_string(&value).unwrap());
+    fs::write(&manifest_path, &content).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt"])
+        .arg(&manifest_path)
+        .assert()
+        .failure()
+        .stdout(contains("comments would be lost"))
+        .stdout(contains("pass --force"));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), content);
+
+    // Only the values are compared, so key order and comments do not count
+    let commented = content.replace("MiB", "mebibytes");
+    fs::write(&manifest_path, &commented).unwrap();
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--check"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("0 unformatted"));
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("0 reformatted"));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), commented);
+
+    fs::write(&manifest_path, &content).unwrap();
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--force"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("1 reformatted"));
+
+    let formatted = fs::read_to_string(&manifest_path).unwrap();
+    assert!(formatted.contains("size_unit: mebibytes"), "{formatted}");
+    assert!(!formatted.contains("# Root disk"), "{formatted}");
+}
+
+#[test]
+fn test_fmt_json5_force_writes_json5() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json5");
+    let content = format!("// Root disk\n{UNFORMATTED_MANIFEST}");
+    fs::write(&manifest_path, &content).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--check"])
+        .arg(&manifest_path)
+        .assert()
+        .failure()
+        .stdout(contains("is not formatted"));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--force"])
+        .arg(&manifest_path)
+        .assert()
+        .success();
+
+    let formatted = fs::read_to_string(&manifest_path).unwrap();
+    assert!(
+        formatted.contains("size_unit: \"mebibytes\","),
+        "{formatted}"
+    );
+    assert!(serde_json::from_str::<serde_json::Value>(&formatted).is_err());
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt", "--check"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("0 unformatted"));
+}
+
+#[test]
+fn test_fmt_rejects_unknown_fields() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let mut value: serde_json::Value = serde_json::from_str(UNFORMATTED_MANIFEST).unwrap();
+    value["runtime"]["provison_default"] = "typo".into();
+    fs::write(&manifest_path, value.to_string()).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "fmt"])
+        .arg(&manifest_path)
+        .assert()
+        .failure()
+        .stdout(contains("unknown field(s): runtime.provison_default"));
+    assert_eq!(
+        fs::read_to_string(&manifest_path).unwrap(),
+        value.to_string()
+    );
+}
@@ -0,0 +1,182 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use predicates::str::contains;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+const LEGACY_MANIFEST: &str = r#"{
+    "runtime": {
+        "platform": "test-platform",
+        "architecture": "noarch",
+        "provision": "provision.sh"
+    },
+    "storage_devices": {
+        "test_device": {
+            "out": "test.img",
+            "devpath": "/dev/test",
+            "images": {
+                "simple_image": "simple.img"
+            },
+            "partitions": []
+        }
+    }
+}"#;
+
+fn write_legacy_inputs(dir: &Path) {
+    fs::write(dir.join("manifest.json"), LEGACY_MANIFEST).unwrap();
+    fs::write(dir.join("simple.img"), "test content").unwrap();
+    fs::write(dir.join("provision.sh"), "#!/bin/sh\n").unwrap();
+}
+
+#[test]
+fn test_upgrade_in_place() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    write_legacy_inputs(dir);
+    let manifest_path = dir.join("manifest.json");
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "validate",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ])
+        .current_dir(dir)
+        .assert()
+        .success()
+        .stdout(contains("runtime.provision is deprecated"));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "upgrade", &manifest_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Moved runtime.provision"))
+        .stdout(contains("Upgraded"));
+
+    let upgraded: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(upgraded["manifest_version"], 2);
+    assert_eq!(
+        upgraded["provision"]["profiles"]["default"]["script"],
+        "provision.sh"
+    );
+    assert_eq!(upgraded["runtime"]["provision_default"], "default");
+    assert!(upgraded["runtime"].get("provision").is_none());
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "validate",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ])
+        .current_dir(dir)
+        .assert()
+        .success()
+        .stdout(contains("deprecated").not());
+
+    // A second upgrade has nothing to do
+    let before = fs::read_to_string(&manifest_path).unwrap();
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "upgrade", &manifest_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("already at manifest version 2"));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), before);
+}
+
+#[test]
+fn test_upgrade_keeps_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let input = dir.join("manifest.yaml");
+    let value: serde_json::Value = serde_json::from_str(LEGACY_MANIFEST).unwrap();
+    fs::write(&input, serde_yaml::to_string(&value).unwrap()).unwrap();
+    let output = dir.join("upgraded.yaml");
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "manifest",
+            "upgrade",
+            &input.to_string_lossy(),
+            "--output",
+            &output.to_string_lossy(),
+        ])
+        .assert()
+        .success();
+
+    let upgraded: serde_json::Value =
+        serde_yaml::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+    assert_eq!(upgraded["manifest_version"], 2);
+    assert_eq!(
+        upgraded["provision"]["profiles"]["default"]["script"],
+        "provision.sh"
+    );
+    // The input is left alone
+    assert!(
+        fs::read_to_string(&input)
+            .unwrap()
+            .contains("provision: provision.sh")
+    );
+}
+
+#[test]
+fn test_upgrade_in_place_keeps_comments_without_force() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.yaml");
+    let value: serde_json::Value = serde_json::from_str(LEGACY_MANIFEST).unwrap();
+    let content = format!("# Test disk\n{}", serde_yaml::to_string(&value).unwrap());
+    fs::write(&manifest_path, &content).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "upgrade"])
+        .arg(&manifest_path)
+        .assert()
+        .failure()
+        .stdout(contains("comments would be lost"))
+        .stdout(contains("--force"));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), content);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["manifest", "upgrade", "--force"])
+        .arg(&manifest_path)
+        .assert()
+        .success()
+        .stdout(contains("Upgraded"));
+
+    let upgraded = fs::read_to_string(&manifest_path).unwrap();
+    assert!(upgraded.contains("manifest_version: 2"), "{upgraded}");
+    assert!(!upgraded.contains("# Test disk"), "{upgraded}");
+}
+
+#[test]
+fn test_newer_manifest_version_is_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    write_legacy_inputs(dir);
+    let mut value: serde_json::Value = serde_json::from_str(LEGACY_MANIFEST).unwrap();
+    value["manifest_version"] = 99.into();
+    let manifest_path = dir.join("manifest.json");
+    fs::write(&manifest_path, value.to_string()).unwrap();
+
+    for args in [
+        vec!["validate", "--manifest-path"],
+        vec!["manifest", "upgrade"],
+    ] {
+        Command::cargo_bin("stone")
+            .unwrap()
+            .args(args)
+            .arg(&manifest_path)
+            .current_dir(dir)
+            .assert()
+            .failure()
+            .stdout(contains("newer than this stone supports"));
+    }
+}
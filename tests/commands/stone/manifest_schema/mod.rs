@@ -0,0 +1,114 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use tempfile::TempDir;
+
+fn manifest_schema() -> serde_json::Value {
+    let output = Command::cargo_bin("stone")
+        .unwrap()
+        .arg("manifest-schema")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    serde_json::from_slice(&output).unwrap()
+}
+
+fn read_fixture(path: &str) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_manifest_schema_accepts_fixtures() {
+    let validator = jsonschema::validator_for(&manifest_schema()).unwrap();
+
+    for fixture in [
+        "tests/fixtures/bundle/stone.json",
+        "tests/fixtures/coverage/stone.json",
+        "tests/fixtures/missing_device_fwup_template/stone.json",
+        "tests/fixtures/partition_without_image/stone.json",
+    ] {
+        let manifest = read_fixture(fixture);
+        let errors: Vec<String> = validator
+            .iter_errors(&manifest)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{fixture}: {errors:?}");
+    }
+}
+
+#[test]
+fn test_manifest_schema_rejects_invalid_manifests() {
+    let validator = jsonschema::validator_for(&manifest_schema()).unwrap();
+    let manifest = read_fixture("tests/fixtures/bundle/stone.json");
+
+    let mut bad_action = manifest.clone();
+    bad_action["update"]["activate"] = serde_json::json!({ "type": "uboot_env", "set": {} });
+    assert!(!validator.is_valid(&bad_action));
+
+    let mut bad_size = manifest.clone();
+    bad_size["storage_devices"]["rootdisk"]["partitions"][0]["size"] = serde_json::json!("1");
+    assert!(!validator.is_valid(&bad_size));
+
+    let mut missing_devpath = manifest;
+    missing_devpath["storage_devices"]["rootdisk"]
+        .as_object_mut()
+        .unwrap()
+        .remove("devpath");
+    assert!(!validator.is_valid(&missing_devpath));
+}
+
+#[test]
+fn test_manifest_schema_rejects_misspelled_keys() {
+    let validator = jsonschema::validator_for(&manifest_schema()).unwrap();
+    let manifest = read_fixture("tests/fixtures/bundle/stone.json");
+
+    let mut misspelled_partition_key = manifest.clone();
+    misspelled_partition_key["storage_devices"]["rootdisk"]["partitions"][0]["offest"] =
+        serde_json::json!(5);
+    assert!(!validator.is_valid(&misspelled_partition_key));
+
+    let mut misspelled_runtime_key = manifest.clone();
+    misspelled_runtime_key["runtime"]["provison_default"] = serde_json::json!("img");
+    assert!(!validator.is_valid(&misspelled_runtime_key));
+
+    let mut misspelled_top_level_key = manifest;
+    misspelled_top_level_key["relase"] = serde_json::json!({});
+    assert!(!validator.is_valid(&misspelled_top_level_key));
+}
+
+#[test]
+fn test_manifest_schema_output_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let schema_path = temp_dir.path().join("stone.schema.json");
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "manifest-schema",
+            "--output",
+            &schema_path.to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Wrote manifest schema"));
+
+    let schema: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&schema_path).unwrap()).unwrap();
+    assert_eq!(schema["title"], "Stone manifest");
+    for definition in [
+        "StorageDevice",
+        "Image",
+        "Partition",
+        "BuildArgs",
+        "Update",
+        "SlotDetection",
+        "SlotAction",
+        "Provision",
+    ] {
+        assert!(
+            schema["$defs"][definition].is_object(),
+            "missing definition {definition}"
+        );
+    }
+}
@@ -1,4 +1,62 @@
+pub mod apply_bundle;
+pub mod bundle;
+pub mod bundle_delta;
+pub mod bundle_schema;
 pub mod create;
 pub mod describe_manifest;
+pub mod import_image;
+pub mod inspect_bundle;
+pub mod manifest;
+pub mod manifest_schema;
 pub mod provision;
 pub mod validate;
+pub mod verify_bundle;
+
+use assert_cmd::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Copy the bundle fixture into `dir`, letting `edit` adjust the manifest
+pub fn write_bundle_inputs(dir: &Path, edit: impl FnOnce(&mut serde_json::Value)) {
+    fs::create_dir_all(dir).unwrap();
+    for file in ["os-release", "boot.img", "rootfs.img"] {
+        fs::copy(
+            Path::new("tests/fixtures/bundle").join(file),
+            dir.join(file),
+        )
+        .unwrap();
+    }
+
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string("tests/fixtures/bundle/stone.json").unwrap())
+            .unwrap();
+    edit(&mut manifest);
+    fs::write(
+        dir.join("stone.json"),
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    )
+    .unwrap();
+}
+
+/// `stone bundle` of the inputs in `dir`, written to `dir/os-bundle.aos`
+pub fn bundle_command(dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("stone").unwrap();
+    cmd.args([
+        "bundle",
+        "--manifest-path",
+        &dir.join("stone.json").to_string_lossy(),
+        "--os-release",
+        &dir.join("os-release").to_string_lossy(),
+        "--input-dir",
+        &dir.to_string_lossy(),
+        "--output",
+        &dir.join("os-bundle.aos").to_string_lossy(),
+    ]);
+    cmd
+}
+
+/// Bundle the inputs in `dir`, returning the path of the bundle
+pub fn build_bundle(dir: &Path) -> PathBuf {
+    bundle_command(dir).assert().success();
+    dir.join("os-bundle.aos")
+}
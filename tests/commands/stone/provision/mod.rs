@@ -14,6 +14,7 @@ fn test_provision_missing_manifest() {
         .args(["provision", "--input-dir", &input_path.to_string_lossy()])
         .assert()
         .failure()
+        .code(5)
         .stdout(predicates::str::contains(
             "Manifest file 'manifest.json' not found",
         ));
@@ -522,6 +523,42 @@ VENDOR_NAME="Avocado Linux""#;
         ));
 }
 
+#[test]
+fn test_provision_exits_with_provision_script_exit_code() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "test-platform",
+            "architecture": "noarch",
+            "provision_default": "default"
+        },
+        "provision": {
+            "profiles": {
+                "default": { "script": "provision.sh" }
+            }
+        },
+        "storage_devices": {}
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(input_path.join("provision.sh"), "#!/bin/bash\nexit 42\n").unwrap();
+    let mut perms = fs::metadata(input_path.join("provision.sh"))
+        .unwrap()
+        .permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(input_path.join("provision.sh"), perms).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .code(42)
+        .stdout(predicates::str::contains(
+            "Provision script 'provision.sh' failed with exit code 42",
+        ));
+}
+
 #[test]
 fn test_provision_builds_images_before_storage_device() {
     let temp_dir = TempDir::new().unwrap();
@@ -996,6 +1033,8 @@ VENDOR_NAME="Avocado Linux""#;
                 },
                 "devpath": "/dev/generic",
                 "block_size": 512,
+                "capacity": 1,
+                "capacity_unit": "gibibytes",
                 "uuid": "4bc367b3-5d70-4289-b24d-9b09cb79685c",
                 "images": {
                     "boot": {
@@ -1064,6 +1103,12 @@ task complete {
         "Should log AVOCADO_DISK_UUID from storage device. Stdout: {stdout}, Stderr: {stderr}"
     );
 
+    assert!(
+        stdout.contains("AVOCADO_DISK_BLOCKS=2097152")
+            || stderr.contains("AVOCADO_DISK_BLOCKS=2097152"),
+        "Should log AVOCADO_DISK_BLOCKS from the storage device capacity. Stdout: {stdout}, Stderr: {stderr}"
+    );
+
     // Check that we're building the storage device
     assert!(
         stdout.contains("Building storage device 'rootdisk'")
@@ -1481,3 +1526,279 @@ VENDOR_NAME="Avocado Linux""#;
         "Stale artifacts from previous provision runs should be cleaned"
     );
 }
+
+#[test]
+fn test_provision_storage_device_with_gpt() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "generic-platform",
+            "architecture": "test-arch"
+        },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "uuid": "4bc367b3-5d70-4289-b24d-9b09cb79685c",
+                "images": {
+                    "boot": "boot.img",
+                    "rootfs": "rootfs.img"
+                },
+                "partitions": [
+                    {
+                        "name": "boot",
+                        "image": "boot",
+                        "partition_type": "c12a7328-f81f-11d2-ba4b-00a0c93ec93b",
+                        "offset": 2048,
+                        "size": 1,
+                        "size_unit": "mebibytes"
+                    },
+                    {
+                        "name": "rootfs",
+                        "image": "rootfs",
+                        "size": 2,
+                        "size_unit": "mebibytes"
+                    }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(input_path.join("boot.img"), "BOOT").unwrap();
+    fs::write(input_path.join("rootfs.img"), "ROOTFS").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("with a GPT partition table"));
+
+    let disk = fs::read(input_path.join("_build/disk.img")).unwrap();
+    // 3 MiB of partitions after 1 MiB, then the backup table and header
+    assert_eq!(disk.len(), 4 * 1024 * 1024 + 33 * 512);
+    assert_eq!(&disk[510..512], &[0x55, 0xaa]);
+    assert_eq!(&disk[512..520], b"EFI PART");
+    assert_eq!(&disk[disk.len() - 512..][..8], b"EFI PART");
+    assert_eq!(&disk[1024 * 1024..][..4], b"BOOT");
+    assert_eq!(&disk[2 * 1024 * 1024..][..6], b"ROOTFS");
+
+    // Disk GUID, stored with its first three fields little-endian
+    assert_eq!(
+        &disk[512 + 56..][..8],
+        &[0xb3, 0x67, 0xc3, 0x4b, 0x70, 0x5d, 0x89, 0x42]
+    );
+    // Second entry: rootfs from block 4096 to 8191
+    let entry = &disk[1024 + 128..][..128];
+    assert_eq!(u64::from_le_bytes(entry[32..40].try_into().unwrap()), 4096);
+    assert_eq!(u64::from_le_bytes(entry[40..48].try_into().unwrap()), 8191);
+}
+
+#[test]
+fn test_provision_si_sized_fat_image_fills_partition() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "generic-platform",
+            "architecture": "test-arch"
+        },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "images": {
+                    "boot": {
+                        "out": "boot.img",
+                        "size": 8,
+                        "size_unit": "megabytes",
+                        "build_args": {
+                            "type": "fat",
+                            "variant": "FAT16",
+                            "files": ["config.txt"]
+                        }
+                    }
+                },
+                "partitions": [
+                    {
+                        "name": "boot",
+                        "image": "boot",
+                        "offset": 2048,
+                        "size": 8,
+                        "size_unit": "megabytes"
+                    }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(input_path.join("config.txt"), "dtparam=audio=on").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .success();
+
+    let boot = fs::read(input_path.join("_build/boot.img")).unwrap();
+    assert_eq!(boot.len(), 8_000_000);
+    let disk = fs::read(input_path.join("_build/disk.img")).unwrap();
+    assert_eq!(&disk[1024 * 1024..][..512], &boot[..512]);
+}
+
+#[test]
+fn test_provision_gpt_expand_to_capacity() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "generic-platform",
+            "architecture": "test-arch"
+        },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "capacity": 8,
+                "capacity_unit": "mebibytes",
+                "images": {},
+                "partitions": [
+                    { "name": "boot", "offset": 2048, "size": 1, "size_unit": "mebibytes" },
+                    { "name": "rootfs", "size": 1, "size_unit": "mebibytes", "expand": "true" }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .success();
+
+    let disk = fs::read(input_path.join("_build/disk.img")).unwrap();
+    assert_eq!(disk.len(), 8 * 1024 * 1024);
+    // rootfs fills the disk up to the backup entries
+    let entry = &disk[1024 + 128..][..128];
+    assert_eq!(u64::from_le_bytes(entry[32..40].try_into().unwrap()), 4096);
+    assert_eq!(u64::from_le_bytes(entry[40..48].try_into().unwrap()), 16350);
+}
+
+#[test]
+fn test_provision_gpt_partition_over_table() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "generic-platform",
+            "architecture": "test-arch"
+        },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "gpt" },
+                "devpath": "/dev/generic",
+                "images": {},
+                "partitions": [
+                    { "name": "rootfs", "offset": 0, "size": 1, "size_unit": "mebibytes" }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .code(4)
+        .stdout(predicates::str::contains(
+            "the GPT (bytes 0..17408) overlaps partition 'rootfs' (bytes 0..1048576)",
+        ));
+}
+
+#[test]
+fn test_provision_storage_device_with_mbr() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": {
+            "platform": "generic-platform",
+            "architecture": "test-arch"
+        },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "disk.img",
+                "build_args": { "type": "mbr" },
+                "devpath": "/dev/generic",
+                "images": {
+                    "boot": "boot.img",
+                    "data": "data.img"
+                },
+                "partitions": [
+                    {
+                        "name": "boot",
+                        "image": "boot",
+                        "partition_type": "0x0c",
+                        "bootable": true,
+                        "offset": 2048,
+                        "size": 1,
+                        "size_unit": "mebibytes"
+                    },
+                    {
+                        "name": "data",
+                        "image": "data",
+                        "logical": true,
+                        "offset": 6144,
+                        "size": 1,
+                        "size_unit": "mebibytes"
+                    }
+                ]
+            }
+        }
+    }"#;
+    fs::write(input_path.join("manifest.json"), manifest_content).unwrap();
+    fs::write(input_path.join("boot.img"), "BOOT").unwrap();
+    fs::write(input_path.join("data.img"), "DATA").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["provision", "--input-dir", &input_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("with a MBR partition table"));
+
+    let disk = fs::read(input_path.join("_build/disk.img")).unwrap();
+    let le_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+    assert_eq!(disk.len(), 8192 * 512);
+    assert_eq!(&disk[510..512], &[0x55, 0xaa]);
+    assert_eq!(&disk[1024 * 1024..][..4], b"BOOT");
+    assert_eq!(&disk[3 * 1024 * 1024..][..4], b"DATA");
+
+    // Bootable FAT32 (LBA) primary partition, then the extended partition
+    let boot = &disk[446..462];
+    assert_eq!((boot[0], boot[4]), (0x80, 0x0c));
+    assert_eq!((le_u32(&boot[8..12]), le_u32(&boot[12..16])), (2048, 2048));
+    let extended = &disk[462..478];
+    assert_eq!(extended[4], 0x0f);
+    assert_eq!(
+        (le_u32(&extended[8..12]), le_u32(&extended[12..16])),
+        (6143, 2049)
+    );
+
+    // The logical partition's extended boot record is in the block before it
+    let ebr = &disk[6143 * 512..6144 * 512];
+    assert_eq!(&ebr[510..512], &[0x55, 0xaa]);
+    assert_eq!(ebr[446 + 4], 0x83);
+    assert_eq!((le_u32(&ebr[454..458]), le_u32(&ebr[458..462])), (1, 2048));
+}
@@ -44,6 +44,7 @@ fn test_validate_missing_device_fwup_template() {
         ])
         .assert()
         .failure()
+        .code(5)
         .stdout(contains("missing_template.conf"));
 }
 
@@ -635,3 +636,58 @@ fn test_validate_with_multiple_input_dirs_not_found() {
         .failure()
         .stdout(contains("test.img"));
 }
+
+#[test]
+fn test_validate_manifest_problems() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let input_path = temp_dir.path();
+
+    let manifest_content = r#"{
+        "runtime": { "platform": "test-platform", "architecture": "noarch" },
+        "storage_devices": {
+            "rootdisk": {
+                "out": "rootdisk.img",
+                "devpath": "/dev/mmcblk0",
+                "images": { "boot": "boot.img" },
+                "partitions": [
+                    { "name": "boot", "image": "boot", "offset": 2048, "size": 64, "size_unit": "mebibytes" },
+                    { "name": "rootfs", "image": "rootfs", "offset": 32, "offset_unit": "mebibytes", "size": 1, "size_unit": "gibibytes",
+                      "partition_type": "linux" }
+                ]
+            }
+        },
+        "update": {
+            "slot_detection": { "type": "uboot-env", "var": "boot_slot" },
+            "os_artifacts": { "rootfs": { "image_key": "rootfs", "slot_partitions": ["rootfs_a", "rootfs_b"] } },
+            "activate": { "type": "uboot-env", "set": { "boot_slot": "{slot}" } }
+        }
+    }"#;
+    let manifest_path = input_path.join("manifest.json");
+    std::fs::write(&manifest_path, manifest_content).unwrap();
+    std::fs::write(input_path.join("boot.img"), "boot").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "validate",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+            "--input-dir",
+            &input_path.to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("6 problem(s) in the manifest"))
+        .stdout(contains(
+            "partition 'rootfs' uses image 'rootfs', which is not defined in images",
+        ))
+        .stdout(contains("partition_type 'linux' is neither a GUID nor an MBR partition type byte"))
+        .stdout(contains(
+            "partition 'boot' (bytes 1048576..68157440) overlaps partition 'rootfs' (bytes 33554432..1107296256)",
+        ))
+        .stdout(contains(
+            "image_key 'rootfs' is not an image of any storage device",
+        ))
+        .stdout(contains("slot partition 'rootfs_a' is not a partition"))
+        .stdout(contains("slot partition 'rootfs_b' is not a partition"));
+}
@@ -0,0 +1,124 @@
+use super::{bundle_command, write_bundle_inputs};
+use assert_cmd::Command;
+use ed25519_dalek::SigningKey;
+use predicates::str::contains;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Write a hex private key and its hex public key, returning (private, public) paths
+fn write_key_pair(dir: &Path, name: &str, seed: u8) -> (PathBuf, PathBuf) {
+    let key = SigningKey::from_bytes(&[seed; 32]);
+    let private_path = dir.join(format!("{name}.key"));
+    let public_path = dir.join(format!("{name}.pub"));
+    std::fs::write(&private_path, hex(&key.to_bytes())).unwrap();
+    std::fs::write(&public_path, hex(key.verifying_key().as_bytes())).unwrap();
+    (private_path, public_path)
+}
+
+/// Bundle the fixture in `dir`, signed with `signing_key` when given
+fn build_bundle(dir: &Path, signing_key: Option<&Path>) -> PathBuf {
+    write_bundle_inputs(dir, |_| {});
+    let mut cmd = bundle_command(dir);
+    if let Some(key) = signing_key {
+        cmd.arg("--signing-key").arg(key);
+    }
+    cmd.assert().success();
+    dir.join("os-bundle.aos")
+}
+
+#[test]
+fn test_verify_signed_bundle() {
+    let temp_dir = TempDir::new().unwrap();
+    let (private_key, public_key) = write_key_pair(temp_dir.path(), "release", 7);
+    let bundle_path = build_bundle(temp_dir.path(), Some(&private_key));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "verify-bundle",
+            &bundle_path.to_string_lossy(),
+            "--pubkey",
+            &public_key.to_string_lossy(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Bundle signature verified"))
+        .stdout(contains("Verified bundle"));
+
+    // inspect-bundle reports the signature without requiring a key
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args(["inspect-bundle", &bundle_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout(contains("Signature      : present"));
+}
+
+#[test]
+fn test_verify_bundle_wrong_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let (private_key, _) = write_key_pair(temp_dir.path(), "release", 7);
+    let (_, other_public_key) = write_key_pair(temp_dir.path(), "other", 8);
+    let bundle_path = build_bundle(temp_dir.path(), Some(&private_key));
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "verify-bundle",
+            &bundle_path.to_string_lossy(),
+            "--pubkey",
+            &other_public_key.to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("Bundle signature verification failed"));
+}
+
+#[test]
+fn test_verify_unsigned_bundle() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, public_key) = write_key_pair(temp_dir.path(), "release", 7);
+    let bundle_path = build_bundle(temp_dir.path(), None);
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "verify-bundle",
+            &bundle_path.to_string_lossy(),
+            "--pubkey",
+            &public_key.to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("is not signed"));
+}
+
+#[test]
+fn test_bundle_with_invalid_signing_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let bad_key = temp_dir.path().join("bad.key");
+    std::fs::write(&bad_key, "not a key").unwrap();
+
+    Command::cargo_bin("stone")
+        .unwrap()
+        .args([
+            "bundle",
+            "--manifest-path",
+            "tests/fixtures/bundle/stone.json",
+            "--os-release",
+            "tests/fixtures/bundle/os-release",
+            "--input-dir",
+            "tests/fixtures/bundle",
+            "--output",
+            &temp_dir.path().join("os-bundle.aos").to_string_lossy(),
+            "--signing-key",
+            &bad_key.to_string_lossy(),
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("must contain a PEM key"));
+}